@@ -0,0 +1,94 @@
+//! Compare the codecs of the bridge frames, run with `cargo bench -p automate`
+
+use automate::bridge::{
+    msg::{Msg, MsgKind, MsgReqKind, SftpUploadParams},
+    protocol::{Codec, FrameOption, Protocol, DEFAULT_COMPRESS_THRESHOLD},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// an artifact like payload, half text and half noise
+fn artifact(size: usize) -> Vec<u8> {
+    let mut seed: u32 = 42;
+    (0..size)
+        .map(|i| {
+            if i % 2 == 0 {
+                b"jiascheduler"[i / 2 % 12]
+            } else {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            }
+        })
+        .collect()
+}
+
+fn upload_msg(size: usize) -> Msg {
+    Msg {
+        id: 1,
+        data: MsgKind::Request(MsgReqKind::SftpUploadRequest(SftpUploadParams {
+            ip: "192.168.1.10".to_string(),
+            port: 22,
+            user: "root".to_string(),
+            password: "".to_string(),
+            filepath: "/opt/app/artifact.tar.gz".to_string(),
+            data: artifact(size),
+            transfer_id: None,
+        })),
+    }
+}
+
+fn options() -> Vec<(&'static str, FrameOption)> {
+    vec![
+        ("json", FrameOption::default()),
+        (
+            "msgpack",
+            FrameOption {
+                codec: Codec::Msgpack,
+                compress_threshold: None,
+            },
+        ),
+        (
+            "msgpack+zstd",
+            FrameOption {
+                codec: Codec::Msgpack,
+                compress_threshold: Some(DEFAULT_COMPRESS_THRESHOLD),
+            },
+        ),
+    ]
+}
+
+fn bench_codec(c: &mut Criterion) {
+    for size in [64 * 1024, 4 * 1024 * 1024] {
+        let msg = upload_msg(size);
+
+        let mut group = c.benchmark_group(format!("pack_{size}"));
+        group.throughput(Throughput::Bytes(size as u64));
+        for (name, option) in options() {
+            let frame = Protocol::pack(msg.clone(), option).unwrap();
+            println!(
+                "{name} frame of {size} bytes payload is {} bytes",
+                frame.len()
+            );
+            group.bench_with_input(BenchmarkId::from_parameter(name), &option, |b, option| {
+                b.iter(|| Protocol::pack(msg.clone(), *option).unwrap())
+            });
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group(format!("unpack_{size}"));
+        group.throughput(Throughput::Bytes(size as u64));
+        for (name, option) in options() {
+            let frame = Protocol::pack(msg.clone(), option).unwrap();
+            group.bench_with_input(BenchmarkId::from_parameter(name), &frame, |b, frame| {
+                b.iter(|| Protocol::unpack_request(frame.clone()).unwrap())
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = bench_codec
+}
+criterion_main!(benches);
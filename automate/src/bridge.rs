@@ -2,29 +2,78 @@
 pub mod client;
 pub mod msg;
 pub mod protocol;
+pub mod transfer;
 // pub mod server;
 
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{Ok, Result};
 use serde_json::Value;
 use tokio::{
     sync::{
-        mpsc::{self, Sender},
-        Mutex,
+        mpsc::{self, Receiver, Sender},
+        Mutex, Notify,
     },
     time::timeout,
 };
 use tracing::info;
 
-use crate::bridge::msg::Msg;
+use crate::{bridge::msg::Msg, code};
 
-use self::msg::{MsgKind, MsgReqKind, MsgState};
+use self::msg::{MsgError, MsgKind, MsgReqKind, MsgState, PeerInfo, TransactionMsg};
+
+/// How a caller waits for the reply of a request
+#[derive(Clone, Default)]
+pub struct RequestOption {
+    /// the timeout of the request kind if not set
+    pub timeout: Option<Duration>,
+    /// the request is given up once notified, and aborted on the peer if it supports
+    pub cancel: Option<Arc<Notify>>,
+}
+
+impl RequestOption {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: Arc<Notify>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+}
+
+/// wait for the reply of the request, dropping the receiver on return is what tells
+/// the writer of the connection to abort the request on the peer
+pub(crate) async fn wait_reply(
+    mut rx: Receiver<MsgState>,
+    wait: Duration,
+    cancel: Option<&Notify>,
+) -> Result<Value> {
+    let reply = timeout(wait, rx.recv());
+    let reply = match cancel {
+        Some(cancel) => tokio::select! {
+            v = reply => v,
+            _ = cancel.notified() => return Err(MsgError::cancelled().into()),
+        },
+        None => reply.await,
+    };
+
+    let Some(state) = reply.map_err(|_| MsgError::timeout(wait))? else {
+        anyhow::bail!("connection closed before the reply");
+    };
+    match state {
+        MsgState::Completed(v) => Ok(v),
+        MsgState::Err(e) => Err(e),
+    }
+}
 
 #[derive(Clone)]
 pub struct Bridge {
     // server: WsServer,
-    server_clients: Arc<Mutex<HashMap<String, Sender<(Msg, Option<Sender<MsgState>>)>>>>,
+    server_clients: Arc<Mutex<HashMap<String, Sender<(Msg, Option<TransactionMsg>)>>>>,
+    /// what the peers announced during Auth
+    peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
 }
 
 impl Default for Bridge {
@@ -38,39 +87,73 @@ impl Bridge {
         Self {
             // server: WsServer::new(),
             server_clients: Arc::new(Mutex::new(HashMap::new())),
+            peers: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
     pub async fn append_client(
         &mut self,
         key: impl Into<String>,
-        client: Sender<(Msg, Option<Sender<MsgState>>)>,
+        client: Sender<(Msg, Option<TransactionMsg>)>,
+        peer: PeerInfo,
     ) {
-        self.server_clients.lock().await.insert(key.into(), client);
+        let key = key.into();
+        self.peers.lock().await.insert(key.clone(), peer);
+        self.server_clients.lock().await.insert(key, client);
     }
 
     pub async fn remove_client(&mut self, key: String) {
         self.server_clients.lock().await.remove(&key);
+        self.peers.lock().await.remove(&key);
+    }
+
+    pub async fn get_peer(&self, key: &str) -> Option<PeerInfo> {
+        self.peers.lock().await.get(key).cloned()
     }
 
+    /// send the request and wait for the reply in the timeout of its kind
     pub async fn send_msg(&self, key: &str, data: MsgReqKind) -> Result<Value> {
+        self.send_msg_with(key, data, RequestOption::default())
+            .await
+    }
+
+    /// the request is refused without sending if the peer does not support it
+    pub async fn send_msg_with(
+        &self,
+        key: &str,
+        data: MsgReqKind,
+        option: RequestOption,
+    ) -> Result<Value> {
+        if let (Some(capability), Some(peer)) =
+            (data.required_capability(), self.get_peer(key).await)
+        {
+            if !peer.supports(capability) {
+                return Err(MsgError::new(
+                    code::UNSUPPORTED_REQUEST,
+                    format!(
+                        "{key} does not support {capability}, protocol version {}",
+                        peer.protocol_version
+                    ),
+                )
+                .into());
+            }
+        }
+
+        let wait = option.timeout.unwrap_or_else(|| data.timeout());
         let msg = Msg {
             id: 0,
             data: MsgKind::Request(data),
         };
-        let (tx, mut rx) = mpsc::channel::<MsgState>(1);
+        let (tx, rx) = mpsc::channel::<MsgState>(1);
+        let tran = TransactionMsg::new(tx, wait);
 
-        match self.server_clients.lock().await.get(key) {
-            Some(sender) => sender.send((msg, Some(tx.clone()))).await?,
+        let sender = match self.server_clients.lock().await.get(key) {
+            Some(sender) => sender.clone(),
             None => return Err(anyhow::anyhow!("not found client {}", key)),
-        }
-
-        let resp = timeout(Duration::from_secs(10), rx.recv()).await?.unwrap();
-
-        return match resp {
-            MsgState::Completed(v) => Ok(v),
-            MsgState::Err(e) => Err(anyhow!(e)),
         };
+        sender.send((msg, Some(tran))).await?;
+
+        wait_reply(rx, wait, option.cancel.as_deref()).await
     }
 
     pub fn handle_msg(&mut self, msg: String) -> String {
@@ -107,3 +190,33 @@ impl Bridge {
     //     }
     // }
 }
+
+#[tokio::test]
+async fn test_wait_reply() {
+    let (_tx, rx) = mpsc::channel::<MsgState>(1);
+    let e = wait_reply(rx, Duration::from_millis(10), None)
+        .await
+        .unwrap_err();
+    assert_eq!(MsgError::code_of(&e), code::REQUEST_TIMEOUT);
+
+    let (_tx, rx) = mpsc::channel::<MsgState>(1);
+    let cancel = Notify::new();
+    cancel.notify_one();
+    let e = wait_reply(rx, Duration::from_secs(1), Some(&cancel))
+        .await
+        .unwrap_err();
+    assert_eq!(MsgError::code_of(&e), code::REQUEST_CANCELLED);
+
+    let (tx, rx) = mpsc::channel::<MsgState>(1);
+    drop(tx);
+    assert!(wait_reply(rx, Duration::from_millis(10), None)
+        .await
+        .is_err());
+
+    let (tx, rx) = mpsc::channel::<MsgState>(1);
+    tx.send(MsgState::Completed(Value::Bool(true)))
+        .await
+        .unwrap();
+    let v = wait_reply(rx, Duration::from_secs(1), None).await.unwrap();
+    assert_eq!(v, Value::Bool(true));
+}
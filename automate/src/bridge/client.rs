@@ -1,22 +1,29 @@
 use std::{
+    collections::HashMap,
     net::IpAddr,
-    sync::atomic::{AtomicU64, Ordering},
-    time::Duration,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use futures_util::{
     stream::{SplitSink, SplitStream},
     Future, SinkExt, StreamExt,
 };
 
-use moka::future::Cache;
+use moka::{future::Cache, Expiry};
 use poem::web::websocket::{Message as PMessage, WebSocketStream as PWebSocketStream};
 
 use serde_json::{json, Value};
 use tokio::{
     net::TcpStream,
-    sync::mpsc::{self, Receiver, Sender},
+    sync::{
+        mpsc::{self, Receiver, Sender, WeakSender},
+        Mutex, Notify,
+    },
     time::timeout,
 };
 use tokio_tungstenite::{
@@ -24,21 +31,173 @@ use tokio_tungstenite::{
     tungstenite::{ClientRequestBuilder, Message},
     MaybeTlsStream, WebSocketStream,
 };
-use tracing::{error, info};
+use tracing::{debug, error, info};
 
 use crate::{
-    get_endpoint,
+    code, get_endpoint,
     scheduler::types::{AssignUserOption, SshConnectionOption},
 };
 
 use super::{
-    msg::{AuthParams, Msg, MsgKind, MsgReqKind, MsgState, TransactionMsg},
-    protocol::Protocol,
-    Bridge,
+    msg::{
+        AbortParams, AuthParams, Capability, Msg, MsgError, MsgKind, MsgReqKind, MsgState,
+        PeerInfo, TransactionMsg, DEFAULT_REQUEST_TIMEOUT,
+    },
+    protocol::{FrameOption, Protocol, DEFAULT_COMPRESS_THRESHOLD},
+    wait_reply, Bridge, RequestOption,
 };
 
+/// a late reply is still delivered in the period after the timeout of its request
+const TRANSACTION_GRACE: Duration = Duration::from_secs(1);
+
+/// the pending requests expire with their own timeout
+struct TransactionExpiry;
+
+impl Expiry<u64, TransactionMsg> for TransactionExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &u64,
+        value: &TransactionMsg,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(value.timeout + TRANSACTION_GRACE)
+    }
+}
+
+type Outgoing = (Msg, Option<TransactionMsg>);
+
+/// remember the request until its reply, a request its caller gave up waiting for,
+/// such as timed out, cancelled or dropped, is aborted on the peer
+async fn track_request(
+    msg_box: &Cache<u64, TransactionMsg>,
+    sender: &WeakSender<Outgoing>,
+    mut tran: TransactionMsg,
+    id: u64,
+    abort: bool,
+) {
+    tran.id = id;
+    msg_box.insert(id, tran.clone()).await;
+    if !abort {
+        return;
+    }
+
+    let msg_box = msg_box.clone();
+    let sender = sender.clone();
+    tokio::spawn(async move {
+        tran.tx.closed().await;
+        // a reply is taken out of the box before it is delivered
+        if msg_box.remove(&tran.id).await.is_none() {
+            return;
+        }
+        let Some(sender) = sender.upgrade() else {
+            return;
+        };
+        debug!("abort request {} on the peer", tran.id);
+        let msg = Msg {
+            id: 0,
+            data: MsgKind::Request(MsgReqKind::AbortRequest(AbortParams { id: tran.id })),
+        };
+        let _ = sender
+            .send((msg, None))
+            .await
+            .map_err(|e| error!("failed send abort - {e}"));
+    });
+}
+
+/// deliver a reply to its request, or handle a request of the peer and reply it.
+/// a request is handled until it is replied or aborted by the peer
+async fn handle_frame<T, F>(
+    buf: Vec<u8>,
+    handler: T,
+    msg_box: Cache<u64, TransactionMsg>,
+    sender: Sender<Outgoing>,
+    running: Arc<Mutex<HashMap<u64, Arc<Notify>>>>,
+) where
+    T: FnOnce(MsgReqKind) -> F + Send + 'static,
+    F: Future<Output = Result<Value, MsgError>> + Send + 'static,
+{
+    if Protocol::is_response(&buf) {
+        let resp = match Protocol::unpack_response(buf) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("failed unpack_response - {e}");
+                return;
+            }
+        };
+
+        if let Some(tx) = msg_box.remove(&resp.id).await.map(|x| x.tx) {
+            let state = match resp.data {
+                MsgKind::Response(v) => MsgState::Completed(v),
+                MsgKind::Error(e) => MsgState::Err(e.into()),
+                v => {
+                    error!("invalid response format {:?}", v);
+                    return;
+                }
+            };
+            let _ = tx
+                .send(state)
+                .await
+                .map_err(|e| error!("failed send response - {e}"));
+        }
+
+        return;
+    }
+
+    // the id is read alone, so that a request unknown to this version is
+    // still replied with an error instead of left to time out
+    let Some(id) = Protocol::unpack_msg_id(&buf) else {
+        error!("failed unpack_request - invalid msg id");
+        return;
+    };
+    let data = match Protocol::unpack_request(buf) {
+        Ok(Msg {
+            data: MsgKind::Request(MsgReqKind::AbortRequest(v)),
+            ..
+        }) => {
+            let aborted = running.lock().await.remove(&v.id);
+            if let Some(abort) = &aborted {
+                abort.notify_one();
+            }
+            MsgKind::Response(json!(aborted.is_some()))
+        }
+        Ok(Msg {
+            data: MsgKind::Request(req),
+            ..
+        }) => {
+            let abort = Arc::new(Notify::new());
+            running.lock().await.insert(id, abort.clone());
+            let ret = tokio::select! {
+                v = handler(req) => Some(v),
+                _ = abort.notified() => None,
+            };
+            running.lock().await.remove(&id);
+            match ret {
+                Some(Ok(v)) => MsgKind::Response(v),
+                Some(Err(e)) => MsgKind::Error(e),
+                None => {
+                    info!("request {id} is aborted by the peer");
+                    return;
+                }
+            }
+        }
+        Ok(_) => MsgKind::Error(MsgError::new(code::INVALID_MSG, "invalid data type")),
+        Err(e) => {
+            error!("failed unpack_request - {e}");
+            MsgKind::Error(MsgError::new(
+                code::UNSUPPORTED_REQUEST,
+                format!("unsupported request - {e}"),
+            ))
+        }
+    };
+    let resp = Msg { id, data };
+    let _ = sender
+        .send_timeout((resp, None), Duration::from_secs(1))
+        .await
+        .map_err(|e| error!("failed send message - {e}"));
+}
+
 pub struct WsClient<W, R> {
-    sender: Sender<(Msg, Option<Sender<MsgState>>)>,
+    sender: Sender<Outgoing>,
     ws_writer: Option<W>,
     ws_reader: Option<R>,
     comet_secret: Option<String>,
@@ -49,15 +208,19 @@ pub struct WsClient<W, R> {
     assign_user_option: Option<AssignUserOption>,
     msg_box: Cache<u64, TransactionMsg>,
     bridge: Option<Bridge>,
-    receiver: Option<Receiver<(Msg, Option<Sender<MsgState>>)>>,
+    receiver: Option<Receiver<Outgoing>>,
+    /// the requests of the peer being handled, by their id
+    running: Arc<Mutex<HashMap<u64, Arc<Notify>>>>,
+    /// protocol version and capabilities of the other side
+    peer: Option<PeerInfo>,
+    compress_threshold: usize,
 }
 
 impl<W, R> WsClient<W, R> {
     pub fn new(bridge: Option<Bridge>) -> Self {
-        let (sender, receiver) = mpsc::channel::<(Msg, Option<Sender<MsgState>>)>(100);
-        let cache: Cache<u64, TransactionMsg> = Cache::builder()
-            .time_to_live(Duration::from_secs(5))
-            .build();
+        let (sender, receiver) = mpsc::channel::<Outgoing>(100);
+        let cache: Cache<u64, TransactionMsg> =
+            Cache::builder().expire_after(TransactionExpiry).build();
 
         Self {
             sender,
@@ -72,6 +235,9 @@ impl<W, R> WsClient<W, R> {
             ws_writer: None,
             ws_reader: None,
             receiver: Some(receiver),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            peer: None,
+            compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
         }
     }
 
@@ -95,29 +261,38 @@ impl<W, R> WsClient<W, R> {
         self
     }
 
+    /// frames above the size are compressed if the peer supports it, 0 disables compression
+    pub fn set_compress_threshold(mut self, compress_threshold: usize) -> Self {
+        self.compress_threshold = compress_threshold;
+        self
+    }
+
     pub fn set_ssh_connection(mut self, ssh_option: SshConnectionOption) -> Self {
         self.ssh_connection_option = Some(ssh_option);
         self
     }
 
-    pub fn sender(&self) -> Sender<(Msg, Option<Sender<MsgState>>)> {
+    pub fn sender(&self) -> Sender<Outgoing> {
         self.sender.clone()
     }
 
+    /// send the request and wait for the reply in the timeout of its kind
     pub async fn send_msg(&mut self, msg: Msg) -> Result<Option<Value>> {
-        let (tx, mut rx) = mpsc::channel::<MsgState>(1);
-        self.sender.send((msg, Some(tx.clone()))).await?;
-
-        let resp = timeout(Duration::from_secs(10), rx.recv()).await?;
+        self.send_msg_with(msg, RequestOption::default())
+            .await
+            .map(Some)
+    }
 
-        if let Some(val) = resp {
-            match val {
-                MsgState::Completed(v) => return Ok(Some(v)),
-                MsgState::Err(e) => return Err(anyhow!(e)),
-            }
-        }
+    pub async fn send_msg_with(&mut self, msg: Msg, option: RequestOption) -> Result<Value> {
+        let wait = option.timeout.unwrap_or_else(|| match &msg.data {
+            MsgKind::Request(v) => v.timeout(),
+            _ => DEFAULT_REQUEST_TIMEOUT,
+        });
+        let (tx, rx) = mpsc::channel::<MsgState>(1);
+        let tran = TransactionMsg::new(tx, wait);
+        self.sender.send((msg, Some(tran))).await?;
 
-        Ok(None)
+        wait_reply(rx, wait, option.cancel.as_deref()).await
     }
 
     pub fn key(&self) -> String {
@@ -131,6 +306,10 @@ impl<W, R> WsClient<W, R> {
         self.is_initialized.unwrap_or_default()
     }
 
+    pub fn get_peer(&self) -> PeerInfo {
+        self.peer.clone().unwrap_or_default()
+    }
+
     pub fn get_namespace(&self) -> String {
         self.namespace.clone().unwrap_or_default()
     }
@@ -154,20 +333,27 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
         let mut receiver = self.receiver.take().unwrap();
         let mut ws_writer = self.ws_writer.take().unwrap();
         let msg_box = self.msg_box.clone();
+        let option = FrameOption::negotiate(&self.get_peer(), self.compress_threshold);
+        debug!("negotiated frame option {option:?}");
+        // the writer keeps no strong sender, or it would never see the channel closed
+        let sender = self.sender.downgrade();
+        let abort = self.get_peer().supports(Capability::Abort);
 
         tokio::spawn(async move {
             let id_count = AtomicU64::new(1);
             while let Some(mut v) = receiver.recv().await {
-                let buf = if let MsgKind::Response(_) = v.0.data {
-                    Protocol::pack_response(v.0)
-                } else {
+                if let MsgKind::Request(_) = v.0.data {
                     v.0.id = id_count.fetch_add(1, Ordering::Relaxed);
-
-                    if let Some(tx) = v.1 {
-                        let tran = TransactionMsg::new(tx.clone(), v.0.id);
-                        msg_box.insert(v.0.id, tran).await;
+                    if let Some(tran) = v.1 {
+                        track_request(&msg_box, &sender, tran, v.0.id, abort).await;
+                    }
+                }
+                let buf = match Protocol::pack(v.0, option) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("failed pack msg - {e}");
+                        continue;
                     }
-                    Protocol::pack_request(v.0)
                 };
 
                 ws_writer
@@ -202,12 +388,14 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
                         self.namespace.replace(namespace);
                         self.local_ip.replace(v.agent_ip.parse().unwrap());
                         self.is_initialized.replace(v.is_initialized);
+                        self.peer.replace(v.peer.clone());
                         self.ws_reader.replace(ws_reader);
 
+                        // the agents before the protocol version read the response as text
                         let _ = ws_writer
                             .send(PMessage::Binary(Protocol::pack_response(Msg {
                                 id: 0,
-                                data: MsgKind::Response(json!("ok")),
+                                data: MsgKind::Response(serde_json::to_value(PeerInfo::local())?),
                             })))
                             .await?;
 
@@ -225,7 +413,7 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
     pub async fn recv<T, F>(&mut self, handler: T)
     where
         T: FnOnce(MsgReqKind) -> F + Send + Sync + Clone + 'static,
-        F: Future<Output = Value> + Send,
+        F: Future<Output = Result<Value, MsgError>> + Send + 'static,
     {
         while let Some(msg) = self.ws_reader.as_mut().unwrap().next().await {
             let msg = match msg {
@@ -236,56 +424,14 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
                 }
             };
 
-            let sender = self.sender.clone();
-            if msg.is_binary() {
-                let msg_box = self.msg_box.clone();
-                let handler = handler.clone();
-                tokio::spawn(async move {
-                    if let PMessage::Binary(buf) = msg {
-                        if Protocol::is_response(&buf) {
-                            let resp = Protocol::unpack_response(buf)
-                                .map_err(|e| error!("failed unpack_response - {e}"))
-                                .unwrap();
-
-                            if let Some(tx) = msg_box.get(&resp.id).await.map(|x| x.tx.clone()) {
-                                if let MsgKind::Response(buf) = resp.data {
-                                    let _ = tx
-                                        .send(MsgState::Completed(buf))
-                                        .await
-                                        .map_err(|e| error!("failed send response - {e}"));
-                                    return;
-                                }
-                                error!("invalid response format {:?}", resp);
-                            }
-
-                            return;
-                        }
-
-                        let resp = Protocol::unpack_request(buf)
-                            .map(|msg| async move {
-                                let id = msg.id;
-                                if let MsgKind::Request(req) = msg.data {
-                                    let resp = handler(req).await;
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(resp),
-                                    }
-                                } else {
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(json!("invalid data type")),
-                                    }
-                                }
-                            })
-                            .map_err(|e| error!("failed unpack_request -{e}"))
-                            .unwrap()
-                            .await;
-                        let _ = sender
-                            .send_timeout((resp, None), Duration::from_secs(1))
-                            .await
-                            .map_err(|e| error!("failed send message - {e}"));
-                    }
-                });
+            if let PMessage::Binary(buf) = msg {
+                tokio::spawn(handle_frame(
+                    buf,
+                    handler.clone(),
+                    self.msg_box.clone(),
+                    self.sender.clone(),
+                    self.running.clone(),
+                ));
             }
         }
     }
@@ -349,6 +495,9 @@ impl
             .await?;
 
         info!("success auth got response {auth_resp}");
+        // comet before the protocol version responds "ok"
+        self.peer
+            .replace(serde_json::from_value(auth_resp).unwrap_or_default());
 
         self.start_processing_to_server_msg();
         Ok(self)
@@ -358,19 +507,27 @@ impl
         let mut receiver = self.receiver.take().unwrap();
         let mut ws_writer = self.ws_writer.take().unwrap();
         let msg_box = self.msg_box.clone();
+        let option = FrameOption::negotiate(&self.get_peer(), self.compress_threshold);
+        debug!("negotiated frame option {option:?}");
+        // the writer keeps no strong sender, or it would never see the channel closed
+        let sender = self.sender.downgrade();
+        let abort = self.get_peer().supports(Capability::Abort);
 
         tokio::spawn(async move {
             let id_count = AtomicU64::new(1);
             while let Some(mut v) = receiver.recv().await {
-                let buf = if let MsgKind::Response(_) = v.0.data {
-                    Protocol::pack_response(v.0)
-                } else {
+                if let MsgKind::Request(_) = v.0.data {
                     v.0.id = id_count.fetch_add(1, Ordering::Relaxed);
-                    if let Some(tx) = v.1 {
-                        let tran = TransactionMsg::new(tx.clone(), v.0.id);
-                        msg_box.insert(v.0.id, tran).await;
+                    if let Some(tran) = v.1 {
+                        track_request(&msg_box, &sender, tran, v.0.id, abort).await;
+                    }
+                }
+                let buf = match Protocol::pack(v.0, option) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("failed pack msg - {e}");
+                        continue;
                     }
-                    Protocol::pack_request(v.0)
                 };
                 ws_writer
                     .send(Message::Binary(buf))
@@ -391,6 +548,7 @@ impl
                     is_initialized,
                     agent_ip: self.local_ip.unwrap().to_string(),
                     secret,
+                    peer: PeerInfo::local(),
                 })),
             })))
             .await?;
@@ -417,7 +575,7 @@ impl
     pub async fn recv<T, F>(&mut self, handler: T)
     where
         T: FnOnce(MsgReqKind) -> F + Send + Sync + Clone + 'static,
-        F: Future<Output = Value> + Send,
+        F: Future<Output = Result<Value, MsgError>> + Send + 'static,
     {
         loop {
             let msg = match timeout(
@@ -438,56 +596,14 @@ impl
                 _ => continue,
             };
 
-            let sender = self.sender.clone();
-            let handler = handler.clone();
-            if msg.is_binary() {
-                let msg_box = self.msg_box.clone();
-                tokio::spawn(async move {
-                    if let Message::Binary(buf) = msg {
-                        if Protocol::is_response(&buf) {
-                            let resp = Protocol::unpack_response(buf)
-                                .map_err(|e| error!("failed unpack_response - {e}"))
-                                .unwrap();
-
-                            if let Some(tx) = msg_box.get(&resp.id).await.map(|x| x.tx.clone()) {
-                                if let MsgKind::Response(buf) = resp.data {
-                                    let _ = tx
-                                        .send(MsgState::Completed(buf))
-                                        .await
-                                        .map_err(|e| error!("failed send response - {e}"));
-                                    return;
-                                }
-                                error!("invalid response format {:?}", resp);
-                            }
-
-                            return;
-                        }
-
-                        let resp = Protocol::unpack_request(buf)
-                            .map(|msg| async move {
-                                let id = msg.id;
-                                if let MsgKind::Request(req) = msg.data {
-                                    let resp = handler(req).await;
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(resp),
-                                    }
-                                } else {
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(json!("invalid data type")),
-                                    }
-                                }
-                            })
-                            .map_err(|e| error!("failed unpack_request -{e}"))
-                            .unwrap()
-                            .await;
-                        let _ = sender
-                            .send_timeout((resp, None), Duration::from_secs(1))
-                            .await
-                            .map_err(|e| error!("failed send message - {e}"));
-                    }
-                });
+            if let Message::Binary(buf) = msg {
+                tokio::spawn(handle_frame(
+                    buf,
+                    handler.clone(),
+                    self.msg_box.clone(),
+                    self.sender.clone(),
+                    self.running.clone(),
+                ));
             }
         }
     }
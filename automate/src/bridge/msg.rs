@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, time::Duration};
 
 use anyhow::Error;
 use chrono::{DateTime, Utc};
@@ -7,9 +7,11 @@ use serde_json::Value;
 use tokio::sync::mpsc::Sender;
 
 use crate::{
+    code,
     comet::handler::SecretHeader,
     scheduler::types::{
-        BaseJob, BundleOutput, JobAction, RunStatus, RuntimeAction, ScheduleStatus, ScheduleType,
+        BaseJob, BundleEntryOutput, BundleOutput, JobAction, RunStatus, RuntimeAction,
+        ScheduleStatus, ScheduleType, SupervisorOption, TimerOption, SECRET_MASK,
     },
 };
 
@@ -18,15 +20,23 @@ pub enum MsgState {
     Err(Error),
 }
 
+/// the timeout of most requests
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// sftp requests copy a whole file between the agent and the server
+pub const SFTP_REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+pub const MAX_REQUEST_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// A request waiting for its reply, the id is assigned when the request is written
 #[derive(Clone)]
 pub struct TransactionMsg {
     pub tx: Sender<MsgState>,
     pub id: u64,
+    pub timeout: Duration,
 }
 
 impl TransactionMsg {
-    pub fn new(tx: Sender<MsgState>, id: u64) -> Self {
-        Self { tx, id }
+    pub fn new(tx: Sender<MsgState>, timeout: Duration) -> Self {
+        Self { tx, id: 0, timeout }
     }
 }
 
@@ -46,7 +56,11 @@ pub struct SftpUploadParams {
     pub user: String,
     pub password: String,
     pub filepath: String,
+    #[serde(default, with = "serde_bytes")]
     pub data: Vec<u8>,
+    /// the file is pushed to the agent in chunks before, see [TransferChunkParams]
+    #[serde(default)]
+    pub transfer_id: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -56,6 +70,10 @@ pub struct SftpDownloadParams {
     pub user: String,
     pub password: String,
     pub filepath: String,
+    /// the file is kept on the agent and replied as [TransferAck] instead of its content,
+    /// it is read in chunks by [TransferReadParams] then
+    #[serde(default)]
+    pub transfer_id: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -80,12 +98,150 @@ pub enum MsgReqKind {
     Auth(AuthParams),
     UpdateJobRequest(UpdateJobParams),
     HeartbeatRequest(HeartbeatParams),
+    JobLogRequest(JobLogParams),
+    ReadOutputRequest(ReadOutputParams),
+    ListRuntimeStateRequest(ListRuntimeStateParams),
+    TransferChunkRequest(TransferChunkParams),
+    TransferStatusRequest(TransferParams),
+    TransferReadRequest(TransferReadParams),
+    TransferRemoveRequest(TransferParams),
+    AbortRequest(AbortParams),
+}
+
+impl MsgReqKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MsgReqKind::DispatchJobRequest(_) => "DispatchJobRequest",
+            MsgReqKind::RuntimeActionRequest(_) => "RuntimeActionRequest",
+            MsgReqKind::PullJobRequest(_) => "PullJobRequest",
+            MsgReqKind::SftpReadDirRequest(_) => "SftpReadDirRequest",
+            MsgReqKind::SftpUploadRequest(_) => "SftpUploadRequest",
+            MsgReqKind::SftpDownloadRequest(_) => "SftpDownloadRequest",
+            MsgReqKind::SftpRemoveRequest(_) => "SftpRemoveRequest",
+            MsgReqKind::Auth(_) => "Auth",
+            MsgReqKind::UpdateJobRequest(_) => "UpdateJobRequest",
+            MsgReqKind::HeartbeatRequest(_) => "HeartbeatRequest",
+            MsgReqKind::JobLogRequest(_) => "JobLogRequest",
+            MsgReqKind::ReadOutputRequest(_) => "ReadOutputRequest",
+            MsgReqKind::ListRuntimeStateRequest(_) => "ListRuntimeStateRequest",
+            MsgReqKind::TransferChunkRequest(_) => "TransferChunkRequest",
+            MsgReqKind::TransferStatusRequest(_) => "TransferStatusRequest",
+            MsgReqKind::TransferReadRequest(_) => "TransferReadRequest",
+            MsgReqKind::TransferRemoveRequest(_) => "TransferRemoveRequest",
+            MsgReqKind::AbortRequest(_) => "AbortRequest",
+        }
+    }
+
+    /// how long the requester waits for the reply
+    pub fn timeout(&self) -> Duration {
+        match self {
+            // a sync exec is replied after the job exits, including its retries
+            MsgReqKind::DispatchJobRequest(v) if v.is_sync && v.action == JobAction::Exec => {
+                v.base_job.max_run_time().map_or(MAX_REQUEST_TIMEOUT, |v| {
+                    v.saturating_add(DEFAULT_REQUEST_TIMEOUT)
+                        .min(MAX_REQUEST_TIMEOUT)
+                })
+            }
+            MsgReqKind::SftpUploadRequest(_) | MsgReqKind::SftpDownloadRequest(_) => {
+                SFTP_REQUEST_TIMEOUT
+            }
+            _ => DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+
+    /// the capability the peer needs to handle the request
+    pub fn required_capability(&self) -> Option<Capability> {
+        match self {
+            MsgReqKind::DispatchJobRequest(v) if TimerOption::is_console_mode(&v.timer_option) => {
+                Some(Capability::ConsoleTimer)
+            }
+            MsgReqKind::DispatchJobRequest(v)
+                if matches!(
+                    v.action,
+                    JobAction::StartSupervisor | JobAction::StopSupervisor
+                ) =>
+            {
+                Some(Capability::Supervisor)
+            }
+            MsgReqKind::RuntimeActionRequest(v) if v.action == RuntimeAction::StopSupervisor => {
+                Some(Capability::Supervisor)
+            }
+            MsgReqKind::JobLogRequest(_) => Some(Capability::StreamLog),
+            MsgReqKind::ReadOutputRequest(_) => Some(Capability::ReadOutput),
+            MsgReqKind::ListRuntimeStateRequest(_) => Some(Capability::RuntimeState),
+            MsgReqKind::TransferChunkRequest(_)
+            | MsgReqKind::TransferStatusRequest(_)
+            | MsgReqKind::TransferReadRequest(_)
+            | MsgReqKind::TransferRemoveRequest(_) => Some(Capability::Transfer),
+            MsgReqKind::AbortRequest(_) => Some(Capability::Abort),
+            MsgReqKind::SftpUploadRequest(v) if v.transfer_id.is_some() => {
+                Some(Capability::Transfer)
+            }
+            MsgReqKind::SftpDownloadRequest(v) if v.transfer_id.is_some() => {
+                Some(Capability::Transfer)
+            }
+            _ => None,
+        }
+    }
 }
 
+/// Error reply of a request, the code is one of [crate::code]
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct MsgError {
+    pub code: i32,
+    pub msg: String,
+}
+
+impl MsgError {
+    pub fn new(code: i32, msg: impl Into<String>) -> Self {
+        Self {
+            code,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn internal(e: Error) -> Self {
+        Self::new(code::INTERNAL_ERROR, e.to_string())
+    }
+
+    pub fn timeout(timeout: Duration) -> Self {
+        Self::new(
+            code::REQUEST_TIMEOUT,
+            format!("no reply in {}s", timeout.as_secs_f32()),
+        )
+    }
+
+    pub fn cancelled() -> Self {
+        Self::new(code::REQUEST_CANCELLED, "request cancelled")
+    }
+
+    pub fn unsupported(req: &MsgReqKind) -> Self {
+        Self::new(
+            code::UNSUPPORTED_REQUEST,
+            format!("unsupported request {}", req.name()),
+        )
+    }
+
+    /// the code of the error replied by the peer, or internal error for the others
+    pub fn code_of(e: &Error) -> i32 {
+        e.downcast_ref::<MsgError>()
+            .map_or(code::INTERNAL_ERROR, |v| v.code)
+    }
+}
+
+impl fmt::Display for MsgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for MsgError {}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
 pub enum MsgKind {
     Response(Value),
     Request(MsgReqKind),
+    Error(MsgError),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -103,6 +259,25 @@ pub struct DispatchJobParams {
     pub is_sync: bool,
     pub created_user: String,
     pub action: JobAction,
+    #[serde(default)]
+    pub supervisor_option: Option<SupervisorOption>,
+    #[serde(default)]
+    pub timer_option: Option<TimerOption>,
+    /// environment variables of the job process
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// injected into the environment like env, but masked in the output
+    #[serde(default)]
+    pub secrets: HashMap<String, String>,
+}
+
+impl DispatchJobParams {
+    /// replace the value of secrets before the params are stored
+    pub fn mask_secrets(&mut self) {
+        self.secrets
+            .values_mut()
+            .for_each(|v| *v = SECRET_MASK.to_string());
+    }
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -149,6 +324,18 @@ pub struct UpdateJobParams {
     pub end_time: Option<DateTime<Utc>>,
     pub prev_time: Option<DateTime<Utc>>,
     pub next_time: Option<DateTime<Utc>>,
+    /// current attempt of the run, starts from 1
+    pub attempt: Option<u8>,
+    /// the run failed and no retry is left
+    pub retry_exhausted: Option<bool>,
+    /// total bytes of the output, stdout and stderr may be truncated
+    pub stdout_size: Option<u64>,
+    pub stderr_size: Option<u64>,
+    /// file on the agent keeping the full output, see [ReadOutputParams]
+    pub stdout_file: Option<String>,
+    pub stderr_file: Option<String>,
+    /// unique id of the update, the console applies a replayed update only once
+    pub msg_id: Option<String>,
 }
 
 impl UpdateJobParams {
@@ -162,6 +349,49 @@ impl UpdateJobParams {
     }
 }
 
+/// Read the full output file of a run from the agent
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct ReadOutputParams {
+    pub filepath: String,
+    pub offset: u64,
+    /// max bytes to read
+    pub limit: u64,
+}
+
+/// List the timers, supervisors and running jobs of the agent, see [RuntimeStateReply]
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct ListRuntimeStateParams {}
+
+/// A job active on the agent
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct RuntimeJob {
+    pub eid: String,
+    pub schedule_id: String,
+    pub schedule_type: Option<ScheduleType>,
+    /// next fire time of a timer
+    pub next_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct RuntimeStateReply {
+    pub timers: Vec<RuntimeJob>,
+    pub supervisors: Vec<RuntimeJob>,
+    /// a job may have more runs at the same time
+    pub running: Vec<RuntimeJob>,
+}
+
+/// A batch of output lines of a running job
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct JobLogParams {
+    pub schedule_id: String,
+    pub eid: String,
+    pub bind_ip: String,
+    pub bind_namespace: String,
+    pub lines: Vec<String>,
+    /// the run is finished and no more lines will be sent
+    pub is_eof: bool,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
 pub struct BundleOutputParams {
     pub eid: String,
@@ -169,6 +399,14 @@ pub struct BundleOutputParams {
     pub exit_status: Option<String>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
+    #[serde(default)]
+    pub stdout_size: Option<u64>,
+    #[serde(default)]
+    pub stderr_size: Option<u64>,
+    #[serde(default)]
+    pub stdout_file: Option<String>,
+    #[serde(default)]
+    pub stderr_file: Option<String>,
 }
 
 impl BundleOutputParams {
@@ -177,18 +415,31 @@ impl BundleOutputParams {
             BundleOutput::Output(_) => None,
             BundleOutput::Bundle(v) => Some(
                 v.iter()
-                    .map(|v| BundleOutputParams {
-                        eid: v.0.to_owned(),
-                        exit_code: {
-                            if v.1.status.success() {
-                                v.1.status.code()
-                            } else {
-                                v.1.status.code().or(Some(9))
-                            }
+                    .map(|(eid, v)| match v {
+                        BundleEntryOutput::Output(v) => BundleOutputParams {
+                            eid: eid.to_owned(),
+                            exit_code: v.exit_code(),
+                            exit_status: Some(v.exit_status()),
+                            stdout: Some(String::from_utf8_lossy(&v.stdout).to_string()),
+                            stderr: Some(String::from_utf8_lossy(&v.stderr).to_string()),
+                            stdout_size: Some(v.stdout_size),
+                            stderr_size: Some(v.stderr_size),
+                            stdout_file: v.stdout_file.clone(),
+                            stderr_file: v.stderr_file.clone(),
+                        },
+                        BundleEntryOutput::Failed(e) => BundleOutputParams {
+                            eid: eid.to_owned(),
+                            exit_code: Some(99),
+                            exit_status: Some(e.to_owned()),
+                            stdout: Some(e.to_owned()),
+                            stderr: Some(e.to_owned()),
+                            ..Default::default()
+                        },
+                        BundleEntryOutput::Skipped(reason) => BundleOutputParams {
+                            eid: eid.to_owned(),
+                            exit_status: Some(reason.to_owned()),
+                            ..Default::default()
                         },
-                        exit_status: Some(v.1.status.to_string()),
-                        stdout: Some(String::from_utf8_lossy(&v.1.stdout).to_string()),
-                        stderr: Some(String::from_utf8_lossy(&v.1.stderr).to_string()),
                     })
                     .collect::<Vec<BundleOutputParams>>(),
             ),
@@ -196,11 +447,157 @@ impl BundleOutputParams {
     }
 }
 
+/// A chunk of a file pushed to the agent, the chunks are sent in order of seq
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct TransferChunkParams {
+    pub transfer_id: String,
+    pub seq: u64,
+    /// where the chunk starts in the file
+    pub offset: u64,
+    pub total_size: u64,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    /// hex sha256 of the whole file, carried by the last chunk only
+    pub sha256: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct TransferParams {
+    pub transfer_id: String,
+}
+
+/// Read a chunk of a file kept on the agent, see [TransferChunk]
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct TransferReadParams {
+    pub transfer_id: String,
+    pub offset: u64,
+    /// max bytes to read
+    pub len: u64,
+}
+
+/// Progress of a transfer replied to a chunk or a status request
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct TransferAck {
+    pub transfer_id: String,
+    /// seq of the acked chunk, none for a status request
+    pub seq: Option<u64>,
+    /// bytes received in order, the next chunk should start from here
+    pub received: u64,
+    /// the file is received and its checksum is verified
+    pub completed: bool,
+    pub sha256: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct TransferChunk {
+    pub offset: u64,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    /// no more data after the chunk
+    pub eof: bool,
+}
+
+/// Abort the request of the id the peer is handling, its caller gave up waiting
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct AbortParams {
+    pub id: u64,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
 pub struct AuthParams {
     pub agent_ip: String,
     pub secret: String,
     pub is_initialized: bool,
+    #[serde(flatten)]
+    pub peer: PeerInfo,
+}
+
+/// version of the bridge protocol, bumped when the messages change incompatibly.
+/// the peers before the version was introduced send none and are taken as 0
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features of a peer, announced during Auth so that the other side gates them
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Supervisor,
+    StreamLog,
+    ReadOutput,
+    RuntimeState,
+    ConsoleTimer,
+    /// reads frames encoded with msgpack, see [super::protocol::Codec]
+    Msgpack,
+    /// reads frames compressed with zstd
+    Zstd,
+    /// chunked file transfer, see [TransferChunkParams]
+    Transfer,
+    /// aborts a request when its caller gives up, see [AbortParams]
+    Abort,
+}
+
+impl Capability {
+    pub fn all() -> Vec<Capability> {
+        vec![
+            Capability::Supervisor,
+            Capability::StreamLog,
+            Capability::ReadOutput,
+            Capability::RuntimeState,
+            Capability::ConsoleTimer,
+            Capability::Msgpack,
+            Capability::Zstd,
+            Capability::Transfer,
+            Capability::Abort,
+        ]
+    }
+
+    /// the features of the peers without protocol version
+    pub fn legacy() -> Vec<Capability> {
+        vec![Capability::Supervisor]
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Capability::Supervisor => write!(f, "supervisor"),
+            Capability::StreamLog => write!(f, "stream_log"),
+            Capability::ReadOutput => write!(f, "read_output"),
+            Capability::RuntimeState => write!(f, "runtime_state"),
+            Capability::ConsoleTimer => write!(f, "console_timer"),
+            Capability::Msgpack => write!(f, "msgpack"),
+            Capability::Zstd => write!(f, "zstd"),
+            Capability::Transfer => write!(f, "transfer"),
+            Capability::Abort => write!(f, "abort"),
+        }
+    }
+}
+
+/// Protocol version and capabilities of a peer of the bridge
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct PeerInfo {
+    #[serde(default)]
+    pub protocol_version: u32,
+    /// kept as strings, a newer peer may have capabilities unknown to this version
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl PeerInfo {
+    /// what this version of the agent and comet announces
+    pub fn local() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Capability::all().iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        if self.protocol_version == 0 {
+            return Capability::legacy().contains(&capability);
+        }
+        let capability = capability.to_string();
+        self.capabilities.iter().any(|v| *v == capability)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -209,6 +606,8 @@ pub struct AgentOnlineParams {
     pub namespace: String,
     pub is_initialized: bool,
     pub secret_header: SecretHeader,
+    #[serde(default)]
+    pub peer: PeerInfo,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -216,3 +615,84 @@ pub struct AgentOfflineParams {
     pub agent_ip: String,
     pub namespace: String,
 }
+
+#[test]
+fn test_peer_capability() {
+    let legacy: PeerInfo = serde_json::from_value(serde_json::json!({})).unwrap();
+    assert_eq!(legacy.protocol_version, 0);
+    assert!(legacy.supports(Capability::Supervisor));
+    assert!(!legacy.supports(Capability::RuntimeState));
+
+    let peer = PeerInfo {
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: vec!["runtime_state".to_string(), "from_the_future".to_string()],
+    };
+    assert!(peer.supports(Capability::RuntimeState));
+    assert!(!peer.supports(Capability::Supervisor));
+    assert!(PeerInfo::local().supports(Capability::ConsoleTimer));
+
+    let req = MsgReqKind::ListRuntimeStateRequest(ListRuntimeStateParams::default());
+    assert_eq!(req.required_capability(), Some(Capability::RuntimeState));
+}
+
+#[test]
+fn test_request_timeout() {
+    use crate::scheduler::types::{BundleMode, BundleScript, ConcurrencyPolicy};
+
+    let mut params = DispatchJobParams {
+        base_job: BaseJob {
+            timeout: 60,
+            ..Default::default()
+        },
+        schedule_id: "".to_string(),
+        fields: None,
+        timer_expr: None,
+        is_sync: true,
+        created_user: "".to_string(),
+        action: JobAction::Exec,
+        supervisor_option: None,
+        timer_option: None,
+        env: HashMap::new(),
+        secrets: HashMap::new(),
+    };
+    assert_eq!(
+        MsgReqKind::DispatchJobRequest(params.clone()).timeout(),
+        Duration::from_secs(70)
+    );
+    // 3 attempts and 2 backoff delays of 1s
+    params.base_job.max_retry = 2;
+    assert_eq!(
+        MsgReqKind::DispatchJobRequest(params.clone()).timeout(),
+        Duration::from_secs(192)
+    );
+    // every entry of a sequential bundle may run up to the timeout
+    params.base_job.bundle_script = Some(vec![BundleScript::default(); 3]);
+    assert_eq!(
+        MsgReqKind::DispatchJobRequest(params.clone()).timeout(),
+        Duration::from_secs(552)
+    );
+    params.base_job.bundle_mode = BundleMode::Parallel;
+    params.base_job.bundle_concurrency = 2;
+    assert_eq!(
+        MsgReqKind::DispatchJobRequest(params.clone()).timeout(),
+        Duration::from_secs(372)
+    );
+    params.base_job.bundle_script = None;
+    params.base_job.max_parallel = 1;
+    params.base_job.concurrency_policy = ConcurrencyPolicy::Queue;
+    assert_eq!(
+        MsgReqKind::DispatchJobRequest(params.clone()).timeout(),
+        MAX_REQUEST_TIMEOUT
+    );
+    params.base_job.concurrency_policy = ConcurrencyPolicy::Allow;
+    params.base_job.timeout = 0;
+    assert_eq!(
+        MsgReqKind::DispatchJobRequest(params.clone()).timeout(),
+        MAX_REQUEST_TIMEOUT
+    );
+    params.is_sync = false;
+    assert_eq!(
+        MsgReqKind::DispatchJobRequest(params).timeout(),
+        DEFAULT_REQUEST_TIMEOUT
+    );
+}
@@ -1,16 +1,59 @@
+use std::borrow::Cow;
+
 use anyhow::{anyhow, Result};
 use bytes::{BufMut, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::msg::{Capability, Msg, MsgKind, PeerInfo};
+
+/// frames above the size are compressed if the peer supports it
+pub const DEFAULT_COMPRESS_THRESHOLD: usize = 64 * 1024;
+const ZSTD_LEVEL: i32 = 3;
 
-use super::msg::Msg;
+/// Encoding of the msg in a frame
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Codec {
+    #[default]
+    Json,
+    /// binary payloads such as uploaded files are kept as bytes instead of arrays of numbers
+    Msgpack,
+}
+
+/// How the frames sent to a peer are encoded, the receiver reads it from the frame
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameOption {
+    pub codec: Codec,
+    /// frames larger than the threshold are compressed with zstd, none disables it
+    pub compress_threshold: Option<usize>,
+}
+
+impl FrameOption {
+    /// the best encoding the peer supports, the peers without capabilities only read json
+    pub fn negotiate(peer: &PeerInfo, compress_threshold: usize) -> Self {
+        Self {
+            codec: if peer.supports(Capability::Msgpack) {
+                Codec::Msgpack
+            } else {
+                Codec::Json
+            },
+            compress_threshold: Some(compress_threshold)
+                .filter(|v| *v > 0 && peer.supports(Capability::Zstd)),
+        }
+    }
+}
 
 pub struct Protocol {}
 
 impl Protocol {
     const REQ_MARK: u8 = 0;
     const RESP_MARK: u8 = 1;
+    /// flags of the mark byte, they are never set for the peers reading plain json
+    const MSGPACK_FLAG: u8 = 1 << 1;
+    const ZSTD_FLAG: u8 = 1 << 2;
 
     pub fn is_response(data: &Vec<u8>) -> bool {
-        data[0] == Self::RESP_MARK
+        data.first()
+            .is_some_and(|v| v & Self::RESP_MARK == Self::RESP_MARK)
     }
 
     pub fn pack_request(data: Msg) -> Vec<u8> {
@@ -21,11 +64,17 @@ impl Protocol {
     }
 
     pub fn unpack_request(data: Vec<u8>) -> Result<Msg> {
-        if data[0] != Self::REQ_MARK {
-            return Err(anyhow!("invalid request msg format"));
+        Self::unpack(Self::REQ_MARK, &data).map_err(|e| anyhow!("invalid request msg - {e}"))
+    }
+
+    /// read the id of a msg without knowing its kind, e.g. a request added in a newer version
+    pub fn unpack_msg_id(data: &[u8]) -> Option<u64> {
+        #[derive(serde::Deserialize)]
+        struct MsgId {
+            id: u64,
         }
-        let val = &data[1..];
-        Ok(serde_json::from_slice::<Msg>(val)?)
+        let (mark, body) = data.split_first()?;
+        Self::decode::<MsgId>(*mark, body).ok().map(|v| v.id)
     }
 
     pub fn pack_response(data: Msg) -> Vec<u8> {
@@ -37,11 +86,57 @@ impl Protocol {
     }
 
     pub fn unpack_response(data: Vec<u8>) -> Result<Msg> {
-        if data[0] != Self::RESP_MARK {
-            return Err(anyhow!("invalid response msg format"));
+        Self::unpack(Self::RESP_MARK, &data).map_err(|e| anyhow!("invalid response msg - {e}"))
+    }
+
+    /// pack the request or response with the codec and compression of the option
+    pub fn pack(data: Msg, option: FrameOption) -> Result<Vec<u8>> {
+        let mut mark = match data.data {
+            MsgKind::Response(_) | MsgKind::Error(_) => Self::RESP_MARK,
+            MsgKind::Request(_) => Self::REQ_MARK,
+        };
+        let mut body = Self::encode(&data, option.codec)?;
+        if option.codec == Codec::Msgpack {
+            mark |= Self::MSGPACK_FLAG;
+        }
+        if option.compress_threshold.is_some_and(|v| body.len() > v) {
+            body = zstd::bulk::compress(&body, ZSTD_LEVEL)?;
+            mark |= Self::ZSTD_FLAG;
+        }
+
+        let mut b = BytesMut::with_capacity(body.len() + 1);
+        b.put_u8(mark);
+        b.extend(body);
+        Ok(b.to_vec())
+    }
+
+    fn unpack(kind: u8, data: &[u8]) -> Result<Msg> {
+        let (mark, body) = data.split_first().ok_or(anyhow!("empty msg"))?;
+        if mark & Self::RESP_MARK != kind {
+            anyhow::bail!("unexpected msg kind");
         }
-        let val = &data[1..];
-        Ok(serde_json::from_slice::<Msg>(val)?)
+        Self::decode(*mark, body)
+    }
+
+    fn encode<T: Serialize>(data: &T, codec: Codec) -> Result<Vec<u8>> {
+        Ok(match codec {
+            Codec::Json => serde_json::to_vec(data)?,
+            // with field names, so that the msgs are read like json when the fields change
+            Codec::Msgpack => rmp_serde::to_vec_named(data)?,
+        })
+    }
+
+    fn decode<T: DeserializeOwned>(mark: u8, body: &[u8]) -> Result<T> {
+        let body = if mark & Self::ZSTD_FLAG != 0 {
+            Cow::Owned(zstd::stream::decode_all(body)?)
+        } else {
+            Cow::Borrowed(body)
+        };
+        Ok(if mark & Self::MSGPACK_FLAG != 0 {
+            rmp_serde::from_slice(&body)?
+        } else {
+            serde_json::from_slice(&body)?
+        })
     }
 }
 
@@ -65,3 +160,77 @@ fn pack_request() {
         Err(_) => todo!(),
     }
 }
+
+#[test]
+fn pack_error_response() {
+    use crate::bridge::msg::{MsgError, MsgKind};
+
+    let old = Msg {
+        id: 7,
+        data: MsgKind::Error(MsgError::new(
+            crate::code::UNSUPPORTED_REQUEST,
+            "unsupported",
+        )),
+    };
+    let data = Protocol::pack_response(old.clone());
+    assert_eq!(Protocol::unpack_msg_id(&data), Some(7));
+    assert_eq!(Protocol::unpack_response(data).unwrap(), old);
+
+    // a request of a newer version is unknown, but its id is still readable
+    let data = [
+        &[Protocol::REQ_MARK][..],
+        br#"{"id":9,"data":{"Request":{"FooRequest":{}}}}"#,
+    ]
+    .concat();
+    assert_eq!(Protocol::unpack_msg_id(&data), Some(9));
+    assert!(Protocol::unpack_request(data).is_err());
+}
+
+#[test]
+fn pack_with_codec() {
+    use crate::bridge::msg::{MsgReqKind, SftpUploadParams};
+
+    let old = Msg {
+        id: 3,
+        data: MsgKind::Request(MsgReqKind::SftpUploadRequest(SftpUploadParams {
+            ip: "127.0.0.1".to_string(),
+            port: 22,
+            user: "root".to_string(),
+            password: "".to_string(),
+            filepath: "/tmp/artifact".to_string(),
+            data: vec![200; 256 * 1024],
+            transfer_id: None,
+        })),
+    };
+    let json = Protocol::pack_request(old.clone());
+
+    for option in [
+        FrameOption::default(),
+        FrameOption {
+            codec: Codec::Msgpack,
+            compress_threshold: None,
+        },
+        FrameOption {
+            codec: Codec::Msgpack,
+            compress_threshold: Some(DEFAULT_COMPRESS_THRESHOLD),
+        },
+    ] {
+        let data = Protocol::pack(old.clone(), option).unwrap();
+        if option.codec == Codec::Msgpack {
+            assert!(data.len() < json.len() / 2, "{option:?} {}", data.len());
+        }
+        assert!(!Protocol::is_response(&data));
+        assert_eq!(Protocol::unpack_msg_id(&data), Some(3));
+        assert_eq!(Protocol::unpack_request(data).unwrap(), old);
+    }
+
+    // the legacy peers only read plain json
+    assert_eq!(
+        FrameOption::negotiate(&PeerInfo::default(), DEFAULT_COMPRESS_THRESHOLD),
+        FrameOption::default()
+    );
+    assert_eq!(
+        FrameOption::negotiate(&PeerInfo::local(), 0).codec,
+        Codec::Msgpack
+    );
+}
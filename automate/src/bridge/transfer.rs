@@ -0,0 +1,520 @@
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    sync::Mutex,
+    time::sleep,
+};
+use tracing::{error, info, warn};
+
+use super::msg::{TransferAck, TransferChunk, TransferChunkParams, TransferReadParams};
+
+/// a chunk is small enough for the default timeout of a request and the memory of comet
+pub const CHUNK_SIZE: usize = 512 * 1024;
+const TRANSFER_DIR: &str = "transfer";
+const PART_EXTENSION: &str = "part";
+/// the files not used for the period are removed
+const MAX_TRANSFER_AGE: Duration = Duration::from_secs(24 * 3600);
+/// max failures in a row before a transfer gives up, e.g. the agent keeps offline
+const MAX_RETRIES: u32 = 10;
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// size and hex sha256 of the file
+pub async fn file_sha256(path: impl AsRef<Path>) -> Result<(u64, String)> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+/// The files transferred in chunks, a file is received into a part file
+/// and renamed once its checksum is verified, the part file is kept to resume
+#[derive(Clone)]
+pub struct TransferStore {
+    dir: PathBuf,
+    /// the chunks are written one by one, so a chunk sent twice is not appended twice
+    lock: Arc<Mutex<()>>,
+}
+
+impl TransferStore {
+    pub async fn open(output_dir: &str) -> Self {
+        let dir = PathBuf::from(output_dir).join(TRANSFER_DIR);
+        if let Err(e) = fs::create_dir_all(&dir).await {
+            error!("failed create transfer dir {} - {e}", dir.display());
+        }
+        let store = Self {
+            dir,
+            lock: Arc::new(Mutex::new(())),
+        };
+        store.purge().await;
+        store
+    }
+
+    fn check_id(transfer_id: &str) -> Result<()> {
+        if transfer_id.is_empty()
+            || transfer_id.len() > 128
+            || !transfer_id
+                .chars()
+                .all(|v| v.is_ascii_alphanumeric() || v == '-' || v == '_')
+        {
+            anyhow::bail!("invalid transfer id {transfer_id}");
+        }
+        Ok(())
+    }
+
+    /// path of the completed file
+    pub fn path(&self, transfer_id: &str) -> Result<PathBuf> {
+        Self::check_id(transfer_id)?;
+        Ok(self.dir.join(transfer_id))
+    }
+
+    /// path of the file being received
+    pub fn part_path(&self, transfer_id: &str) -> Result<PathBuf> {
+        Ok(self.path(transfer_id)?.with_extension(PART_EXTENSION))
+    }
+
+    pub async fn status(&self, transfer_id: &str) -> Result<TransferAck> {
+        let _lock = self.lock.lock().await;
+        self.status_locked(transfer_id).await
+    }
+
+    async fn status_locked(&self, transfer_id: &str) -> Result<TransferAck> {
+        let path = self.path(transfer_id)?;
+        if let Ok(file) = fs::File::open(&path).await {
+            // keep the file used recently from being purged
+            let file = file.into_std().await;
+            let size = file.metadata()?.len();
+            let _ = file.set_modified(SystemTime::now());
+            return Ok(TransferAck {
+                transfer_id: transfer_id.to_string(),
+                received: size,
+                completed: true,
+                ..Default::default()
+            });
+        }
+
+        let received = match fs::metadata(self.part_path(transfer_id)?).await {
+            Ok(v) => v.len(),
+            Err(_) => 0,
+        };
+        Ok(TransferAck {
+            transfer_id: transfer_id.to_string(),
+            received,
+            ..Default::default()
+        })
+    }
+
+    /// append the chunk if it starts where the received data ends, otherwise the chunk
+    /// is ignored and the ack tells the sender where to continue
+    pub async fn write_chunk(&self, chunk: TransferChunkParams) -> Result<TransferAck> {
+        let _lock = self.lock.lock().await;
+        let mut ack = self.status_locked(&chunk.transfer_id).await?;
+        ack.seq = Some(chunk.seq);
+        if ack.completed {
+            return Ok(ack);
+        }
+        // a part file longer than the file is left by another transfer, start over
+        if chunk.offset == 0 && ack.received > 0 {
+            ack.received = 0;
+        }
+        if chunk.offset != ack.received {
+            return Ok(ack);
+        }
+        if chunk.offset + chunk.data.len() as u64 > chunk.total_size {
+            anyhow::bail!(
+                "chunk {} of transfer {} exceeds the size {}",
+                chunk.seq,
+                chunk.transfer_id,
+                chunk.total_size
+            );
+        }
+
+        let part_path = self.part_path(&chunk.transfer_id)?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(chunk.offset == 0)
+            .open(&part_path)
+            .await?;
+        file.seek(SeekFrom::Start(chunk.offset)).await?;
+        file.write_all(&chunk.data).await?;
+        file.flush().await?;
+        ack.received = chunk.offset + chunk.data.len() as u64;
+
+        if ack.received < chunk.total_size {
+            return Ok(ack);
+        }
+        let Some(expected) = chunk.sha256 else {
+            anyhow::bail!(
+                "the last chunk of transfer {} has no checksum",
+                chunk.transfer_id
+            );
+        };
+        let (_, sha256) = file_sha256(&part_path).await?;
+        if sha256 != expected {
+            fs::remove_file(&part_path).await?;
+            anyhow::bail!(
+                "checksum mismatch of transfer {}, expected {expected} but got {sha256}",
+                chunk.transfer_id
+            );
+        }
+        fs::rename(&part_path, self.path(&chunk.transfer_id)?).await?;
+        info!(
+            "transfer {} completed, {} bytes",
+            chunk.transfer_id, ack.received
+        );
+
+        ack.completed = true;
+        ack.sha256 = Some(sha256);
+        Ok(ack)
+    }
+
+    /// mark the part file written by the agent itself as completed
+    pub async fn complete(&self, transfer_id: &str) -> Result<TransferAck> {
+        let _lock = self.lock.lock().await;
+        let path = self.path(transfer_id)?;
+        fs::rename(self.part_path(transfer_id)?, &path).await?;
+        let (size, sha256) = file_sha256(&path).await?;
+        Ok(TransferAck {
+            transfer_id: transfer_id.to_string(),
+            received: size,
+            completed: true,
+            sha256: Some(sha256),
+            ..Default::default()
+        })
+    }
+
+    pub async fn read_chunk(&self, params: TransferReadParams) -> Result<TransferChunk> {
+        let mut file = fs::File::open(self.path(&params.transfer_id)?).await?;
+        let size = file.metadata().await?.len();
+        let len = params.len.min(CHUNK_SIZE as u64);
+        file.seek(SeekFrom::Start(params.offset)).await?;
+        let mut data = Vec::new();
+        file.take(len).read_to_end(&mut data).await?;
+
+        Ok(TransferChunk {
+            offset: params.offset,
+            eof: params.offset + data.len() as u64 >= size,
+            data,
+        })
+    }
+
+    pub async fn remove(&self, transfer_id: &str) -> Result<()> {
+        let _lock = self.lock.lock().await;
+        for path in [self.path(transfer_id)?, self.part_path(transfer_id)?] {
+            if let Err(e) = fs::remove_file(&path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// remove the files of the transfers abandoned or not used for a long time
+    pub async fn purge(&self) {
+        let Ok(mut entries) = fs::read_dir(&self.dir).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let expired = entry
+                .metadata()
+                .await
+                .and_then(|v| v.modified())
+                .ok()
+                .and_then(|v| v.elapsed().ok())
+                .is_some_and(|v| v > MAX_TRANSFER_AGE);
+            if expired {
+                if let Err(e) = fs::remove_file(entry.path()).await {
+                    warn!("failed remove transfer {} - {e}", entry.path().display());
+                }
+            }
+        }
+    }
+}
+
+/// Where the chunks of a file are pushed to, e.g. the agent through comet
+#[async_trait]
+pub trait ChunkChannel: Send + Sync {
+    async fn status(&self, transfer_id: &str) -> Result<TransferAck>;
+    async fn send_chunk(&self, chunk: TransferChunkParams) -> Result<TransferAck>;
+}
+
+/// Where the chunks of a file are pulled from
+#[async_trait]
+pub trait ChunkSource: Send + Sync {
+    async fn read_chunk(&self, params: TransferReadParams) -> Result<TransferChunk>;
+}
+
+/// push the file in chunks, the transfer resumes from what the receiver has
+/// after a failure such as a reconnect of the agent
+pub async fn push<C, R>(
+    channel: &C,
+    transfer_id: &str,
+    mut reader: R,
+    total_size: u64,
+    sha256: &str,
+) -> Result<TransferAck>
+where
+    C: ChunkChannel + ?Sized,
+    R: AsyncRead + AsyncSeek + Unpin + Send,
+{
+    let mut retries = 0;
+    let mut offset = None;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        if retries > MAX_RETRIES {
+            anyhow::bail!("transfer {transfer_id} failed after {MAX_RETRIES} retries");
+        }
+        let start = match offset {
+            Some(v) => v,
+            None => match channel.status(transfer_id).await {
+                Ok(ack) if ack.completed => return Ok(ack),
+                Ok(ack) if ack.received > total_size => 0,
+                Ok(ack) => ack.received,
+                Err(e) => {
+                    warn!("failed get status of transfer {transfer_id} - {e}");
+                    retries += 1;
+                    sleep(RETRY_INTERVAL * retries).await;
+                    continue;
+                }
+            },
+        };
+
+        let len = (total_size - start).min(CHUNK_SIZE as u64) as usize;
+        reader.seek(SeekFrom::Start(start)).await?;
+        reader.read_exact(&mut buf[..len]).await?;
+        let is_last = start + len as u64 == total_size;
+        let chunk = TransferChunkParams {
+            transfer_id: transfer_id.to_string(),
+            seq: start / CHUNK_SIZE as u64,
+            offset: start,
+            total_size,
+            data: buf[..len].to_vec(),
+            sha256: Some(sha256.to_string()).filter(|_| is_last),
+        };
+
+        match channel.send_chunk(chunk).await {
+            Ok(ack) if ack.completed => return Ok(ack),
+            Ok(ack) if is_last && ack.received == total_size => {
+                anyhow::bail!("transfer {transfer_id} is not completed by the receiver")
+            }
+            Ok(ack) => {
+                retries = 0;
+                offset = Some(ack.received).filter(|v| *v <= total_size);
+            }
+            Err(e) => {
+                warn!("failed send chunk of transfer {transfer_id} at {start} - {e}");
+                retries += 1;
+                offset = None;
+                sleep(RETRY_INTERVAL * retries).await;
+            }
+        }
+    }
+}
+
+/// push the file on the disk
+pub async fn push_file<C: ChunkChannel + ?Sized>(
+    channel: &C,
+    transfer_id: &str,
+    path: impl AsRef<Path>,
+) -> Result<TransferAck> {
+    let (size, sha256) = file_sha256(&path).await?;
+    let file = fs::File::open(&path).await?;
+    push(channel, transfer_id, file, size, &sha256).await
+}
+
+/// pull the file the source acked into the writer and verify its checksum
+pub async fn pull<S, W>(source: &S, ack: &TransferAck, writer: &mut W) -> Result<u64>
+where
+    S: ChunkSource + ?Sized,
+    W: AsyncWrite + Unpin + Send,
+{
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    let mut retries = 0;
+    loop {
+        let params = TransferReadParams {
+            transfer_id: ack.transfer_id.clone(),
+            offset,
+            len: CHUNK_SIZE as u64,
+        };
+        let chunk = match source.read_chunk(params).await {
+            Ok(v) => v,
+            Err(e) if retries < MAX_RETRIES => {
+                warn!(
+                    "failed read chunk of transfer {} at {offset} - {e}",
+                    ack.transfer_id
+                );
+                retries += 1;
+                sleep(RETRY_INTERVAL * retries).await;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        retries = 0;
+        if chunk.offset != offset {
+            anyhow::bail!(
+                "unexpected chunk of transfer {} at {}, expected {offset}",
+                ack.transfer_id,
+                chunk.offset
+            );
+        }
+        hasher.update(&chunk.data);
+        writer.write_all(&chunk.data).await?;
+        offset += chunk.data.len() as u64;
+        if chunk.eof || chunk.data.is_empty() {
+            break;
+        }
+    }
+    writer.flush().await?;
+
+    if offset != ack.received {
+        anyhow::bail!(
+            "size mismatch of transfer {}, expected {} but got {offset}",
+            ack.transfer_id,
+            ack.received
+        );
+    }
+    let sha256 = format!("{:x}", hasher.finalize());
+    match &ack.sha256 {
+        Some(v) if *v != sha256 => Err(anyhow!(
+            "checksum mismatch of transfer {}, expected {v} but got {sha256}",
+            ack.transfer_id
+        )),
+        _ => Ok(offset),
+    }
+}
+
+#[async_trait]
+impl ChunkChannel for TransferStore {
+    async fn status(&self, transfer_id: &str) -> Result<TransferAck> {
+        TransferStore::status(self, transfer_id).await
+    }
+
+    async fn send_chunk(&self, chunk: TransferChunkParams) -> Result<TransferAck> {
+        self.write_chunk(chunk).await
+    }
+}
+
+#[async_trait]
+impl ChunkSource for TransferStore {
+    async fn read_chunk(&self, params: TransferReadParams) -> Result<TransferChunk> {
+        TransferStore::read_chunk(self, params).await
+    }
+}
+
+#[tokio::test]
+async fn test_push_and_pull() {
+    /// fails every other chunk as if the agent reconnects during the transfer
+    struct FlakyChannel {
+        store: TransferStore,
+        calls: std::sync::atomic::AtomicU32,
+    }
+
+    #[async_trait]
+    impl ChunkChannel for FlakyChannel {
+        async fn status(&self, transfer_id: &str) -> Result<TransferAck> {
+            self.store.status(transfer_id).await
+        }
+
+        async fn send_chunk(&self, chunk: TransferChunkParams) -> Result<TransferAck> {
+            let n = self
+                .calls
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let ack = self.store.write_chunk(chunk).await?;
+            if n % 2 == 1 {
+                anyhow::bail!("connection reset");
+            }
+            Ok(ack)
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("transfer-{}", uuid::Uuid::new_v4()));
+    let store = TransferStore::open(dir.to_str().unwrap()).await;
+    let channel = FlakyChannel {
+        store: store.clone(),
+        calls: Default::default(),
+    };
+
+    let data: Vec<u8> = (0..CHUNK_SIZE * 3 + 100).map(|v| v as u8).collect();
+    let sha256 = sha256_hex(&data);
+    let ack = push(
+        &channel,
+        &sha256,
+        std::io::Cursor::new(data.clone()),
+        data.len() as u64,
+        &sha256,
+    )
+    .await
+    .unwrap();
+    assert!(ack.completed);
+    assert_eq!(ack.received, data.len() as u64);
+    assert_eq!(fs::read(store.path(&sha256).unwrap()).await.unwrap(), data);
+
+    // pushed again, the completed file is reused
+    let ack = push(
+        &store,
+        &sha256,
+        std::io::Cursor::new(Vec::<u8>::new()),
+        0,
+        "",
+    )
+    .await
+    .unwrap();
+    assert!(ack.completed);
+
+    let mut pulled = vec![];
+    let ack = TransferAck {
+        sha256: Some(sha256.clone()),
+        ..ack
+    };
+    pull(&store, &ack, &mut pulled).await.unwrap();
+    assert_eq!(pulled, data);
+
+    let ack = TransferAck {
+        sha256: Some(sha256_hex(b"other")),
+        ..ack
+    };
+    assert!(pull(&store, &ack, &mut Vec::<u8>::new()).await.is_err());
+
+    // a corrupted file is refused
+    let bad = store
+        .write_chunk(TransferChunkParams {
+            transfer_id: "bad".to_string(),
+            seq: 0,
+            offset: 0,
+            total_size: 3,
+            data: b"abc".to_vec(),
+            sha256: Some(sha256_hex(b"abd")),
+        })
+        .await;
+    assert!(bad.is_err());
+    assert!(!store.status("bad").await.unwrap().completed);
+    assert!(store.path("../etc/passwd").is_err());
+
+    store.remove(&sha256).await.unwrap();
+    fs::remove_dir_all(dir).await.unwrap();
+}
@@ -1,11 +1,17 @@
-use std::pin::Pin;
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 use futures::Future;
-use local_ip_address::local_ip;
 use redis::{
+    aio::MultiplexedConnection,
     from_redis_value,
-    streams::{StreamReadOptions, StreamReadReply},
+    streams::{
+        StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamInfoConsumersReply,
+        StreamMaxlen, StreamReadOptions, StreamReadReply,
+    },
     AsyncCommands, Client,
 };
 use redis_macros::{FromRedisValue, ToRedisArgs};
@@ -13,7 +19,12 @@ use serde::{Deserialize, Serialize};
 
 use tracing::{error, info, warn};
 
-use crate::bridge::msg::{AgentOfflineParams, AgentOnlineParams, HeartbeatParams, UpdateJobParams};
+use crate::{
+    bridge::msg::{
+        AgentOfflineParams, AgentOnlineParams, HeartbeatParams, JobLogParams, UpdateJobParams,
+    },
+    get_endpoint,
+};
 
 #[derive(Debug, Serialize, Deserialize, FromRedisValue, ToRedisArgs)]
 pub enum Msg {
@@ -31,6 +42,19 @@ pub struct Bus {
 impl Bus {
     pub const JOB_TOPIC: &'static str = "jiascheduler:job:event";
     pub const CONSUMER_GROUP: &'static str = "jiascheduler-group";
+    pub const JOB_LOG_TOPIC: &'static str = "jiascheduler:job:log";
+    /// max batches kept in the log stream of a schedule
+    pub const JOB_LOG_MAXLEN: usize = 1000;
+    /// seconds the log stream is kept after the last write
+    pub const JOB_LOG_TTL: i64 = 86400;
+    pub const UPDATE_JOB_DEDUP_KEY: &'static str = "jiascheduler:job:update";
+    /// seconds the id of an applied update is kept, longer than the agent keeps an update
+    pub const UPDATE_JOB_DEDUP_TTL: u64 = 7 * 86400;
+    /// milliseconds a message is pending before it is taken over from a dead consumer
+    pub const PENDING_IDLE_MS: usize = 60000;
+    /// milliseconds a consumer without pending messages is idle before it is removed from the group
+    pub const CONSUMER_IDLE_MS: usize = 86400000;
+    const CLAIM_INTERVAL: Duration = Duration::from_secs(30);
 
     pub fn new(redis_client: Client) -> Self {
         Self { redis_client }
@@ -40,6 +64,29 @@ impl Bus {
         self.send_msg(&[("event", Msg::UpdateJob(msg))]).await
     }
 
+    /// mark the update as applied, return false if it has been applied before
+    pub async fn try_mark_update(&self, msg_id: &str) -> Result<bool> {
+        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+        let v: Option<String> = redis::cmd("SET")
+            .arg(format!("{}:{msg_id}", Self::UPDATE_JOB_DEDUP_KEY))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(Self::UPDATE_JOB_DEDUP_TTL)
+            .query_async(&mut conn)
+            .await?;
+        Ok(v.is_some())
+    }
+
+    /// unmark the update which failed to be applied, so that it can be applied again
+    pub async fn unmark_update(&self, msg_id: &str) -> Result<()> {
+        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+        let _: i64 = conn
+            .del(format!("{}:{msg_id}", Self::UPDATE_JOB_DEDUP_KEY))
+            .await?;
+        Ok(())
+    }
+
     pub async fn heartbeat(&self, msg: HeartbeatParams) -> Result<String> {
         self.send_msg(&[("event", Msg::Heartbeat(msg))]).await
     }
@@ -52,6 +99,62 @@ impl Bus {
         self.send_msg(&[("event", Msg::AgentOffline(msg))]).await
     }
 
+    pub fn job_log_key(namespace: &str, ip: &str, schedule_id: &str) -> String {
+        format!(
+            "{}:{}:{schedule_id}",
+            Self::JOB_LOG_TOPIC,
+            get_endpoint(namespace, ip)
+        )
+    }
+
+    /// append a batch of job output lines to the log stream of the schedule on the instance,
+    /// the stream is not consumed by the group, every reader tails it independently
+    pub async fn job_log(&self, msg: JobLogParams) -> Result<String> {
+        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+        let key = Self::job_log_key(&msg.bind_namespace, &msg.bind_ip, &msg.schedule_id);
+
+        let v: String = conn
+            .xadd_maxlen(
+                &key,
+                StreamMaxlen::Approx(Self::JOB_LOG_MAXLEN),
+                "*",
+                &[("log", serde_json::to_string(&msg)?)],
+            )
+            .await?;
+        let _: () = conn.expire(&key, Self::JOB_LOG_TTL).await?;
+        Ok(v)
+    }
+
+    /// read job log batches after last_id, block at most block_ms if nothing is available,
+    /// use "0" as last_id to read from the beginning, return the id of the last batch
+    pub async fn tail_job_log(
+        &self,
+        namespace: &str,
+        ip: &str,
+        schedule_id: &str,
+        last_id: &str,
+        block_ms: usize,
+    ) -> Result<(String, Vec<JobLogParams>)> {
+        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+        let key = Self::job_log_key(namespace, ip, schedule_id);
+        let opts = StreamReadOptions::default().block(block_ms).count(100);
+
+        let ret: StreamReadReply = conn.xread_options(&[&key], &[last_id], &opts).await?;
+
+        let mut last_id = last_id.to_string();
+        let mut list = Vec::new();
+        for stream_key in ret.keys {
+            for stream_id in stream_key.ids {
+                if let Some(v) = stream_id.map.get("log") {
+                    let v: String = from_redis_value(v)?;
+                    list.push(serde_json::from_str(&v)?);
+                }
+                last_id = stream_id.id;
+            }
+        }
+        Ok((last_id, list))
+    }
+
     pub async fn send_msg<'a>(&self, items: &'a [(&'a str, Msg)]) -> Result<String> {
         let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
 
@@ -59,8 +162,45 @@ impl Bus {
         Ok(v)
     }
 
+    /// take over the messages pending too long on other consumers, e.g. a console replica
+    /// which exited before acking them, and remove the consumers which are gone
+    async fn claim_pending(
+        &self,
+        conn: &mut MultiplexedConnection,
+        consumer: &str,
+    ) -> Result<Vec<StreamId>> {
+        let ret: StreamAutoClaimReply = conn
+            .xautoclaim_options(
+                Self::JOB_TOPIC,
+                Self::CONSUMER_GROUP,
+                consumer,
+                Self::PENDING_IDLE_MS,
+                "0-0",
+                StreamAutoClaimOptions::default().count(100),
+            )
+            .await?;
+        if !ret.claimed.is_empty() {
+            info!("claimed {} pending msg", ret.claimed.len());
+        }
+
+        let info: StreamInfoConsumersReply = conn
+            .xinfo_consumers(Self::JOB_TOPIC, Self::CONSUMER_GROUP)
+            .await?;
+        for v in info.consumers {
+            if v.name != consumer && v.pending == 0 && v.idle > Self::CONSUMER_IDLE_MS {
+                let _: i64 = conn
+                    .xgroup_delconsumer(Self::JOB_TOPIC, Self::CONSUMER_GROUP, &v.name)
+                    .await?;
+                info!("removed idle consumer {}", v.name);
+            }
+        }
+        Ok(ret.claimed)
+    }
+
+    /// consume the messages of the group as the consumer, which must be unique among the processes
     pub async fn recv(
         &self,
+        consumer: &str,
         mut cb: impl Sync
             + Send
             + FnMut(String, Msg) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>,
@@ -81,47 +221,56 @@ impl Bus {
         info!("create stream group {}", ret);
 
         let opts = StreamReadOptions::default()
-            .group(Self::CONSUMER_GROUP, local_ip()?.to_string())
+            .group(Self::CONSUMER_GROUP, consumer)
             .block(50)
             .count(100);
+        let mut claimed_at: Option<Instant> = None;
 
         loop {
+            let mut entries = Vec::new();
+            if claimed_at.map_or(true, |v| v.elapsed() >= Self::CLAIM_INTERVAL) {
+                claimed_at = Some(Instant::now());
+                match self.claim_pending(&mut conn, consumer).await {
+                    Ok(v) => entries.extend(v),
+                    Err(e) => warn!("failed claim pending msg - {e}"),
+                }
+            }
+
             let ret: StreamReadReply = conn
                 .xread_options(&[Self::JOB_TOPIC], &[">"], &opts)
                 .await?;
-
             for stream_key in ret.keys {
-                let msg_key = stream_key.key;
-
-                for stream_id in stream_key.ids {
-                    for (k, v) in stream_id.map {
-                        let ret = match from_redis_value::<Msg>(&v) {
-                            Ok(msg) => cb(k, msg).await,
-                            Err(e) => {
-                                error!("failed to parse redis val - {e}");
-                                Ok(())
-                            }
-                        };
-
-                        if let Err(e) = ret {
-                            error!("failed to handle msg - {e}");
+                entries.extend(stream_key.ids);
+            }
+
+            for stream_id in entries {
+                for (k, v) in stream_id.map {
+                    let ret = match from_redis_value::<Msg>(&v) {
+                        Ok(msg) => cb(k, msg).await,
+                        Err(e) => {
+                            error!("failed to parse redis val - {e}");
+                            Ok(())
                         }
+                    };
 
-                        let _: i32 = conn
-                            .xack(
-                                msg_key.clone(),
-                                Self::CONSUMER_GROUP,
-                                &[stream_id.id.clone()],
-                            )
-                            .await
-                            .map_or_else(
-                                |v| {
-                                    error!("faile to exec xack - {}", v);
-                                    0
-                                },
-                                |v| v,
-                            );
+                    if let Err(e) = ret {
+                        error!("failed to handle msg - {e}");
                     }
+
+                    let _: i32 = conn
+                        .xack(
+                            Self::JOB_TOPIC,
+                            Self::CONSUMER_GROUP,
+                            &[stream_id.id.clone()],
+                        )
+                        .await
+                        .map_or_else(
+                            |v| {
+                                error!("faile to exec xack - {}", v);
+                                0
+                            },
+                            |v| v,
+                        );
                 }
             }
         }
@@ -153,7 +302,7 @@ async fn test_bus() {
     .await
     .unwrap();
 
-    bus.recv(|key, val| {
+    bus.recv("test", |key, val| {
         Box::pin(async move {
             println!("key:{key} val:{}", serde_json::to_string(&val).unwrap());
             Ok(())
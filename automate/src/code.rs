@@ -0,0 +1,20 @@
+//! Response codes shared by the agent, comet and console
+
+pub const SUCCESS: i32 = 20000;
+/// unexpected error while handling the request
+pub const INTERNAL_ERROR: i32 = 50000;
+/// the request is rejected by the business logic
+pub const BIZ_ERROR: i32 = 50001;
+pub const INVALID_JSON: i32 = 50003;
+pub const INVALID_USER: i32 = 50004;
+pub const NO_PERMISSION: i32 = 50005;
+/// the peer of the bridge does not handle the kind of request, e.g. it is an older version
+pub const UNSUPPORTED_REQUEST: i32 = 50100;
+/// the message of the bridge is malformed, e.g. a response is sent as a request
+pub const INVALID_MSG: i32 = 50101;
+/// no reply of the bridge request in its timeout
+pub const REQUEST_TIMEOUT: i32 = 50102;
+/// the bridge request is cancelled by its caller
+pub const REQUEST_CANCELLED: i32 = 50103;
+pub const BAD_REQUEST: i32 = 50400;
+pub const NOT_LOGIN: i32 = 50401;
@@ -7,15 +7,16 @@ use handler::SecretHeader;
 use poem::web::websocket::WebSocketStream;
 use serde_json::{json, Value};
 use tokio::sync::{mpsc::Sender, Mutex};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use types::SshLoginParams;
 
 use crate::{
     bridge::{
         msg::{
-            AgentOfflineParams, AgentOnlineParams, HeartbeatParams, Msg, MsgReqKind, MsgState,
-            UpdateJobParams,
+            AgentOfflineParams, AgentOnlineParams, HeartbeatParams, JobLogParams, Msg, MsgError,
+            MsgReqKind, PeerInfo, TransactionMsg, UpdateJobParams,
         },
+        protocol::DEFAULT_COMPRESS_THRESHOLD,
         Bridge,
     },
     get_endpoint,
@@ -36,6 +37,7 @@ pub struct Comet {
     logic: Logic,
     secret: String,
     port: u16,
+    compress_threshold: usize,
     pub ssh_ws_streams: Arc<Mutex<HashMap<String, WebSocketStream>>>,
 }
 
@@ -47,9 +49,16 @@ impl Comet {
             ssh_ws_streams: Arc::new(Mutex::new(HashMap::new())),
             port,
             secret,
+            compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
         }
     }
 
+    /// frames sent to the agents above the size are compressed, 0 disables compression
+    pub fn set_compress_threshold(mut self, compress_threshold: usize) -> Self {
+        self.compress_threshold = compress_threshold;
+        self
+    }
+
     pub async fn register_ssh_stream(&mut self, key: String, ws: WebSocketStream) {
         self.ssh_ws_streams.lock().await.insert(key.clone(), ws);
         debug!("completed register ssh stream {key}");
@@ -82,12 +91,16 @@ impl Comet {
         is_initialized: bool,
         namespace: String,
         ip: String,
-        client: Sender<(Msg, Option<Sender<MsgState>>)>,
+        client: Sender<(Msg, Option<TransactionMsg>)>,
+        peer: PeerInfo,
     ) {
         let key = get_endpoint(namespace.clone(), ip.clone());
-        info!("{key} online");
+        info!(
+            "{key} online, protocol version {}, capabilities {:?}",
+            peer.protocol_version, peer.capabilities
+        );
 
-        self.bridge.append_client(key, client).await;
+        self.bridge.append_client(key, client, peer.clone()).await;
         let ret = self
             .logic
             .agent_online(AgentOnlineParams {
@@ -95,6 +108,7 @@ impl Comet {
                 agent_ip: ip,
                 namespace,
                 secret_header,
+                peer,
             })
             .await;
         if let Err(e) = ret {
@@ -156,6 +170,43 @@ impl Comet {
         Ok(ret)
     }
 
+    pub async fn read_output(&self, req: types::ReadOutputRequest) -> Result<Value> {
+        let val = self.logic.read_output(req).await?;
+        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
+    pub async fn list_runtime_state(&self, req: types::ListRuntimeStateRequest) -> Result<Value> {
+        let val = self.logic.list_runtime_state(req).await?;
+        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
+    /// a chunk of a transfer is relayed to the agent as it is, comet keeps nothing of it
+    pub async fn transfer_chunk(&self, req: types::TransferChunkRequest) -> Result<Value> {
+        let val = self.logic.transfer_chunk(req).await?;
+        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
+    pub async fn transfer_status(&self, req: types::TransferRequest) -> Result<Value> {
+        let val = self.logic.transfer_status(req).await?;
+        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
+    pub async fn transfer_read(&self, req: types::TransferReadRequest) -> Result<Value> {
+        let val = self.logic.transfer_read(req).await?;
+        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
+    pub async fn transfer_remove(&self, req: types::TransferRequest) -> Result<Value> {
+        let val = self.logic.transfer_remove(req).await?;
+        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
     pub async fn heartbeat(&self, req: HeartbeatParams) -> Result<Value> {
         let v = self.logic.heartbeat(req, self.port).await?;
         Ok(v)
@@ -166,21 +217,25 @@ impl Comet {
         Ok(ret)
     }
 
-    pub async fn handle(&self, msg: MsgReqKind) -> Value {
+    pub async fn job_log(&self, req: JobLogParams) -> Result<Value> {
+        let ret = self.logic.job_log(req).await?;
+        Ok(ret)
+    }
+
+    pub async fn handle(&self, msg: MsgReqKind) -> Result<Value, MsgError> {
         match msg {
             MsgReqKind::PullJobRequest(v) => self.pull_job(v).await,
             MsgReqKind::HeartbeatRequest(v) => self.heartbeat(v).await,
             MsgReqKind::UpdateJobRequest(v) => self.update_job(v).await,
-            _ => todo!(),
+            MsgReqKind::JobLogRequest(v) => self.job_log(v).await,
+            v => {
+                warn!("unsupported request {}", v.name());
+                return Err(MsgError::unsupported(&v));
+            }
         }
-        .map_or_else(
-            |e| {
-                error!("failed handle msg - {e}");
-                json!({
-                    "error":e.to_string()
-                })
-            },
-            |v| v,
-        )
+        .map_err(|e| {
+            error!("failed handle msg - {e}");
+            MsgError::internal(e)
+        })
     }
 }
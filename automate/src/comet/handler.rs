@@ -30,7 +30,7 @@ use tokio::{
 use tracing::error;
 
 use crate::{
-    bridge::client::WsClient,
+    bridge::{client::WsClient, msg::MsgError},
     comet::{
         types::{self, SshLoginParams},
         Comet,
@@ -158,7 +158,7 @@ pub fn ws(
         let mut client: WsClient<
             SplitSink<WebSocketStream, Message>,
             SplitStream<WebSocketStream>,
-        > = WsClient::new(Some(bridge.clone()));
+        > = WsClient::new(Some(bridge.clone())).set_compress_threshold(comet.compress_threshold);
 
         client.set_rw(sink, stream);
 
@@ -178,6 +178,7 @@ pub fn ws(
                 namespace.clone(),
                 agent_ip.clone(),
                 client.sender(),
+                client.get_peer(),
             )
             .await;
 
@@ -210,6 +211,7 @@ async fn upload_file(file: Option<UploadFile>) -> Result<Option<UploadFile>> {
     Ok(Some(UploadFile {
         filename: file.filename,
         data: None,
+        transfer_id: None,
     }))
 }
 
@@ -260,7 +262,7 @@ pub async fn dispatch(
         Ok(v) => {
             return_response!(json:v);
         }
-        Err(e) => return_response!(code: 50000, e.to_string()),
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
     }
 }
 
@@ -274,7 +276,7 @@ pub async fn runtime_action(
         Ok(v) => {
             return_response!(json:v);
         }
-        Err(e) => return_response!(code: 50000, e.to_string()),
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
     }
 }
 
@@ -355,7 +357,7 @@ pub async fn sftp_read_dir(
         Ok(v) => {
             return_response!(json:v);
         }
-        Err(e) => return_response!(code: 50000, e.to_string()),
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
     }
 }
 
@@ -369,7 +371,7 @@ pub async fn sftp_upload(
         Ok(v) => {
             return_response!(json:v);
         }
-        Err(e) => return_response!(code: 50000, e.to_string()),
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
     }
 }
 
@@ -383,7 +385,7 @@ pub async fn sftp_download(
         Ok(v) => {
             return_response!(json:v);
         }
-        Err(e) => return_response!(code: 50000, e.to_string()),
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
     }
 }
 
@@ -397,6 +399,90 @@ pub async fn sftp_remove(
         Ok(v) => {
             return_response!(json:v);
         }
-        Err(e) => return_response!(code: 50000, e.to_string()),
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn read_output(
+    comet: Data<&Comet>,
+    Json(req): Json<types::ReadOutputRequest>,
+) -> Json<serde_json::Value> {
+    let ret = comet.read_output(req).await;
+    match ret {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn list_runtime_state(
+    comet: Data<&Comet>,
+    Json(req): Json<types::ListRuntimeStateRequest>,
+) -> Json<serde_json::Value> {
+    let ret = comet.list_runtime_state(req).await;
+    match ret {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn transfer_chunk(
+    comet: Data<&Comet>,
+    Json(req): Json<types::TransferChunkRequest>,
+) -> Json<serde_json::Value> {
+    let ret = comet.transfer_chunk(req).await;
+    match ret {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn transfer_status(
+    comet: Data<&Comet>,
+    Json(req): Json<types::TransferRequest>,
+) -> Json<serde_json::Value> {
+    let ret = comet.transfer_status(req).await;
+    match ret {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn transfer_read(
+    comet: Data<&Comet>,
+    Json(req): Json<types::TransferReadRequest>,
+) -> Json<serde_json::Value> {
+    let ret = comet.transfer_read(req).await;
+    match ret {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn transfer_remove(
+    comet: Data<&Comet>,
+    Json(req): Json<types::TransferRequest>,
+) -> Json<serde_json::Value> {
+    let ret = comet.transfer_remove(req).await;
+    match ret {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: MsgError::code_of(&e), e.to_string()),
     }
 }
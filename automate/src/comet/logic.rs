@@ -2,7 +2,8 @@ use std::net::IpAddr;
 
 use crate::{
     bridge::msg::{
-        AgentOfflineParams, AgentOnlineParams, HeartbeatParams, MsgReqKind, UpdateJobParams,
+        AgentOfflineParams, AgentOnlineParams, HeartbeatParams, JobLogParams, MsgReqKind,
+        UpdateJobParams,
     },
     bus::Bus,
     get_endpoint, LinkPair,
@@ -110,6 +111,51 @@ impl Logic {
         Ok((key, msg))
     }
 
+    pub async fn read_output(&self, req: types::ReadOutputRequest) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.namespace, &req.agent_ip).await?;
+        Ok((pair.0, MsgReqKind::ReadOutputRequest(req.params)))
+    }
+
+    pub async fn list_runtime_state(
+        &self,
+        req: types::ListRuntimeStateRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.namespace, &req.agent_ip).await?;
+        Ok((pair.0, MsgReqKind::ListRuntimeStateRequest(req.params)))
+    }
+
+    pub async fn transfer_chunk(
+        &self,
+        req: types::TransferChunkRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.namespace, &req.agent_ip).await?;
+        Ok((pair.0, MsgReqKind::TransferChunkRequest(req.params)))
+    }
+
+    pub async fn transfer_status(
+        &self,
+        req: types::TransferRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.namespace, &req.agent_ip).await?;
+        Ok((pair.0, MsgReqKind::TransferStatusRequest(req.params)))
+    }
+
+    pub async fn transfer_read(
+        &self,
+        req: types::TransferReadRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.namespace, &req.agent_ip).await?;
+        Ok((pair.0, MsgReqKind::TransferReadRequest(req.params)))
+    }
+
+    pub async fn transfer_remove(
+        &self,
+        req: types::TransferRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.namespace, &req.agent_ip).await?;
+        Ok((pair.0, MsgReqKind::TransferRemoveRequest(req.params)))
+    }
+
     pub async fn runtime_action(
         &self,
         req: types::RuntimeActionRequest,
@@ -123,6 +169,11 @@ impl Logic {
         Ok(json!(null))
     }
 
+    pub async fn job_log(&self, req: JobLogParams) -> Result<Value> {
+        self.bus.job_log(req).await?;
+        Ok(json!(null))
+    }
+
     pub async fn agent_online(&self, req: AgentOnlineParams) -> Result<Value> {
         self.bus.agent_online(req).await?;
         Ok(json!(null))
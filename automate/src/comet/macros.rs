@@ -14,21 +14,21 @@
 macro_rules! return_response {
     () => {
         return poem::web::Json(serde_json::json!({
-            "code":20000,
+            "code":$crate::code::SUCCESS,
             "data":null,
             "msg":"success",
         }))
     };
     ($data:expr) => {
         return poem::web::Json(serde_json::json!({
-            "code":20000,
+            "code":$crate::code::SUCCESS,
             "data":Some($data),
             "msg":"success",
         }))
     };
     ($data:expr,$msg:expr) => {
         return poem::web::Json(serde_json::json!({
-            "code":20000,
+            "code":$crate::code::SUCCESS,
             "data":Some($data),
             "msg":$msg,
         }))
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
 
 use crate::bridge::msg::{
-    DispatchJobParams, RuntimeActionParams, SftpDownloadParams, SftpReadDirParams,
-    SftpRemoveParams, SftpUploadParams,
+    DispatchJobParams, ListRuntimeStateParams, ReadOutputParams, RuntimeActionParams,
+    SftpDownloadParams, SftpReadDirParams, SftpRemoveParams, SftpUploadParams, TransferChunkParams,
+    TransferParams, TransferReadParams,
 };
 use redis_macros::{FromRedisValue, ToRedisArgs};
 use serde_repr::*;
@@ -49,6 +50,41 @@ pub struct SftpDownloadRequest {
     pub params: SftpDownloadParams,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReadOutputRequest {
+    pub agent_ip: String,
+    pub namespace: String,
+    pub params: ReadOutputParams,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListRuntimeStateRequest {
+    pub agent_ip: String,
+    pub namespace: String,
+    pub params: ListRuntimeStateParams,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferChunkRequest {
+    pub agent_ip: String,
+    pub namespace: String,
+    pub params: TransferChunkParams,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferRequest {
+    pub agent_ip: String,
+    pub namespace: String,
+    pub params: TransferParams,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferReadRequest {
+    pub agent_ip: String,
+    pub namespace: String,
+    pub params: TransferReadParams,
+}
+
 #[derive(Serialize, Clone, FromRedisValue, Deserialize, ToRedisArgs)]
 pub struct LinkPair {
     pub comet_addr: String,
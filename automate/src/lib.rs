@@ -4,14 +4,16 @@ use std::net::IpAddr;
 use std::sync::OnceLock;
 
 pub mod bridge;
+pub mod code;
 pub mod comet;
 pub mod scheduler;
 pub mod ssh;
 pub use bridge::msg::DispatchJobParams;
 pub use comet::logic::Logic;
 pub use comet::types::{
-    DispatchJobRequest, LinkPair, SftpDownloadRequest, SftpReadDirRequest, SftpRemoveRequest,
-    SftpUploadRequest,
+    DispatchJobRequest, LinkPair, ListRuntimeStateRequest, ReadOutputRequest, SftpDownloadRequest,
+    SftpReadDirRequest, SftpRemoveRequest, SftpUploadRequest, TransferChunkRequest,
+    TransferReadRequest, TransferRequest,
 };
 use reqwest::Client;
 pub use scheduler::types::BaseJob;
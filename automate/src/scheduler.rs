@@ -1,7 +1,11 @@
 mod cmd;
 pub(self) mod executor;
 pub(self) mod file;
+mod limits;
+mod outbox;
 pub mod scheduler;
+mod store;
+pub mod timer;
 pub mod types;
 
 pub use scheduler::*;
@@ -1,21 +1,134 @@
-use std::{ffi::OsStr, process::Output, time::Duration};
+use std::{collections::VecDeque, ffi::OsStr, path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::{anyhow, Result};
 use bytes::BufMut;
 
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::{setgid, setgroups, setuid, Gid, Pid, Uid},
+};
 use tokio::{
+    fs::{create_dir_all, File},
     io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt},
-    process::Command,
+    process::{Child, Command},
     sync::mpsc::{Receiver, UnboundedSender},
 };
-use tracing::error;
+use tracing::{debug, error};
+use users::os::unix::UserExt;
+
+use super::{
+    limits::{self, Cgroup},
+    types::{ExitReason, JobOutput, ResourceLimits, SECRET_MASK},
+};
+
+/// Keep the head and the tail of an output stream within the limit,
+/// the full output is spilled to a file once the limit is exceeded
+struct OutputCapture {
+    limit: u64,
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    size: u64,
+    spill_path: Option<PathBuf>,
+    spill_file: Option<(PathBuf, File)>,
+}
+
+impl OutputCapture {
+    fn new(limit: u64, spill_path: Option<PathBuf>) -> Self {
+        Self {
+            limit,
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            size: 0,
+            spill_path,
+            spill_file: None,
+        }
+    }
+
+    async fn push(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+
+        if let Some((path, file)) = self.spill_file.as_mut() {
+            if let Err(e) = file.write_all(data).await {
+                error!("failed write output to {} - {e}", path.display());
+                self.spill_file = None;
+            }
+        }
+
+        if self.limit == 0 {
+            self.head.extend_from_slice(data);
+            return;
+        }
+
+        let head_limit = (self.limit / 2) as usize;
+        let tail_limit = (self.limit - self.limit / 2) as usize;
+
+        let n = head_limit.saturating_sub(self.head.len()).min(data.len());
+        self.head.extend_from_slice(&data[..n]);
+        let data = &data[n..];
+
+        if self.tail.len() + data.len() > tail_limit {
+            if let Some(path) = self.spill_path.take() {
+                match self.spill(&path, data).await {
+                    Ok(file) => self.spill_file = Some((path, file)),
+                    Err(e) => error!("failed spill output to {} - {e}", path.display()),
+                }
+            }
+        }
+
+        self.tail.extend(data);
+        let overflow = self.tail.len().saturating_sub(tail_limit);
+        self.tail.drain(..overflow);
+    }
+
+    /// write everything captured so far and the incoming data to the spill file
+    async fn spill(&self, path: &PathBuf, data: &[u8]) -> std::io::Result<File> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).await?;
+        }
+        let mut file = File::create(path).await?;
+        let (front, back) = self.tail.as_slices();
+        for v in [self.head.as_slice(), front, back, data] {
+            file.write_all(v).await?;
+        }
+        Ok(file)
+    }
+
+    async fn finish(self) -> (Vec<u8>, u64, Option<String>) {
+        let truncated = self.size - (self.head.len() + self.tail.len()) as u64;
+        let mut data = self.head;
+        if truncated > 0 {
+            data.put(format!("\n... [truncated {truncated} bytes] ...\n").as_bytes());
+        }
+        data.extend(self.tail);
+
+        let spill_file = match self.spill_file {
+            Some((path, mut file)) => match file.flush().await {
+                Ok(_) => Some(path.to_string_lossy().to_string()),
+                Err(e) => {
+                    error!("failed flush output to {} - {e}", path.display());
+                    None
+                }
+            },
+            None => None,
+        };
+        (data, self.size, spill_file)
+    }
+}
+
+fn mask_line(line: String, masks: &[String]) -> String {
+    masks
+        .iter()
+        .filter(|v| !v.is_empty())
+        .fold(line, |line, v| line.replace(v.as_str(), SECRET_MASK))
+}
 
-async fn read_to_end<A: AsyncRead + Unpin>(
-    io: &mut Option<A>,
+async fn read_to_capture<A: AsyncRead + Unpin>(
+    io: Option<A>,
     tx: UnboundedSender<String>,
-) -> std::io::Result<Vec<u8>> {
-    let mut vec = Vec::new();
-    if let Some(io) = io.as_mut() {
+    mut capture: OutputCapture,
+    masks: Arc<Vec<String>>,
+) -> std::io::Result<OutputCapture> {
+    if let Some(io) = io {
         let mut reader = tokio::io::BufReader::new(io);
         loop {
             let mut line = String::new();
@@ -25,20 +138,44 @@ async fn read_to_end<A: AsyncRead + Unpin>(
                 break;
             }
 
-            if let Err(e) = tx.send(line.clone()) {
+            let line = mask_line(line, &masks);
+            capture.push(line.as_bytes()).await;
+
+            if let Err(e) = tx.send(line) {
                 error!("failed send job lot - {e}");
             }
-
-            vec.put(line.as_bytes());
         }
     }
 
-    std::result::Result::Ok(vec)
+    std::result::Result::Ok(capture)
 }
 
+/// Kills the process group if the run is dropped before the job exits,
+/// e.g. the sync exec is aborted by the peer
+struct ProcessGroupGuard(Option<Pid>);
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        if let Some(pgid) = self.0 {
+            let _ = killpg(pgid, Signal::SIGKILL);
+        }
+    }
+}
+
+const ROOT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+const USER_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
 pub struct Cmd<'a> {
     inner: Command,
     timeout: Option<Duration>,
+    disable_timeout: bool,
+    grace_period: Duration,
+    resource_limits: ResourceLimits,
+    output_limit: u64,
+    spill_path: Option<(PathBuf, PathBuf)>,
+    masks: Vec<String>,
+    /// uid, primary gid and supplementary gids switched to in the child before exec
+    user: Option<(Uid, Gid, Vec<Gid>)>,
     read_code_from_stdin: (bool, &'a str),
 }
 
@@ -48,6 +185,13 @@ impl<'a> Cmd<'a> {
             inner: Command::new(program),
             read_code_from_stdin: (false, ""),
             timeout: None,
+            disable_timeout: false,
+            grace_period: Duration::from_secs(10),
+            resource_limits: ResourceLimits::default(),
+            output_limit: 0,
+            spill_path: None,
+            masks: Vec::new(),
+            user: None,
         }
     }
 
@@ -65,9 +209,71 @@ impl<'a> Cmd<'a> {
         self
     }
 
-    pub fn work_user(&mut self, user: &str) -> Result<&mut Self> {
+    /// never kill the process because of timeout, used by long-running process
+    pub fn disable_timeout(&mut self) -> &mut Self {
+        self.disable_timeout = true;
+        self
+    }
+
+    /// time to wait after SIGTERM before the process group is killed by SIGKILL
+    pub fn grace_period(&mut self, secs: u64) -> &mut Self {
+        self.grace_period = Duration::from_secs(secs);
+        self
+    }
+
+    pub fn resource_limits(&mut self, limits: ResourceLimits) -> &mut Self {
+        self.resource_limits = limits;
+        self
+    }
+
+    /// bytes of stdout and stderr kept each, 0 means no limit
+    pub fn output_limit(&mut self, limit: u64) -> &mut Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// values replaced in the output, such as secrets
+    pub fn mask(&mut self, masks: Vec<String>) -> &mut Self {
+        self.masks = masks;
+        self
+    }
+
+    /// files to keep the full stdout and stderr once they exceed the output limit
+    pub fn spill_to(&mut self, stdout: PathBuf, stderr: PathBuf) -> &mut Self {
+        self.spill_path = Some((stdout, stderr));
+        self
+    }
+
+    /// run as the user with its primary group and supplementary groups,
+    /// HOME, USER, LOGNAME and SHELL are taken from passwd,
+    /// login_env clears the environment of the agent like a login shell does
+    pub fn work_user(&mut self, user: &str, login_env: bool) -> Result<&mut Self> {
         let u = users::get_user_by_name(user).ok_or(anyhow!("invalid system user {user}"))?;
-        self.inner.uid(u.uid());
+        let uid = Uid::from_raw(u.uid());
+        let gid = Gid::from_raw(u.primary_group_id());
+        // resolve the groups like initgroups does in the parent, nss lookup is not safe after fork
+        let groups: Vec<Gid> = users::get_user_groups(u.name(), u.primary_group_id())
+            .unwrap_or_default()
+            .iter()
+            .map(|v| Gid::from_raw(v.gid()))
+            .collect();
+
+        if login_env {
+            self.inner.env_clear();
+            for key in ["TERM", "LANG"] {
+                if let Ok(val) = std::env::var(key) {
+                    self.inner.env(key, val);
+                }
+            }
+            self.inner
+                .env("PATH", if uid.is_root() { ROOT_PATH } else { USER_PATH });
+        }
+        self.inner
+            .env("HOME", u.home_dir())
+            .env("USER", u.name())
+            .env("LOGNAME", u.name())
+            .env("SHELL", u.shell());
+        self.user = Some((uid, gid, groups));
         Ok(self)
     }
 
@@ -76,12 +282,83 @@ impl<'a> Cmd<'a> {
         self
     }
 
+    /// send SIGTERM to the process group, then SIGKILL if it is still alive after the grace period
+    async fn terminate(&self, child: &mut Child) -> Result<()> {
+        let Some(pid) = child.id() else {
+            // already reaped
+            return Ok(());
+        };
+        let pgid = Pid::from_raw(pid as i32);
+
+        if let Err(e) = killpg(pgid, Signal::SIGTERM) {
+            debug!("failed send SIGTERM to process group {pid} - {e}");
+        }
+
+        if tokio::time::timeout(self.grace_period, child.wait())
+            .await
+            .is_ok()
+        {
+            // kill the remaining processes of the group which ignored SIGTERM
+            let _ = killpg(pgid, Signal::SIGKILL);
+            return Ok(());
+        }
+
+        if let Err(e) = killpg(pgid, Signal::SIGKILL) {
+            debug!("failed send SIGKILL to process group {pid} - {e}");
+        }
+        child.kill().await?;
+        Ok(())
+    }
+
     pub async fn wait_with_output(
         &mut self,
         tx: UnboundedSender<String>,
         mut kill_signal_rx: Receiver<()>,
-    ) -> Result<Output> {
-        let mut child = self.inner.spawn()?;
+    ) -> Result<JobOutput> {
+        let cgroup = Cgroup::create(&nanoid::nanoid!(10), &self.resource_limits).await;
+        let procs_path = match cgroup.as_ref().map(Cgroup::procs_path).transpose() {
+            Ok(v) => v,
+            Err(e) => {
+                if let Some(cgroup) = cgroup {
+                    cgroup.cleanup().await;
+                }
+                return Err(e);
+            }
+        };
+        let rlimits = limits::rlimits(&self.resource_limits, cgroup.is_some());
+        let user = self.user.clone();
+
+        if procs_path.is_some() || !rlimits.is_empty() || user.is_some() {
+            // SAFETY: only async-signal-safe syscalls are called in the child before exec,
+            // the cgroup is joined before the privileges are dropped
+            unsafe {
+                self.inner.pre_exec(move || {
+                    if let Some(ref path) = procs_path {
+                        limits::join_cgroup(path)?;
+                    }
+                    limits::set_rlimits(&rlimits)?;
+                    if let Some((uid, gid, ref groups)) = user {
+                        setgroups(groups)?;
+                        setgid(gid)?;
+                        setuid(uid)?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        // run in a new process group, so that the processes started by the job are killed together,
+        // also when the run is dropped, the processes which left the group are killed by the cgroup
+        let mut child = match self.inner.process_group(0).kill_on_drop(true).spawn() {
+            Ok(v) => v,
+            Err(e) => {
+                if let Some(cgroup) = cgroup {
+                    cgroup.cleanup().await;
+                }
+                return Err(e.into());
+            }
+        };
+        let mut group_guard = ProcessGroupGuard(child.id().map(|v| Pid::from_raw(v as i32)));
 
         if self.read_code_from_stdin.0 {
             if let Some(mut stdin_pipe) = child.stdin.take() {
@@ -91,11 +368,22 @@ impl<'a> Cmd<'a> {
             }
         }
 
-        let mut stdout_pipe = child.stdout.take();
-        let mut stderr_pipe = child.stderr.take();
-
-        let stdout_fut = read_to_end(&mut stdout_pipe, tx.clone());
-        let stderr_fut = read_to_end(&mut stderr_pipe, tx.clone());
+        // drain the pipes while the process is running, so that a chatty process
+        // never blocks on a full pipe and its lines are forwarded as they come
+        let (stdout_spill, stderr_spill) = self.spill_path.clone().unzip();
+        let masks = Arc::new(self.masks.clone());
+        let stdout_handle = tokio::spawn(read_to_capture(
+            child.stdout.take(),
+            tx.clone(),
+            OutputCapture::new(self.output_limit, stdout_spill),
+            masks.clone(),
+        ));
+        let stderr_handle = tokio::spawn(read_to_capture(
+            child.stderr.take(),
+            tx.clone(),
+            OutputCapture::new(self.output_limit, stderr_spill),
+            masks,
+        ));
 
         let sleep = self
             .timeout
@@ -103,27 +391,58 @@ impl<'a> Cmd<'a> {
                 tokio::time::sleep(v)
             });
         tokio::pin!(sleep);
+        let disable_timeout = self.disable_timeout;
 
-        tokio::select! {
-            _ = &mut sleep => child.kill().await?,
-            _ = kill_signal_rx.recv() => child.kill().await?,
+        let exit_reason = tokio::select! {
+            _ = &mut sleep, if !disable_timeout => ExitReason::Timeout,
+            _ = kill_signal_rx.recv() => ExitReason::Killed,
             ret = child.wait() =>{
                 ret?;
+                ExitReason::Exited
             },
-
         };
 
-        let (status, stdout, stderr) =
-            futures_util::future::try_join3(child.wait(), stdout_fut, stderr_fut).await?;
+        if exit_reason != ExitReason::Exited {
+            self.terminate(&mut child).await?;
+        }
 
-        // Drop happens after `try_join` due to <https://github.com/tokio-rs/tokio/issues/4309>
-        drop(stdout_pipe);
-        drop(stderr_pipe);
+        let status = child.wait().await?;
+        group_guard.0 = None;
 
-        Ok(Output {
+        let exit_reason = match cgroup {
+            Some(cgroup) => {
+                let oom_killed = !status.success() && cgroup.is_oom_killed().await;
+                // kill the processes left by the job, so that the pipes are closed
+                cgroup.cleanup().await;
+                if oom_killed {
+                    ExitReason::OutOfMemory
+                } else {
+                    exit_reason
+                }
+            }
+            None => exit_reason,
+        };
+        let (stdout, stdout_size, stdout_file) = stdout_handle.await??.finish().await;
+        let (stderr, stderr_size, stderr_file) = stderr_handle.await??.finish().await;
+
+        Ok(JobOutput {
             status,
-            stderr,
+            exit_reason,
             stdout,
+            stderr,
+            stdout_size,
+            stderr_size,
+            stdout_file,
+            stderr_file,
         })
     }
 }
+
+#[test]
+fn test_mask_line() {
+    let masks = vec!["s3cret".to_string(), "".to_string()];
+    assert_eq!(
+        mask_line("password=s3cret\n".to_string(), &masks),
+        format!("password={SECRET_MASK}\n")
+    );
+}
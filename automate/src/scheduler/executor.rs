@@ -4,26 +4,34 @@ use file_rotate::{compression::Compression, suffix::AppendCount, FileRotate};
 use std::io::Write;
 
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::{
-    collections::HashMap,
-    process::{Output, Stdio},
+    collections::{HashMap, HashSet},
+    process::Stdio,
 };
-use tokio::sync::mpsc::Receiver;
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
 
 use tokio::sync::{mpsc, Mutex};
 use tracing::error;
 
 use crate::scheduler::cmd::Cmd;
 
-use super::types::{BaseJob, BundleOutput};
+use super::types::{BaseJob, BundleEntryOutput, BundleMode, BundleOutput, BundleScript, JobOutput};
+
+/// spilled output files kept for each job
+const OUTPUT_FILE_KEEP: usize = 40;
 
 #[derive(Default)]
 pub struct ExecutorBuilder {
     pub job: BaseJob,
     output_dir: String,
     disable_log: bool,
+    disable_timeout: bool,
     pub env: HashMap<String, String>,
+    secrets: Vec<String>,
 }
 
 #[allow(unused)]
@@ -50,30 +58,48 @@ impl ExecutorBuilder {
         self
     }
 
+    pub fn disable_timeout(mut self, disable: bool) -> Self {
+        self.disable_timeout = disable;
+        self
+    }
+
     pub fn env(mut self, k: String, v: String) -> Self {
         self.env.insert(k, v);
         self
     }
 
+    /// set the env and mask its value in the output
+    pub fn secret(mut self, k: String, v: String) -> Self {
+        self.secrets.push(v.clone());
+        self.env.insert(k, v);
+        self
+    }
+
     pub fn build(self) -> Executor {
         Executor {
             job: self.job,
             output_dir: self.output_dir,
             env: self.env,
+            secrets: self.secrets,
             disable_log: self.disable_log,
+            disable_timeout: self.disable_timeout,
         }
     }
 }
 
 pub struct Ctx {
     pub kill_signal_rx: Receiver<()>,
+    /// receive every output line of the running process
+    pub log_tx: Option<UnboundedSender<String>>,
 }
 
 pub struct Executor {
     job: BaseJob,
     output_dir: String,
     disable_log: bool,
+    disable_timeout: bool,
     env: HashMap<String, String>,
+    secrets: Vec<String>,
 }
 
 impl Executor {
@@ -85,6 +111,11 @@ impl Executor {
         PathBuf::from(&self.output_dir).join(format!("{}.log", self.job.eid))
     }
 
+    /// directory of the full output files of the job which exceed max_output_size
+    pub fn get_output_file_dir(&self) -> PathBuf {
+        output_file_root(&self.output_dir).join(&self.job.eid)
+    }
+
     pub async fn run(&self, mut ctx: Ctx) -> Result<BundleOutput> {
         if self.job.bundle_script.is_none() {
             let output = self
@@ -99,13 +130,29 @@ impl Executor {
             return Ok(BundleOutput::Output(output));
         }
 
+        let mut pending = self.job.bundle_script.clone().unwrap();
+        let dag = self.job.bundle_mode == BundleMode::Dag;
+        if dag {
+            check_dag(&pending)?;
+        }
+        let limit = match self.job.bundle_mode {
+            BundleMode::Sequential => 1,
+            _ if self.job.bundle_concurrency == 0 => pending.len().max(1),
+            _ => self.job.bundle_concurrency as usize,
+        };
+
+        let log_tx = ctx.log_tx.take();
         let kill_signal_tx: Arc<Mutex<Vec<mpsc::Sender<()>>>> = Arc::new(Mutex::new(vec![]));
         let kill_signal_tx_clone = kill_signal_tx.clone();
-        let mut outputs = HashMap::new();
+        let killed = Arc::new(AtomicBool::new(false));
+        let killed_clone = killed.clone();
+        let mut outputs: HashMap<String, BundleEntryOutput> = HashMap::new();
 
         let handler = tokio::spawn(async move {
             match ctx.kill_signal_rx.recv().await {
                 Some(v) => {
+                    // entries not started yet check it after registering their sender
+                    killed_clone.store(true, Ordering::SeqCst);
                     for s in kill_signal_tx_clone.lock().await.to_vec() {
                         if let Err(e) = s.send(v).await {
                             error!("failed to send kill singal {e}");
@@ -118,31 +165,86 @@ impl Executor {
             };
         });
 
-        for v in self.job.bundle_script.clone().unwrap().clone().into_iter() {
-            let (tx, kill_signal_rx) = mpsc::channel::<()>(1);
-            kill_signal_tx.lock().await.push(tx);
-            let output = self
-                .exec(
-                    Ctx { kill_signal_rx },
-                    v.cmd_name.clone(),
-                    v.args.clone(),
-                    v.code.clone(),
-                )
-                .await?;
-            outputs.insert(v.eid, output);
+        let mut running = FuturesUnordered::new();
+        loop {
+            let mut i = 0;
+            while i < pending.len() && running.len() < limit {
+                let deps: &[String] = if dag { &pending[i].depends_on } else { &[] };
+                if deps.iter().any(|v| !outputs.contains_key(v)) {
+                    i += 1;
+                    continue;
+                }
+
+                let v = pending.remove(i);
+                if let Some(dep) = deps_failed(&v, dag, &outputs) {
+                    let msg = format!("skipped, dependency {dep} failed");
+                    outputs.insert(v.eid, BundleEntryOutput::Skipped(msg));
+                    // the skipped entry may be the dependency of a previous one
+                    i = 0;
+                    continue;
+                }
+                running.push(self.exec_entry(
+                    v,
+                    log_tx.clone(),
+                    kill_signal_tx.clone(),
+                    killed.clone(),
+                ));
+            }
+
+            match running.next().await {
+                Some((eid, output)) => {
+                    outputs.insert(eid, output);
+                }
+                None => break,
+            }
         }
 
         handler.abort();
         return Ok(BundleOutput::Bundle(outputs));
     }
 
+    /// run an entry of a bundle, the error is kept in the output of the entry
+    async fn exec_entry(
+        &self,
+        v: BundleScript,
+        log_tx: Option<UnboundedSender<String>>,
+        kill_signal_tx: Arc<Mutex<Vec<mpsc::Sender<()>>>>,
+        killed: Arc<AtomicBool>,
+    ) -> (String, BundleEntryOutput) {
+        let (tx, kill_signal_rx) = mpsc::channel::<()>(1);
+        kill_signal_tx.lock().await.push(tx);
+        if killed.load(Ordering::SeqCst) {
+            return (
+                v.eid,
+                BundleEntryOutput::Skipped("skipped, the job is killed".to_string()),
+            );
+        }
+
+        let ret = self
+            .exec(
+                Ctx {
+                    kill_signal_rx,
+                    log_tx,
+                },
+                v.cmd_name,
+                v.args,
+                v.code,
+            )
+            .await;
+        let output = match ret {
+            Ok(v) => BundleEntryOutput::Output(v),
+            Err(e) => BundleEntryOutput::Failed(e.to_string()),
+        };
+        (v.eid, output)
+    }
+
     async fn exec(
         &self,
         ctx: Ctx,
         cmd_name: String,
         args: Vec<String>,
         code: String,
-    ) -> Result<Output> {
+    ) -> Result<JobOutput> {
         let mut cmd = Cmd::new(cmd_name);
         let mut args = args;
         if self.job.read_code_from_stdin {
@@ -157,18 +259,37 @@ impl Executor {
         }
 
         if let Some(ref work_user) = self.job.work_user {
-            cmd.work_user(work_user)?;
+            cmd.work_user(work_user, self.job.login_env)?;
         }
-        if self.job.timeout > 0 {
+        if self.disable_timeout {
+            cmd.disable_timeout();
+        } else if self.job.timeout > 0 {
             cmd.timeout(self.job.timeout);
         }
+        cmd.grace_period(self.job.kill_grace_period)
+            .resource_limits(self.job.resource_limits.clone());
 
         for (key, val) in self.env.iter() {
             cmd.get_ref().env(key, val);
         }
+        cmd.mask(self.secrets.clone());
 
         cmd.get_ref().args(&args);
 
+        if self.job.max_output_size > 0 {
+            let output_file_dir = self.get_output_file_dir();
+            let name = format!(
+                "{}-{}",
+                chrono::Local::now().format("%Y%m%d%H%M%S"),
+                nanoid::nanoid!(6)
+            );
+            cmd.output_limit(self.job.max_output_size).spill_to(
+                output_file_dir.join(format!("{name}.stdout")),
+                output_file_dir.join(format!("{name}.stderr")),
+            );
+            prune_output_files(output_file_dir).await;
+        }
+
         let (tx, mut rx) = mpsc::unbounded_channel::<String>();
 
         let filepath = self.get_log_file_path();
@@ -184,6 +305,7 @@ impl Executor {
             ))
         };
 
+        let log_tx = ctx.log_tx;
         tokio::spawn(async move {
             while let Some(line) = rx.recv().await {
                 if let Some(f) = logfile.as_mut() {
@@ -191,6 +313,9 @@ impl Executor {
                         error!("cannot write to log file - {e}");
                     }
                 }
+                if let Some(ref log_tx) = log_tx {
+                    let _ = log_tx.send(line);
+                }
             }
         });
 
@@ -203,6 +328,72 @@ impl Executor {
     }
 }
 
+pub fn output_file_root(output_dir: &str) -> PathBuf {
+    PathBuf::from(output_dir).join("output")
+}
+
+/// remove the oldest output files, file names start with the creation time
+async fn prune_output_files(dir: PathBuf) {
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let mut files = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        files.push(entry.path());
+    }
+    if files.len() <= OUTPUT_FILE_KEEP {
+        return;
+    }
+    files.sort();
+    for v in &files[..files.len() - OUTPUT_FILE_KEEP] {
+        if let Err(e) = tokio::fs::remove_file(v).await {
+            error!("failed remove output file {} - {e}", v.display());
+        }
+    }
+}
+
+/// every dependency must be an entry of the bundle and there must be no cycle
+fn check_dag(list: &[BundleScript]) -> Result<()> {
+    let mut done: HashSet<&str> = HashSet::new();
+    while done.len() < list.len() {
+        let ready: Vec<&str> = list
+            .iter()
+            .filter(|v| !done.contains(v.eid.as_str()))
+            .filter(|v| v.depends_on.iter().all(|d| done.contains(d.as_str())))
+            .map(|v| v.eid.as_str())
+            .collect();
+        if ready.is_empty() {
+            let eid: Vec<&str> = list
+                .iter()
+                .filter(|v| !done.contains(v.eid.as_str()))
+                .map(|v| v.eid.as_str())
+                .collect();
+            anyhow::bail!(
+                "invalid bundle dependencies, unknown or cyclic among {}",
+                eid.join(", ")
+            );
+        }
+        done.extend(ready);
+    }
+    Ok(())
+}
+
+/// the first dependency of the entry which did not succeed
+fn deps_failed<'a>(
+    v: &'a BundleScript,
+    dag: bool,
+    outputs: &HashMap<String, BundleEntryOutput>,
+) -> Option<&'a str> {
+    if !dag {
+        return None;
+    }
+    v.depends_on
+        .iter()
+        .find(|d| outputs.get(d.as_str()).is_some_and(|o| !o.is_success()))
+        .map(|d| d.as_str())
+}
+
 #[tokio::test]
 async fn test_command_exec() {
     use nanoid::nanoid;
@@ -220,10 +411,30 @@ async fn test_command_exec() {
             work_user: None,
             max_retry: 1,
             max_parallel: 1,
+            ..Default::default()
         })
         .build();
     let (_kill_sinal_tx, kill_signal_rx) = mpsc::channel::<()>(1);
-    let output = c.run(Ctx { kill_signal_rx }).await.unwrap();
+    let output = c
+        .run(Ctx {
+            kill_signal_rx,
+            log_tx: None,
+        })
+        .await
+        .unwrap();
     println!("stdout: {:?}", output.get_stdout());
     println!("stderr: {:?}", output.get_stderr());
 }
+
+#[test]
+fn test_check_dag() {
+    let entry = |eid: &str, depends_on: &[&str]| BundleScript {
+        eid: eid.to_string(),
+        depends_on: depends_on.iter().map(|v| v.to_string()).collect(),
+        ..Default::default()
+    };
+
+    assert!(check_dag(&[entry("a", &[]), entry("b", &["a"]), entry("c", &["a", "b"])]).is_ok());
+    assert!(check_dag(&[entry("a", &["b"]), entry("b", &["a"])]).is_err());
+    assert!(check_dag(&[entry("a", &["x"])]).is_err());
+}
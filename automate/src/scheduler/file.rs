@@ -1,20 +1,38 @@
 // use crate::get_http_client;
 
 use super::types::UploadFile;
+use crate::bridge::transfer::TransferStore;
 use anyhow::Result;
 use tokio::{
-    fs::{create_dir_all, File},
+    fs::{self, create_dir_all, File},
     io::AsyncWriteExt,
 };
+use tracing::warn;
 
 const UPLOAD_DIR: &str = "/tmp/jiascheduler-agent";
 
-pub async fn try_download_file(_host: String, file: Option<UploadFile>) -> Result<()> {
+pub async fn try_download_file(
+    _host: String,
+    file: Option<UploadFile>,
+    transfer: &TransferStore,
+) -> Result<()> {
     let file = match file {
         Some(v) => v,
         None => return Ok(()),
     };
 
+    // the file is pushed in chunks before the job is dispatched
+    if let Some(transfer_id) = file.transfer_id {
+        let source = transfer.path(&transfer_id)?;
+        if fs::metadata(&source).await.is_err() {
+            warn!("transfer {transfer_id} of {} is not found", file.filename);
+            return Ok(());
+        }
+        create_dir_all(UPLOAD_DIR).await?;
+        fs::copy(source, format!("{}/{}", UPLOAD_DIR, file.filename)).await?;
+        return Ok(());
+    }
+
     let data = if let Some(data) = file.data {
         data
     } else {
@@ -0,0 +1,217 @@
+use std::{
+    ffi::{CStr, CString},
+    io,
+    os::{
+        fd::{FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use nix::{
+    fcntl::{open, OFlag},
+    sys::{
+        resource::{setrlimit, Resource},
+        stat::Mode,
+    },
+    unistd::write,
+};
+use tokio::{fs, runtime::Handle};
+use tracing::{debug, error, warn};
+
+use super::types::ResourceLimits;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CGROUP_NAME: &str = "jiascheduler";
+const CPU_PERIOD: u64 = 100000;
+
+/// A cgroup v2 created for one run of a job, removed by cleanup,
+/// or in the background if the run is dropped before it
+pub struct Cgroup {
+    path: PathBuf,
+    removed: bool,
+}
+
+impl Cgroup {
+    /// create a cgroup with the limits, return none if cgroups v2 is not available
+    pub async fn create(name: &str, limits: &ResourceLimits) -> Option<Self> {
+        if limits.memory_max.is_none() && limits.cpu_quota.is_none() && limits.pids_max.is_none() {
+            return None;
+        }
+
+        let root = Path::new(CGROUP_ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            return None;
+        }
+
+        match Self::try_create(root, name, limits).await {
+            Ok(v) => Some(v),
+            Err(e) => {
+                warn!("cgroups v2 is not available, fallback to setrlimit - {e}");
+                None
+            }
+        }
+    }
+
+    async fn try_create(root: &Path, name: &str, limits: &ResourceLimits) -> Result<Self> {
+        let parent = root.join(CGROUP_NAME);
+        fs::create_dir_all(&parent).await?;
+        for dir in [root, parent.as_path()] {
+            write_file(dir.join("cgroup.subtree_control"), "+memory +cpu +pids").await?;
+        }
+
+        let path = parent.join(name);
+        fs::create_dir_all(&path).await?;
+        let cgroup = Self {
+            path,
+            removed: false,
+        };
+
+        if let Err(e) = cgroup.apply(limits).await {
+            cgroup.cleanup().await;
+            return Err(e);
+        }
+        Ok(cgroup)
+    }
+
+    async fn apply(&self, limits: &ResourceLimits) -> Result<()> {
+        if let Some(v) = limits.memory_max {
+            write_file(self.path.join("memory.max"), &v.to_string()).await?;
+            write_file(self.path.join("memory.swap.max"), "0")
+                .await
+                .unwrap_or_else(|e| debug!("failed disable swap of cgroup - {e}"));
+        }
+        if let Some(v) = limits.cpu_quota {
+            let quota = v as u64 * CPU_PERIOD / 100;
+            write_file(self.path.join("cpu.max"), &format!("{quota} {CPU_PERIOD}")).await?;
+        }
+        if let Some(v) = limits.pids_max {
+            write_file(self.path.join("pids.max"), &v.to_string()).await?;
+        }
+        Ok(())
+    }
+
+    /// path of cgroup.procs, prepared before fork for [join_cgroup]
+    pub fn procs_path(&self) -> Result<CString> {
+        Ok(CString::new(
+            self.path.join("cgroup.procs").as_os_str().as_bytes(),
+        )?)
+    }
+
+    /// whether a process of the cgroup has been killed by the oom killer
+    pub async fn is_oom_killed(&self) -> bool {
+        let Ok(events) = fs::read_to_string(self.path.join("memory.events")).await else {
+            return false;
+        };
+        events
+            .lines()
+            .filter_map(|v| v.strip_prefix("oom_kill "))
+            .any(|v| v.trim().parse::<u64>().is_ok_and(|v| v > 0))
+    }
+
+    /// kill the processes left in the cgroup and remove it
+    pub async fn cleanup(mut self) {
+        self.removed = true;
+        remove_cgroup(&self.path).await;
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        if self.removed {
+            return;
+        }
+        let Ok(handle) = Handle::try_current() else {
+            error!("failed remove cgroup {}, no runtime", self.path.display());
+            return;
+        };
+        let path = self.path.clone();
+        handle.spawn(async move { remove_cgroup(&path).await });
+    }
+}
+
+async fn remove_cgroup(path: &Path) {
+    let _ = write_file(path.join("cgroup.kill"), "1").await;
+
+    for _ in 0..10 {
+        match fs::remove_dir(path).await {
+            Ok(_) => return,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    error!("failed remove cgroup {}", path.display());
+}
+
+async fn write_file(path: PathBuf, content: &str) -> Result<()> {
+    fs::write(&path, content)
+        .await
+        .map_err(|e| anyhow!("failed write {} - {e}", path.display()))
+}
+
+/// move the calling process into the cgroup, called in the child before exec,
+/// so that nothing the job forks or allocates escapes the limits
+pub fn join_cgroup(procs_path: &CStr) -> io::Result<()> {
+    let fd = open(
+        procs_path,
+        OFlag::O_WRONLY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )?;
+    // SAFETY: the fd is just opened and owned here only
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    // 0 is the writing process itself
+    write(&fd, b"0")?;
+    Ok(())
+}
+
+/// rlimits set in the child process before exec, memory falls back to RLIMIT_AS
+/// if it is not enforced by cgroup, pids_max has no fallback because RLIMIT_NPROC
+/// counts every process of the user, it would break the other jobs of a shared user
+pub fn rlimits(limits: &ResourceLimits, with_cgroup: bool) -> Vec<(Resource, u64)> {
+    let mut list = vec![];
+    if let Some(v) = limits.nofile {
+        list.push((Resource::RLIMIT_NOFILE, v));
+    }
+    if let Some(v) = limits.core_size {
+        list.push((Resource::RLIMIT_CORE, v));
+    }
+    if !with_cgroup {
+        if let Some(v) = limits.memory_max {
+            list.push((Resource::RLIMIT_AS, v));
+        }
+        if limits.pids_max.is_some() {
+            warn!("pids_max is not enforced without cgroups v2");
+        }
+    }
+    list
+}
+
+pub fn set_rlimits(list: &[(Resource, u64)]) -> io::Result<()> {
+    for &(resource, limit) in list {
+        setrlimit(resource, limit, limit)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_rlimits_fallback() {
+    let limits = ResourceLimits {
+        memory_max: Some(1 << 30),
+        pids_max: Some(100),
+        nofile: Some(1024),
+        ..Default::default()
+    };
+    assert_eq!(
+        rlimits(&limits, true),
+        vec![(Resource::RLIMIT_NOFILE, 1024)]
+    );
+    assert_eq!(
+        rlimits(&limits, false),
+        vec![
+            (Resource::RLIMIT_NOFILE, 1024),
+            (Resource::RLIMIT_AS, 1 << 30)
+        ]
+    );
+}
@@ -0,0 +1,281 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use tokio::{
+    fs,
+    sync::{Mutex, Notify},
+};
+use tracing::{error, warn};
+
+use crate::bridge::msg::UpdateJobParams;
+
+const OUTBOX_DIR: &str = "outbox";
+/// the updates which keep failing are moved aside into it
+const DEAD_LETTER_DIR: &str = "dead";
+/// max replay attempts of an update before it is moved to the dead letter dir
+const MAX_REPLAY_ATTEMPTS: u32 = 5;
+/// max updates kept in the outbox, the oldest one is dropped once it is full
+const MAX_OUTBOX_LEN: usize = 10000;
+
+/// A durable queue of the job updates which failed to be sent to comet,
+/// every update is saved as a file named by its sequence and replayed in order
+#[derive(Clone)]
+pub struct Outbox {
+    dir: PathBuf,
+    /// the lock also keeps the queue in order
+    queue: Arc<Mutex<Queue>>,
+    notify: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// sequence of the next update
+    next_seq: u64,
+    len: usize,
+    /// failed replay attempts by sequence
+    attempts: HashMap<u64, u32>,
+}
+
+impl Outbox {
+    pub async fn open(output_dir: &str) -> Self {
+        let dir = PathBuf::from(output_dir).join(OUTBOX_DIR);
+        if let Err(e) = fs::create_dir_all(dir.join(DEAD_LETTER_DIR)).await {
+            error!("failed create outbox dir {} - {e}", dir.display());
+        }
+        let outbox = Self {
+            dir,
+            queue: Arc::new(Mutex::new(Queue::default())),
+            notify: Arc::new(Notify::new()),
+        };
+        let list = outbox.list().await;
+        *outbox.queue.lock().await = Queue {
+            next_seq: list.last().map_or(0, |v| v.0 + 1),
+            len: list.len(),
+            attempts: HashMap::new(),
+        };
+        outbox
+    }
+
+    /// sequences and paths of the queued updates in order
+    async fn list(&self) -> Vec<(u64, PathBuf)> {
+        let mut list = vec![];
+        let Ok(mut entries) = fs::read_dir(&self.dir).await else {
+            return list;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|v| v == "json") {
+                if let Some(seq) = path
+                    .file_stem()
+                    .and_then(|v| v.to_str())
+                    .and_then(|v| v.parse::<u64>().ok())
+                {
+                    list.push((seq, path));
+                }
+            }
+        }
+        list.sort_by_key(|v| v.0);
+        list
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.queue.lock().await.len == 0
+    }
+
+    /// append the update to the end of the queue
+    pub async fn push(&self, params: &UpdateJobParams) -> Result<()> {
+        let mut queue = self.queue.lock().await;
+
+        if queue.len >= MAX_OUTBOX_LEN {
+            let list = self.list().await;
+            for (_, path) in &list[..list.len().saturating_sub(MAX_OUTBOX_LEN - 1)] {
+                warn!("outbox is full, drop {}", path.display());
+                let _ = fs::remove_file(path).await;
+            }
+            queue.len = list.len().min(MAX_OUTBOX_LEN - 1);
+        }
+
+        let path = self.dir.join(format!("{:020}.json", queue.next_seq));
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(params)?).await?;
+        fs::rename(&tmp, &path).await?;
+        queue.next_seq += 1;
+        queue.len += 1;
+        Ok(())
+    }
+
+    /// wake up the replay, e.g. after the agent reconnects to comet
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    pub async fn notified(&self) {
+        self.notify.notified().await
+    }
+
+    /// send the queued updates in order and remove the sent ones,
+    /// stop at the first failure so that the rest are kept in order,
+    /// an update failed `MAX_REPLAY_ATTEMPTS` times is moved to the dead letter dir.
+    /// the lock is only held between the sends so that push is not blocked
+    pub async fn replay<F, Fut>(&self, mut send: F) -> Result<usize>
+    where
+        F: FnMut(UpdateJobParams) -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        let mut sent = 0;
+        loop {
+            let list = {
+                let mut queue = self.queue.lock().await;
+                if queue.len == 0 {
+                    return Ok(sent);
+                }
+                let list = self.list().await;
+                queue.len = list.len();
+                list
+            };
+            if list.is_empty() {
+                return Ok(sent);
+            }
+
+            for (seq, path) in list {
+                let Some(params) = self.read(&path).await else {
+                    continue;
+                };
+
+                let ret = send(params).await;
+                let mut queue = self.queue.lock().await;
+                match ret {
+                    Ok(_) => {
+                        queue.attempts.remove(&seq);
+                        if self.remove(&path).await? {
+                            queue.len = queue.len.saturating_sub(1);
+                        }
+                        sent += 1;
+                    }
+                    Err(e) => {
+                        let attempts = queue.attempts.entry(seq).or_default();
+                        *attempts += 1;
+                        if *attempts < MAX_REPLAY_ATTEMPTS {
+                            return Err(e);
+                        }
+                        queue.attempts.remove(&seq);
+
+                        let dead = self
+                            .dir
+                            .join(DEAD_LETTER_DIR)
+                            .join(path.file_name().unwrap_or_default());
+                        error!(
+                            "failed replay update {MAX_REPLAY_ATTEMPTS} times, move it to {} - {e}",
+                            dead.display()
+                        );
+                        match fs::rename(&path, &dead).await {
+                            Ok(_) => queue.len = queue.len.saturating_sub(1),
+                            Err(v) if v.kind() == ErrorKind::NotFound => {}
+                            Err(v) => return Err(v.into()),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// read the queued update, the broken one is discarded,
+    /// None if it is discarded or already dropped by push
+    async fn read(&self, path: &Path) -> Option<UpdateJobParams> {
+        let mut queue = self.queue.lock().await;
+        let content = match fs::read(path).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => {
+                error!("failed read update {} - {e}", path.display());
+                return None;
+            }
+        };
+        match serde_json::from_slice(&content) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("discard broken update {} - {e}", path.display());
+                if self.remove(path).await.unwrap_or_default() {
+                    queue.len = queue.len.saturating_sub(1);
+                }
+                None
+            }
+        }
+    }
+
+    /// false if the update is already dropped by push
+    async fn remove(&self, path: &Path) -> Result<bool> {
+        match fs::remove_file(path).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_outbox() {
+    let dir = std::env::temp_dir().join(format!("jiascheduler-outbox-{}", std::process::id()));
+    let output_dir = dir.to_string_lossy().to_string();
+
+    let outbox = Outbox::open(&output_dir).await;
+    for id in ["1", "2", "3"] {
+        let params = UpdateJobParams {
+            msg_id: Some(id.to_string()),
+            ..Default::default()
+        };
+        outbox.push(&params).await.unwrap();
+    }
+
+    let outbox = Outbox::open(&output_dir).await;
+    let mut sent = vec![];
+    let ret = outbox
+        .replay(|v| {
+            let fail = v.msg_id.as_deref() == Some("2") && sent.len() == 1;
+            sent.push(v.msg_id.unwrap_or_default());
+            async move {
+                if fail {
+                    anyhow::bail!("comet is unreachable");
+                }
+                Ok(())
+            }
+        })
+        .await;
+    assert!(ret.is_err());
+
+    assert_eq!(outbox.replay(|_| async { Ok(()) }).await.unwrap(), 2);
+    assert_eq!(sent, vec!["1", "2"]);
+    assert!(outbox.is_empty().await);
+
+    // an update which keeps failing is moved aside and no longer blocks the queue
+    for id in ["4", "5"] {
+        let params = UpdateJobParams {
+            msg_id: Some(id.to_string()),
+            ..Default::default()
+        };
+        outbox.push(&params).await.unwrap();
+    }
+    let fail = |v: UpdateJobParams| async move {
+        if v.msg_id.as_deref() == Some("4") {
+            anyhow::bail!("rejected");
+        }
+        Ok(())
+    };
+    for _ in 1..MAX_REPLAY_ATTEMPTS {
+        assert!(outbox.replay(fail).await.is_err());
+    }
+    assert_eq!(outbox.replay(fail).await.unwrap(), 1);
+    assert!(outbox.is_empty().await);
+    assert_eq!(
+        std::fs::read_dir(dir.join(OUTBOX_DIR).join(DEAD_LETTER_DIR))
+            .unwrap()
+            .count(),
+        1
+    );
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -1,13 +1,24 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::SeekFrom,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Local, Utc};
-use futures::{SinkExt, StreamExt};
+use chrono::{DateTime, Local, SubsecRound, Utc};
+use futures::{future::BoxFuture, SinkExt, StreamExt};
 
 use crate::{
     bridge::msg::{
-        BundleOutputParams, RuntimeActionParams, SftpDownloadParams, SftpReadDirParams,
-        SftpRemoveParams, SftpUploadParams, UpdateJobParams,
+        BundleOutputParams, JobLogParams, ListRuntimeStateParams, ReadOutputParams,
+        RuntimeActionParams, RuntimeJob, RuntimeStateReply, SftpDownloadParams, SftpReadDirParams,
+        SftpRemoveParams, SftpUploadParams, TransferChunkParams, TransferParams,
+        TransferReadParams, UpdateJobParams,
     },
     comet::types::SshLoginParams,
     get_comet_addr, get_local_ip,
@@ -19,12 +30,15 @@ use futures_util::stream::{SplitSink, SplitStream};
 
 use serde_json::{json, Value};
 use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
     net::TcpStream,
+    runtime::Handle,
     sync::{
-        mpsc::{channel, Receiver, Sender},
-        Mutex,
+        mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedSender},
+        Mutex, Notify,
     },
-    task,
+    task::{self, JoinHandle},
     time::{sleep, timeout},
 };
 use tokio_cron_scheduler::{Job, JobScheduler};
@@ -33,27 +47,45 @@ use tokio_tungstenite::{
     tungstenite::{ClientRequestBuilder, Message},
     MaybeTlsStream, WebSocketStream,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use super::{
-    executor::Ctx,
+    executor::{output_file_root, Ctx, ExecutorBuilder},
     file::try_download_file,
+    outbox::Outbox,
+    store::RuntimeStore,
+    timer::{self, CalendarCheck, Missed},
     types::{
-        self, AssignUserOption, BundleOutput, RuntimeAction, ScheduleType, SshConnectionOption,
+        self, AssignUserOption, BaseJob, BundleOutput, ConcurrencyPolicy, MisfirePolicy,
+        RuntimeAction, ScheduleType, SshConnectionOption, TimerOption,
     },
 };
 
 use crate::{
     bridge::{
         client::WsClient,
-        msg::{DispatchJobParams, HeartbeatParams, MsgReqKind},
+        msg::{DispatchJobParams, HeartbeatParams, MsgError, MsgReqKind},
+        protocol::DEFAULT_COMPRESS_THRESHOLD,
+        transfer::TransferStore,
         Bridge,
     },
-    get_endpoint,
+    code, get_endpoint,
     scheduler::executor::Executor,
 };
 
+/// seconds a supervised process should keep running before its restart count is reset
+const SUPERVISOR_STABLE_SECS: i64 = 60;
+/// output lines are sent to comet once the batch is full or the interval is reached
+const LOG_BATCH_LINES: usize = 200;
+const LOG_BATCH_INTERVAL: Duration = Duration::from_millis(500);
+/// max bytes of an output file returned by one read
+const MAX_READ_OUTPUT_SIZE: u64 = 1 << 20;
+/// max missed runs of a timer reported one by one, the earlier ones are counted only
+const MAX_REPORTED_MISSED: usize = 100;
+/// interval to retry the job updates queued in the outbox
+const OUTBOX_REPLAY_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Clone)]
 pub struct React {
     sched: JobScheduler,
@@ -63,7 +95,76 @@ pub struct React {
     local_ip: String,
     client_key: String,
     schedule_uuid_mapping: Arc<Mutex<HashMap<String, Uuid>>>,
-    kill_signal_mapping: Arc<Mutex<HashMap<String, Vec<Sender<()>>>>>,
+    /// fires of timers shifted by the calendar and waiting for the target time
+    shifted_fire_mapping: Arc<Mutex<HashMap<String, HashMap<DateTime<Utc>, JoinHandle<()>>>>>,
+    run_slot_mapping: Arc<Mutex<HashMap<String, Vec<RunSlot>>>>,
+    run_slot_released: Arc<Notify>,
+    run_id: Arc<AtomicU64>,
+    supervisor_mapping: Arc<Mutex<HashMap<String, (u64, Sender<()>)>>>,
+    store: RuntimeStore,
+    outbox: Outbox,
+    transfer: TransferStore,
+}
+
+/// A run of a job which is running or waiting for a free slot
+struct RunSlot {
+    id: u64,
+    running: bool,
+    /// killed to make room for a new run by [ConcurrencyPolicy::Replace]
+    replaced: bool,
+    kill_signal_tx: Sender<()>,
+    schedule_id: String,
+    schedule_type: Option<ScheduleType>,
+}
+
+enum RunSlotState {
+    Acquired,
+    Queued,
+    Skipped,
+}
+
+/// Releases the run slot when the run ends, or when the run is dropped before it ends,
+/// e.g. the sync exec is aborted by the peer, the dropped run is reported as stopped
+struct RunSlotGuard {
+    react: React,
+    job_id: String,
+    run_id: u64,
+    released: bool,
+    update_params: UpdateJobParams,
+}
+
+impl RunSlotGuard {
+    async fn release(mut self) {
+        self.released = true;
+        self.react.release_run_slot(&self.job_id, self.run_id).await;
+    }
+}
+
+impl Drop for RunSlotGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let Ok(handle) = Handle::try_current() else {
+            return;
+        };
+        let react = self.react.clone();
+        let job_id = std::mem::take(&mut self.job_id);
+        let run_id = self.run_id;
+        let update_params = std::mem::take(&mut self.update_params);
+        handle.spawn(async move {
+            react.release_run_slot(&job_id, run_id).await;
+            let _ = react
+                .send_update_job_msg(UpdateJobParams {
+                    run_status: Some(types::RunStatus::Stop),
+                    exit_status: Some("aborted".to_string()),
+                    exit_code: Some(9),
+                    end_time: Some(Utc::now()),
+                    ..update_params
+                })
+                .await;
+        });
+    }
 }
 
 impl React {
@@ -76,9 +177,16 @@ impl React {
     ) -> Self {
         Self {
             sched: JobScheduler::new().await.unwrap(),
+            store: RuntimeStore::open(&output_dir).await,
+            outbox: Outbox::open(&output_dir).await,
+            transfer: TransferStore::open(&output_dir).await,
             output_dir,
             schedule_uuid_mapping: Arc::new(Mutex::new(HashMap::new())),
-            kill_signal_mapping: Arc::new(Mutex::new(HashMap::new())),
+            shifted_fire_mapping: Arc::new(Mutex::new(HashMap::new())),
+            run_slot_mapping: Arc::new(Mutex::new(HashMap::new())),
+            run_slot_released: Arc::new(Notify::new()),
+            run_id: Arc::new(AtomicU64::new(1)),
+            supervisor_mapping: Arc::new(Mutex::new(HashMap::new())),
             bridge,
             client_key,
             namespace,
@@ -86,15 +194,124 @@ impl React {
         }
     }
 
-    async fn send_update_job_msg(&self, data: UpdateJobParams) -> Result<Value> {
-        self.send_bridge_msg(MsgReqKind::UpdateJobRequest(data))
+    /// send the update to comet, the update is queued in the outbox if comet is unreachable
+    /// or earlier updates are still queued, so that no result is lost and the order is kept
+    async fn send_update_job_msg(&self, mut data: UpdateJobParams) -> Result<Value> {
+        data.msg_id
+            .get_or_insert_with(|| Uuid::new_v4().to_string());
+
+        if !self.outbox.is_empty().await {
+            self.outbox.push(&data).await?;
+            return Ok(json!(null));
+        }
+
+        match self
+            .send_bridge_msg(MsgReqKind::UpdateJobRequest(data.clone()))
             .await
+        {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                warn!(
+                    "failed send job update, queue it in outbox, job_id: {} - {e}",
+                    data.base_job.eid
+                );
+                self.outbox
+                    .push(&data)
+                    .await
+                    .map_err(|v| anyhow!("{e}, {v}"))?;
+                Ok(json!(null))
+            }
+        }
+    }
+
+    /// replay the queued updates periodically and after the agent reconnects
+    fn spawn_outbox_replay(&self) {
+        let react = self.clone();
+        task::spawn(async move {
+            loop {
+                let sender = &react;
+                let ret = react
+                    .outbox
+                    .replay(|v| async move {
+                        sender
+                            .send_bridge_msg(MsgReqKind::UpdateJobRequest(v))
+                            .await
+                            .map(|_| ())
+                    })
+                    .await;
+                match ret {
+                    Ok(0) => {}
+                    Ok(n) => info!("replayed {n} job updates from outbox"),
+                    Err(e) => debug!("failed replay outbox - {e}"),
+                }
+
+                tokio::select! {
+                    _ = sleep(OUTBOX_REPLAY_INTERVAL) => {},
+                    _ = react.outbox.notified() => {},
+                }
+            }
+        });
     }
 
     async fn send_bridge_msg(&self, data: MsgReqKind) -> Result<Value> {
         self.bridge.send_msg(&self.client_key, data).await
     }
 
+    /// forward output lines of a running job to comet in batches,
+    /// the last batch is marked as eof after all senders are dropped
+    fn spawn_log_forwarder(&self, update_params: &UpdateJobParams) -> UnboundedSender<String> {
+        let (log_tx, mut log_rx) = unbounded_channel::<String>();
+        let react = self.clone();
+        let mut params = JobLogParams {
+            schedule_id: update_params.schedule_id.clone(),
+            eid: update_params.base_job.eid.clone(),
+            bind_ip: update_params.bind_ip.clone(),
+            bind_namespace: update_params.bind_namespace.clone(),
+            ..Default::default()
+        };
+
+        task::spawn(async move {
+            let mut interval = tokio::time::interval(LOG_BATCH_INTERVAL);
+            loop {
+                let is_eof = tokio::select! {
+                    line = log_rx.recv() => match line {
+                        Some(line) => {
+                            params.lines.push(line);
+                            if params.lines.len() < LOG_BATCH_LINES {
+                                continue;
+                            }
+                            false
+                        }
+                        None => true,
+                    },
+                    _ = interval.tick() => {
+                        if params.lines.is_empty() {
+                            continue;
+                        }
+                        false
+                    }
+                };
+
+                let batch = JobLogParams {
+                    lines: std::mem::take(&mut params.lines),
+                    is_eof,
+                    ..params.clone()
+                };
+                if let Err(e) = react
+                    .send_bridge_msg(MsgReqKind::JobLogRequest(batch))
+                    .await
+                {
+                    error!("failed send job log, job_id: {} - {e}", params.eid);
+                }
+                if is_eof {
+                    break;
+                }
+            }
+        });
+
+        log_tx
+    }
+
     async fn add_job_schedule(
         &mut self,
         job_id: String,
@@ -115,7 +332,26 @@ impl React {
         Ok(next_time)
     }
 
+    async fn next_fire_time(&self, job_id: &str) -> Option<DateTime<Utc>> {
+        let uuid = self
+            .schedule_uuid_mapping
+            .lock()
+            .await
+            .get(job_id)
+            .cloned()?;
+        self.sched
+            .clone()
+            .next_tick_for_job(uuid)
+            .await
+            .ok()
+            .flatten()
+    }
+
     async fn remove_job_schedule(&mut self, job_id: &str) -> Result<()> {
+        if let Some(fires) = self.shifted_fire_mapping.lock().await.remove(job_id) {
+            fires.values().for_each(JoinHandle::abort);
+        }
+
         let mut locked_map = self.schedule_uuid_mapping.lock().await;
         if let Some(uuid) = locked_map.get(job_id) {
             self.sched.remove(uuid).await?;
@@ -124,35 +360,267 @@ impl React {
         Ok(())
     }
 
-    async fn add_kill_signal_tx(&mut self, job_id: String, kill_signal_tx: Sender<()>) {
-        let mut locked_map = self.kill_signal_mapping.lock().await;
-        if let Some(val) = locked_map.get_mut(&job_id) {
-            val.append(&mut vec![kill_signal_tx]);
+    /// run the fire shifted by the calendar at the target time, the fires shifted to
+    /// the same target run once, the waiting ones are aborted when the timer is removed
+    async fn add_shifted_fire<F>(&self, job_id: &str, target: DateTime<Utc>, fire: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut locked_map = self.shifted_fire_mapping.lock().await;
+        let fires = locked_map.entry(job_id.to_string()).or_default();
+        fires.retain(|_, v| !v.is_finished());
+        if fires.contains_key(&target) {
+            debug!("{job_id} is already shifted to {target}");
+            return;
+        }
+
+        let handle = task::spawn(async move {
+            sleep((target - Utc::now()).to_std().unwrap_or_default()).await;
+            // the run is not aborted with the wait once it started
+            task::spawn(fire);
+        });
+        fires.insert(target, handle);
+    }
+
+    fn next_run_id(&self) -> u64 {
+        self.run_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Try to take a run slot of the job according to max_parallel and concurrency_policy,
+    /// a queued run keeps its place and should try again after a slot is released,
+    /// the queued runs take the free slots in the order they came
+    async fn try_acquire_run_slot(
+        &self,
+        job: &BaseJob,
+        run_id: u64,
+        kill_signal_tx: &Sender<()>,
+        update_params: &UpdateJobParams,
+    ) -> RunSlotState {
+        let mut locked_map = self.run_slot_mapping.lock().await;
+        let slots = locked_map.entry(job.eid.clone()).or_default();
+        let running_num = slots.iter().filter(|v| v.running).count();
+        let max_parallel = job.max_parallel as usize;
+        let is_head = slots
+            .iter()
+            .find(|v| !v.running)
+            .map_or(true, |v| v.id == run_id);
+
+        let state = if max_parallel == 0 || job.concurrency_policy == ConcurrencyPolicy::Allow {
+            RunSlotState::Acquired
+        } else if running_num < max_parallel {
+            if is_head {
+                RunSlotState::Acquired
+            } else {
+                RunSlotState::Queued
+            }
         } else {
-            locked_map.insert(job_id, vec![kill_signal_tx]);
+            match job.concurrency_policy {
+                ConcurrencyPolicy::Skip => RunSlotState::Skipped,
+                ConcurrencyPolicy::Allow | ConcurrencyPolicy::Queue => RunSlotState::Queued,
+                ConcurrencyPolicy::Replace => {
+                    // the new run waits until the replaced runs exit, so they never overlap
+                    let replacing = slots.iter().filter(|v| v.running && v.replaced).count();
+                    let num = (running_num + 1 - max_parallel).saturating_sub(replacing);
+                    for slot in slots
+                        .iter_mut()
+                        .filter(|v| v.running && !v.replaced)
+                        .take(num)
+                    {
+                        slot.replaced = true;
+                        if let Err(e) = slot.kill_signal_tx.try_send(()) {
+                            error!("failed send kill signal, job_id: {} - {e}", job.eid);
+                        }
+                    }
+                    RunSlotState::Queued
+                }
+            }
+        };
+
+        let running = matches!(state, RunSlotState::Acquired);
+        match slots.iter_mut().find(|v| v.id == run_id) {
+            Some(slot) => slot.running = running,
+            None if !matches!(state, RunSlotState::Skipped) => slots.push(RunSlot {
+                id: run_id,
+                running,
+                replaced: false,
+                kill_signal_tx: kill_signal_tx.clone(),
+                schedule_id: update_params.schedule_id.clone(),
+                schedule_type: update_params.schedule_type.clone(),
+            }),
+            None => {}
+        }
+
+        if slots.is_empty() {
+            locked_map.remove(&job.eid);
+        }
+
+        state
+    }
+
+    async fn release_run_slot(&self, job_id: &str, run_id: u64) {
+        let mut locked_map = self.run_slot_mapping.lock().await;
+        if let Some(slots) = locked_map.get_mut(job_id) {
+            slots.retain(|v| v.id != run_id);
+            if slots.is_empty() {
+                locked_map.remove(job_id);
+            }
         }
+        // the queued runs check again, only the head of the queue takes the free slot
+        self.run_slot_released.notify_waiters();
     }
 
     async fn kill_job(&mut self, job_id: String) {
-        let mut locked_map = self.kill_signal_mapping.lock().await;
-        locked_map.remove(&job_id).map(|v| async {
-            for tx in v {
-                if let Err(_) = tx.send(()).await {
+        let locked_map = self.run_slot_mapping.lock().await;
+        if let Some(slots) = locked_map.get(&job_id) {
+            for slot in slots {
+                if let Err(_) = slot.kill_signal_tx.try_send(()) {
                     error!("failed send kill signal, job_id: {job_id}");
                 }
             }
-        });
+        }
     }
 
-    async fn remove_job(&mut self, job_id: String) {
-        let mut locked_map = self.kill_signal_mapping.lock().await;
-        locked_map.remove(&job_id);
+    /// register a supervisor, return false if the job is already supervised
+    async fn add_supervisor(&self, job_id: String, id: u64, stop_signal_tx: Sender<()>) -> bool {
+        let mut locked_map = self.supervisor_mapping.lock().await;
+        if locked_map.contains_key(&job_id) {
+            return false;
+        }
+        locked_map.insert(job_id, (id, stop_signal_tx));
+        true
+    }
+
+    /// remove the exited supervisor, the saved one is kept if the job is supervised again
+    async fn remove_supervisor(&self, job_id: &str, id: u64) {
+        let mut locked_map = self.supervisor_mapping.lock().await;
+        if locked_map.get(job_id).is_some_and(|v| v.0 == id) {
+            locked_map.remove(job_id);
+        }
+        if !locked_map.contains_key(job_id) {
+            self.store.remove_supervisor(job_id).await;
+        }
+    }
+
+    /// send stop signal to the supervisor, return false if the job is not supervised
+    async fn stop_supervisor(&self, job_id: &str) -> bool {
+        let mut locked_map = self.supervisor_mapping.lock().await;
+        self.store.remove_supervisor(job_id).await;
+        match locked_map.remove(job_id) {
+            Some((_, stop_signal_tx)) => {
+                if let Err(_) = stop_signal_tx.try_send(()) {
+                    error!("failed send stop signal, job_id: {job_id}");
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// the timers, supervisors and runs active now, the schedule ids are taken from the saved state
+    async fn runtime_state(&self) -> RuntimeStateReply {
+        let saved = self.store.state().await;
+        let mut state = RuntimeStateReply::default();
+
+        let timers = self.schedule_uuid_mapping.lock().await.clone();
+        for (eid, uuid) in timers {
+            state.timers.push(RuntimeJob {
+                schedule_id: saved
+                    .timers
+                    .get(&eid)
+                    .map(|v| v.schedule_id.clone())
+                    .unwrap_or_default(),
+                schedule_type: Some(ScheduleType::Timer),
+                next_time: self
+                    .sched
+                    .clone()
+                    .next_tick_for_job(uuid)
+                    .await
+                    .ok()
+                    .flatten(),
+                eid,
+            });
+        }
+
+        for eid in self.supervisor_mapping.lock().await.keys() {
+            state.supervisors.push(RuntimeJob {
+                eid: eid.clone(),
+                schedule_id: saved
+                    .supervisors
+                    .get(eid)
+                    .map(|v| v.schedule_id.clone())
+                    .unwrap_or_default(),
+                schedule_type: Some(ScheduleType::Supervisor),
+                next_time: None,
+            });
+        }
+
+        for (eid, slots) in self.run_slot_mapping.lock().await.iter() {
+            state
+                .running
+                .extend(slots.iter().filter(|v| v.running).map(|v| RuntimeJob {
+                    eid: eid.clone(),
+                    schedule_id: v.schedule_id.clone(),
+                    schedule_type: v.schedule_type.clone(),
+                    next_time: None,
+                }));
+        }
+        state
     }
 
     async fn start(&mut self) -> Result<()> {
         self.sched.start().await?;
         Ok(())
     }
+
+    /// start the timers and supervisors saved before the agent restarts,
+    /// the console dispatches its runnable jobs again after connected and replaces them
+    async fn restore(&self) {
+        let state = self.store.state().await;
+        let now = Utc::now();
+        for (eid, params) in state.timers {
+            info!("restore timer {eid}");
+            let missed = match (state.fired.get(&eid), params.timer_expr.as_deref()) {
+                (Some(&last), Some(timer_expr)) => {
+                    let option = params.timer_option.clone().unwrap_or_default();
+                    let limit = MAX_REPORTED_MISSED.max(option.max_misfire_runs as usize);
+                    match option.time_zone.as_deref().map(timer::parse_time_zone) {
+                        Some(Ok(tz)) => timer::missed_times(timer_expr, &tz, last, now, limit),
+                        Some(Err(e)) => Err(e),
+                        None => timer::missed_times(timer_expr, &Local, last, now, limit),
+                    }
+                    .map_err(|e| error!("failed find missed runs of {eid} - {e}"))
+                    .unwrap_or_default()
+                }
+                _ => Missed::default(),
+            };
+
+            if let Err(e) = Scheduler::start_timer(params.clone(), self.clone()).await {
+                error!("failed restore timer {eid} - {e}");
+            }
+            if missed.count > 0 {
+                self.store.set_fired(&eid, now).await;
+                task::spawn(Scheduler::catch_up(params, self.clone(), missed));
+            }
+        }
+        for (eid, params) in state.supervisors {
+            info!("restore supervisor {eid}");
+            if let Err(e) = Scheduler::start_supervisor(params, self.clone()).await {
+                error!("failed restore supervisor {eid} - {e}");
+            }
+        }
+    }
+}
+
+/// executor of the dispatched job with the env and secrets of the dispatch
+fn executor_builder(dispatch_params: &DispatchJobParams) -> ExecutorBuilder {
+    let mut builder = Executor::builder().job(dispatch_params.base_job.clone());
+    for (k, v) in dispatch_params.env.clone() {
+        builder = builder.env(k, v);
+    }
+    for (k, v) in dispatch_params.secrets.clone() {
+        builder = builder.secret(k, v);
+    }
+    builder
 }
 
 pub struct Scheduler<T> {
@@ -165,6 +633,7 @@ pub struct Scheduler<T> {
     bridge: Bridge,
     ssh_connection_option: Option<SshConnectionOption>,
     assign_user_option: Option<AssignUserOption>,
+    compress_threshold: usize,
 }
 
 impl
@@ -193,9 +662,15 @@ impl
             bridge: Bridge::new(),
             ssh_connection_option,
             assign_user_option,
+            compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
         }
     }
 
+    /// frames sent to comet above the size are compressed, 0 disables compression
+    pub fn set_compress_threshold(&mut self, compress_threshold: usize) {
+        self.compress_threshold = compress_threshold;
+    }
+
     pub fn client_key(&self) -> String {
         get_endpoint(self.namespace.clone(), get_local_ip().to_string())
     }
@@ -320,7 +795,8 @@ impl
         let mut client = WsClient::new(Some(self.bridge.clone()))
             .set_namespace(self.namespace.clone())
             .set_local_ip(local_ip.clone())
-            .set_comet_secret(self.comet_secret.clone());
+            .set_comet_secret(self.comet_secret.clone())
+            .set_compress_threshold(self.compress_threshold);
 
         if let Some(ref opt) = self.assign_user_option {
             client = client.set_assign_user(opt.to_owned());
@@ -340,7 +816,7 @@ impl
         info!("append new sender {client_key} to {ws_addr}");
 
         self.bridge
-            .append_client(client_key.clone(), client.sender())
+            .append_client(client_key.clone(), client.sender(), client.get_peer())
             .await;
 
         self.client.replace(client);
@@ -352,94 +828,245 @@ impl
         e: Executor,
         react: React,
         schedule_type: Option<ScheduleType>,
-        kill_signal_rx: Receiver<()>,
         prev_time: Option<DateTime<Utc>>,
         next_time: Option<DateTime<Utc>>,
         job_params: DispatchJobParams,
     ) -> Result<BundleOutput> {
-        let start_time = Utc::now();
-        let schedule_id = job_params.schedule_id;
-        let base_job = job_params.base_job;
+        let base_job = job_params.base_job.clone();
+        let update_params = UpdateJobParams {
+            base_job: base_job.to_pure_job(),
+            schedule_id: job_params.schedule_id.clone(),
+            prev_time,
+            next_time,
+            bind_namespace: react.namespace.clone(),
+            bind_ip: react.local_ip.clone(),
+            schedule_type: schedule_type.clone(),
+            created_user: job_params.created_user.clone(),
+            ..Default::default()
+        };
 
-        let _ = react
-            .send_update_job_msg(UpdateJobParams {
-                base_job: base_job.to_pure_job(),
-                run_status: Some(types::RunStatus::Running),
-                schedule_id: schedule_id.clone(),
-                exit_status: None,
-                exit_code: None,
-                stdout: None,
-                stderr: None,
-                next_time,
-                prev_time,
-                bind_namespace: react.namespace.clone(),
-                bind_ip: react.local_ip.clone(),
-                schedule_type: schedule_type.clone(),
-                created_user: job_params.created_user.clone(),
-                start_time: Some(start_time.clone()),
-                ..Default::default()
-            })
-            .await?;
+        let run_id = react.next_run_id();
+        let slot_guard = RunSlotGuard {
+            react: react.clone(),
+            job_id: base_job.eid.clone(),
+            run_id,
+            released: false,
+            update_params: update_params.clone(),
+        };
+        let (kill_signal_tx, mut kill_signal_rx) = channel::<()>(1);
+        let mut queued = false;
 
-        let output = match e.run(Ctx { kill_signal_rx }).await {
-            Ok(v) => v,
-            Err(e) => {
-                let bundle_output = if base_job.bundle_script.is_none() {
-                    None
-                } else {
-                    Some(vec![])
-                };
-                let _ = react
-                    .send_update_job_msg(UpdateJobParams {
-                        base_job: base_job.to_pure_job(),
-                        run_status: Some(types::RunStatus::Stop),
-                        schedule_id: schedule_id.clone(),
-                        exit_status: Some(e.to_string()),
-                        exit_code: Some(99),
-                        prev_time,
-                        next_time,
-                        bind_namespace: react.namespace.clone(),
-                        bind_ip: react.local_ip.clone(),
-                        start_time: Some(start_time),
-                        schedule_type: schedule_type.clone(),
-                        stdout: Some(e.to_string()),
-                        stderr: Some(e.to_string()),
-                        end_time: Some(Utc::now()),
-                        created_user: job_params.created_user.clone(),
-                        bundle_output,
-                        ..Default::default()
-                    })
-                    .await?;
-                return Err(e);
+        loop {
+            let released = react.run_slot_released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            match react
+                .try_acquire_run_slot(&base_job, run_id, &kill_signal_tx, &update_params)
+                .await
+            {
+                RunSlotState::Acquired => break,
+                RunSlotState::Skipped => {
+                    let now = Utc::now();
+                    let exit_status =
+                        format!("skipped, reached max_parallel {}", base_job.max_parallel);
+                    let _ = react
+                        .send_update_job_msg(UpdateJobParams {
+                            run_status: Some(types::RunStatus::Skipped),
+                            exit_status: Some(exit_status.clone()),
+                            start_time: Some(now),
+                            end_time: Some(now),
+                            ..update_params.clone()
+                        })
+                        .await?;
+                    anyhow::bail!(exit_status);
+                }
+                RunSlotState::Queued => {
+                    if !queued {
+                        queued = true;
+                        let _ = react
+                            .send_update_job_msg(UpdateJobParams {
+                                run_status: Some(types::RunStatus::Queued),
+                                exit_status: Some(format!(
+                                    "queued, reached max_parallel {}",
+                                    base_job.max_parallel
+                                )),
+                                ..update_params.clone()
+                            })
+                            .await;
+                    }
+                    tokio::select! {
+                        _ = released => {},
+                        Some(_) = kill_signal_rx.recv() => {
+                            slot_guard.release().await;
+                            let now = Utc::now();
+                            let _ = react
+                                .send_update_job_msg(UpdateJobParams {
+                                    run_status: Some(types::RunStatus::Stop),
+                                    exit_status: Some("killed while queued".to_string()),
+                                    exit_code: Some(9),
+                                    start_time: Some(now),
+                                    end_time: Some(now),
+                                    ..update_params.clone()
+                                })
+                                .await?;
+                            anyhow::bail!("{} killed while queued", base_job.eid);
+                        }
+                    }
+                }
             }
-        };
+        }
 
-        let _ = react
-            .send_update_job_msg(UpdateJobParams {
-                base_job: base_job.to_pure_job(),
-                run_status: Some(types::RunStatus::Stop),
-                schedule_id: schedule_id.clone(),
-                exit_status: output.get_exit_status(),
-                exit_code: output.get_exit_code(),
-                prev_time,
-                next_time,
-                bind_namespace: react.namespace.clone(),
-                bind_ip: react.local_ip.clone(),
-                start_time: Some(start_time),
-                schedule_type: schedule_type.clone(),
-                stdout: output.get_stdout(),
-                stderr: output.get_stderr(),
-                end_time: Some(Utc::now()),
-                created_user: job_params.created_user.clone(),
-                bundle_output: BundleOutputParams::parse(&output),
-                ..Default::default()
-            })
-            .await?;
+        let ret = Self::run_job(e, &react, &base_job, kill_signal_rx, update_params).await;
+        slot_guard.release().await;
+        ret
+    }
+
+    async fn run_job(
+        e: Executor,
+        react: &React,
+        base_job: &types::BaseJob,
+        mut kill_signal_rx: Receiver<()>,
+        update_params: UpdateJobParams,
+    ) -> Result<BundleOutput> {
+        let mut attempt: u8 = 0;
+        let log_tx = react.spawn_log_forwarder(&update_params);
+
+        loop {
+            attempt = attempt.saturating_add(1);
+            let start_time = Utc::now();
+
+            let _ = react
+                .send_update_job_msg(UpdateJobParams {
+                    run_status: Some(types::RunStatus::Running),
+                    start_time: Some(start_time.clone()),
+                    attempt: Some(attempt),
+                    ..update_params.clone()
+                })
+                .await?;
+
+            // forward the kill signal to the current attempt, a killed job is never retried
+            let (attempt_kill_tx, attempt_kill_rx) = channel::<()>(1);
+            let mut killed = false;
+            let ret = {
+                let run = e.run(Ctx {
+                    kill_signal_rx: attempt_kill_rx,
+                    log_tx: Some(log_tx.clone()),
+                });
+                tokio::pin!(run);
+                loop {
+                    tokio::select! {
+                        ret = &mut run => break ret,
+                        Some(_) = kill_signal_rx.recv(), if !killed => {
+                            killed = true;
+                            let _ = attempt_kill_tx.send(()).await;
+                        }
+                    }
+                }
+            };
 
-        Ok(output)
+            let can_retry = !killed && attempt <= base_job.max_retry && attempt < u8::MAX;
+            let is_success = ret.as_ref().is_ok_and(|v| v.is_success());
+            let run_status = if is_success || !can_retry {
+                types::RunStatus::Stop
+            } else {
+                types::RunStatus::Retrying
+            };
+            let retry_exhausted = Some(!is_success && !killed && !can_retry);
+
+            let ret = match ret {
+                Ok(output) => {
+                    let _ = react
+                        .send_update_job_msg(UpdateJobParams {
+                            run_status: Some(run_status.clone()),
+                            exit_status: output.get_exit_status(),
+                            exit_code: output.get_exit_code(),
+                            start_time: Some(start_time),
+                            stdout: output.get_stdout(),
+                            stderr: output.get_stderr(),
+                            stdout_size: output.get_stdout_size(),
+                            stderr_size: output.get_stderr_size(),
+                            stdout_file: output.get_stdout_file(),
+                            stderr_file: output.get_stderr_file(),
+                            end_time: Some(Utc::now()),
+                            bundle_output: BundleOutputParams::parse(&output),
+                            attempt: Some(attempt),
+                            retry_exhausted,
+                            ..update_params.clone()
+                        })
+                        .await?;
+                    Ok(output)
+                }
+                Err(e) => {
+                    let bundle_output = if base_job.bundle_script.is_none() {
+                        None
+                    } else {
+                        Some(vec![])
+                    };
+                    let _ = react
+                        .send_update_job_msg(UpdateJobParams {
+                            run_status: Some(run_status.clone()),
+                            exit_status: Some(e.to_string()),
+                            exit_code: Some(99),
+                            start_time: Some(start_time),
+                            stdout: Some(e.to_string()),
+                            stderr: Some(e.to_string()),
+                            end_time: Some(Utc::now()),
+                            bundle_output,
+                            attempt: Some(attempt),
+                            retry_exhausted,
+                            ..update_params.clone()
+                        })
+                        .await?;
+                    Err(e)
+                }
+            };
+
+            if run_status == types::RunStatus::Stop {
+                return ret;
+            }
+
+            let delay = base_job.retry_backoff.delay(attempt);
+            debug!(
+                "retry {} after {:?}, attempt: {attempt}",
+                base_job.eid, delay
+            );
+
+            tokio::select! {
+                _ = sleep(delay) => {},
+                Some(_) = kill_signal_rx.recv() => {
+                    let now = Utc::now();
+                    let _ = react
+                        .send_update_job_msg(UpdateJobParams {
+                            run_status: Some(types::RunStatus::Stop),
+                            exit_status: Some("killed before retry".to_string()),
+                            exit_code: Some(9),
+                            start_time: Some(now),
+                            end_time: Some(now),
+                            attempt: Some(attempt.saturating_add(1)),
+                            retry_exhausted: Some(false),
+                            ..update_params.clone()
+                        })
+                        .await?;
+                    return ret;
+                }
+            }
+        }
     }
 
     async fn start_timer(dispatch_params: DispatchJobParams, mut react: React) -> Result<Value> {
+        // the console owns the clock, drop the local timer so that the job does not run twice
+        if TimerOption::is_console_mode(&dispatch_params.timer_option) {
+            react
+                .remove_job_schedule(&dispatch_params.base_job.eid)
+                .await?;
+            react
+                .store
+                .remove_timer(&dispatch_params.base_job.eid)
+                .await;
+            return Ok(json!(null));
+        }
+
         let timer_expr = dispatch_params.timer_expr.clone().unwrap_or_default();
         let base_job = dispatch_params.base_job.clone();
         let pure_job = base_job.to_pure_job();
@@ -447,49 +1074,76 @@ impl
         let react_clone = react.clone();
         let created_user = dispatch_params.created_user.clone();
         let schedule_id = dispatch_params.schedule_id.clone();
-
-        let job = Job::new_cron_job_async_tz(
-            timer_expr.as_str(),
-            Local,
-            move |job_id, mut job_scheduler| {
-                let base_job = base_job.clone();
-                let (kill_signal_tx, kill_signal_rx) = channel::<()>(1);
-                let mut react_clone = react_clone.clone();
-                let dispatch_params = dispatch_params.clone();
-
-                Box::pin(async move {
-                    let next_time = job_scheduler.next_tick_for_job(job_id).await.unwrap();
-                    let prev_time = Some(Local::now().into());
-
-                    react_clone
-                        .add_kill_signal_tx(base_job.eid.clone(), kill_signal_tx)
-                        .await;
-
-                    let e = Executor::builder()
-                        .job(base_job.clone())
-                        .output_dir(react_clone.output_dir.clone())
-                        .disable_write_log(true)
-                        .build();
-                    match Self::exec_job(
-                        e,
-                        react_clone,
-                        Some(ScheduleType::Timer),
-                        kill_signal_rx,
-                        prev_time,
-                        next_time,
-                        dispatch_params,
-                    )
-                    .await
-                    {
-                        Ok(_) => {}
-                        Err(e) => error!("failed exec {} - detail: {e}", base_job.eid),
+        let saved_params = dispatch_params.clone();
+        let option = dispatch_params.timer_option.clone().unwrap_or_default();
+        let time_zone = option
+            .time_zone
+            .as_deref()
+            .map(timer::parse_time_zone)
+            .transpose()?;
+        let splay = option.splay;
+        let calendar = option.calendar;
+        let run_timer_expr = timer_expr.clone();
+
+        let run = move |job_id: Uuid, mut job_scheduler: JobScheduler| -> BoxFuture<'static, ()> {
+            let base_job = base_job.clone();
+            let react_clone = react_clone.clone();
+            let dispatch_params = dispatch_params.clone();
+            let calendar = calendar.clone();
+            let timer_expr = run_timer_expr.clone();
+
+            Box::pin(async move {
+                let next_time = job_scheduler.next_tick_for_job(job_id).await.unwrap();
+                let fire_time = Utc::now().trunc_subsecs(0);
+                react_clone.store.set_fired(&base_job.eid, fire_time).await;
+
+                if let Some(ref calendar) = calendar {
+                    match timer::check_calendar(calendar, &timer_expr, time_zone, fire_time) {
+                        CalendarCheck::Run => {}
+                        CalendarCheck::Skip(reason) => {
+                            Self::report_skipped(&react_clone, &dispatch_params, fire_time, reason)
+                                .await;
+                            return;
+                        }
+                        CalendarCheck::Shift(target, reason) => {
+                            info!("shift {} to {target}, {reason}", base_job.eid);
+                            let fire = Self::fire_timer(
+                                react_clone.clone(),
+                                dispatch_params,
+                                Some(target),
+                                next_time,
+                                splay,
+                            );
+                            react_clone
+                                .add_shifted_fire(&base_job.eid, target, fire)
+                                .await;
+                            return;
+                        }
                     }
-                })
-            },
-        )
+                }
+                Self::fire_timer(
+                    react_clone,
+                    dispatch_params,
+                    Some(fire_time),
+                    next_time,
+                    splay,
+                )
+                .await;
+            })
+        };
+
+        let job = match time_zone {
+            Some(tz) => Job::new_cron_job_async_tz(timer_expr.as_str(), tz, run),
+            None => Job::new_cron_job_async_tz(timer_expr.as_str(), Local, run),
+        }
         .map_err(|v| anyhow!("failed parse timer expr {} - {}", timer_expr, v))?;
 
         let next_time = react.add_job_schedule(euid, job).await?;
+        react.store.add_timer(&saved_params).await;
+        react
+            .store
+            .init_fired(&saved_params.base_job.eid, Utc::now())
+            .await;
 
         let _ = react
             .send_update_job_msg(UpdateJobParams {
@@ -514,10 +1168,161 @@ impl
         Ok(json!(null))
     }
 
+    /// run a fire of the timer after its random splay
+    async fn fire_timer(
+        react: React,
+        dispatch_params: DispatchJobParams,
+        prev_time: Option<DateTime<Utc>>,
+        next_time: Option<DateTime<Utc>>,
+        splay: u64,
+    ) {
+        sleep(timer::splay_delay(splay)).await;
+
+        let eid = dispatch_params.base_job.eid.clone();
+        let e = executor_builder(&dispatch_params)
+            .output_dir(react.output_dir.clone())
+            .disable_write_log(true)
+            .build();
+        if let Err(e) = Self::exec_job(
+            e,
+            react,
+            Some(ScheduleType::Timer),
+            prev_time,
+            next_time,
+            dispatch_params,
+        )
+        .await
+        {
+            error!("failed exec {eid} - detail: {e}");
+        }
+    }
+
+    /// report a fire of the timer which is skipped by its calendar
+    async fn report_skipped(
+        react: &React,
+        dispatch_params: &DispatchJobParams,
+        time: DateTime<Utc>,
+        reason: String,
+    ) {
+        info!("skip {}, {reason}", dispatch_params.base_job.eid);
+        if let Err(e) = react
+            .send_update_job_msg(UpdateJobParams {
+                base_job: dispatch_params.base_job.to_pure_job(),
+                schedule_id: dispatch_params.schedule_id.clone(),
+                bind_namespace: react.namespace.clone(),
+                bind_ip: react.local_ip.clone(),
+                schedule_type: Some(ScheduleType::Timer),
+                created_user: dispatch_params.created_user.clone(),
+                run_status: Some(types::RunStatus::Skipped),
+                exit_status: Some(format!("skipped, {reason}")),
+                start_time: Some(time),
+                end_time: Some(time),
+                ..Default::default()
+            })
+            .await
+        {
+            error!(
+                "failed report skipped run of {} - {e}",
+                dispatch_params.base_job.eid
+            );
+        }
+    }
+
+    /// report the runs missed while the agent was not running,
+    /// and catch up the latest ones according to the misfire policy of the timer
+    async fn catch_up(dispatch_params: DispatchJobParams, react: React, missed: Missed) {
+        let eid = dispatch_params.base_job.eid.clone();
+        let option = dispatch_params.timer_option.clone().unwrap_or_default();
+        let run_num = match option.misfire_policy {
+            MisfirePolicy::Skip => 0,
+            MisfirePolicy::RunOnce => 1,
+            MisfirePolicy::RunAll => option.max_misfire_runs as usize,
+        }
+        .min(missed.times.len());
+        let (reported, runs) = missed.times.split_at(missed.times.len() - run_num);
+        let reported = &reported[reported.len().saturating_sub(MAX_REPORTED_MISSED)..];
+
+        let update_params = UpdateJobParams {
+            base_job: dispatch_params.base_job.to_pure_job(),
+            schedule_id: dispatch_params.schedule_id.clone(),
+            bind_namespace: react.namespace.clone(),
+            bind_ip: react.local_ip.clone(),
+            schedule_type: Some(ScheduleType::Timer),
+            created_user: dispatch_params.created_user.clone(),
+            run_status: Some(types::RunStatus::Missed),
+            ..Default::default()
+        };
+        info!(
+            "timer {eid} missed {} runs, catch up {run_num} runs",
+            missed.count
+        );
+
+        let uncounted = missed.count - run_num - reported.len();
+        let mut reports = reported
+            .iter()
+            .map(|v| (*v, "missed, the agent was not running".to_string()))
+            .collect::<Vec<_>>();
+        if let Some(&first) = missed.times.first().filter(|_| uncounted > 0) {
+            reports.insert(0, (first, format!("missed {uncounted} earlier runs")));
+        }
+        for (time, exit_status) in reports {
+            if let Err(e) = react
+                .send_update_job_msg(UpdateJobParams {
+                    exit_status: Some(exit_status),
+                    start_time: Some(time),
+                    end_time: Some(time),
+                    ..update_params.clone()
+                })
+                .await
+            {
+                error!("failed report missed run of {eid} - {e}");
+            }
+        }
+
+        let timer_expr = dispatch_params.timer_expr.clone().unwrap_or_default();
+        let time_zone = option
+            .time_zone
+            .as_deref()
+            .and_then(|v| timer::parse_time_zone(v).ok());
+        for &time in runs {
+            // the calendar still applies, a shifted run is caught up now
+            if let Some(ref calendar) = option.calendar {
+                if let CalendarCheck::Skip(reason) =
+                    timer::check_calendar(calendar, &timer_expr, time_zone, time)
+                {
+                    Self::report_skipped(&react, &dispatch_params, time, reason).await;
+                    continue;
+                }
+            }
+
+            let e = executor_builder(&dispatch_params)
+                .output_dir(react.output_dir.clone())
+                .disable_write_log(true)
+                .build();
+            let next_time = react.next_fire_time(&eid).await;
+            if let Err(e) = Self::exec_job(
+                e,
+                react.clone(),
+                Some(ScheduleType::Timer),
+                Some(time),
+                next_time,
+                dispatch_params.clone(),
+            )
+            .await
+            {
+                error!("failed catch up {eid} missed at {time} - {e}");
+            }
+        }
+    }
+
     async fn stop_timer(dispatch_params: DispatchJobParams, mut react: React) -> Result<Value> {
         react
             .remove_job_schedule(&dispatch_params.base_job.eid)
             .await?;
+        react
+            .store
+            .remove_timer(&dispatch_params.base_job.eid)
+            .await;
         let _ = react
             .send_update_job_msg(UpdateJobParams {
                 base_job: dispatch_params.base_job.to_pure_job(),
@@ -540,46 +1345,43 @@ impl
         Ok(json!(null))
     }
 
-    async fn exec(dispatch_params: DispatchJobParams, mut react: React) -> Result<Value> {
+    async fn exec(dispatch_params: DispatchJobParams, react: React) -> Result<Value> {
         let base_job = dispatch_params.base_job.clone();
-        let (kill_signal_tx, kill_signal_rx) = channel::<()>(1);
 
-        let e = Executor::builder()
-            .job(base_job.clone())
+        let e = executor_builder(&dispatch_params)
             .output_dir(react.output_dir.clone())
             .disable_write_log(true)
             .build();
-
-        react
-            .add_kill_signal_tx(base_job.eid.clone(), kill_signal_tx)
-            .await;
+        // a fire of a console timer is reported as a timer run
+        let schedule_type = if TimerOption::is_console_mode(&dispatch_params.timer_option) {
+            ScheduleType::Timer
+        } else {
+            ScheduleType::Once
+        };
 
         if dispatch_params.is_sync {
+            // the run is tied to the request, a request aborted by the peer drops the run,
+            // which kills the process group, the slot and the cgroup are released by their guards
             let output = Self::exec_job(
                 e,
                 react.clone(),
-                Some(ScheduleType::Once),
-                kill_signal_rx,
+                Some(schedule_type),
                 None,
                 None,
                 dispatch_params,
             )
             .await?;
-            react.remove_job(base_job.eid.clone()).await;
             return Ok(json!({
                 "stdout":output.get_stdout(),
                 "exit_code":output.get_exit_code(),
                 "stderr":output.get_stderr(),
             }));
         }
-        let juid = base_job.eid.clone();
-
         task::spawn(async move {
             match Self::exec_job(
                 e,
                 react.clone(),
-                Some(ScheduleType::Once),
-                kill_signal_rx,
+                Some(schedule_type),
                 None,
                 None,
                 dispatch_params,
@@ -589,13 +1391,228 @@ impl
                 Ok(_) => {}
                 Err(e) => error!("failed exec {} - detail: {e}", base_job.eid),
             }
-
-            react.remove_job(juid).await
         });
 
         return Ok(json!(null));
     }
 
+    async fn start_supervisor(dispatch_params: DispatchJobParams, react: React) -> Result<Value> {
+        let base_job = dispatch_params.base_job.clone();
+        let option = dispatch_params
+            .supervisor_option
+            .clone()
+            .unwrap_or_default();
+        let id = react.next_run_id();
+        let (stop_signal_tx, stop_signal_rx) = channel::<()>(1);
+
+        // the console dispatches again after the agent reconnects, keep the running one
+        if !react
+            .add_supervisor(base_job.eid.clone(), id, stop_signal_tx)
+            .await
+        {
+            debug!("{} is already supervised", base_job.eid);
+            return Ok(json!(null));
+        }
+        react.store.add_supervisor(&dispatch_params).await;
+
+        let e = executor_builder(&dispatch_params)
+            .output_dir(react.output_dir.clone())
+            .disable_timeout(true)
+            .build();
+
+        let _ = react
+            .send_update_job_msg(UpdateJobParams {
+                base_job: base_job.to_pure_job(),
+                run_status: Some(types::RunStatus::Prepare),
+                schedule_status: Some(types::ScheduleStatus::Scheduling),
+                schedule_id: dispatch_params.schedule_id.clone(),
+                bind_namespace: react.namespace.clone(),
+                bind_ip: react.local_ip.clone(),
+                schedule_type: Some(ScheduleType::Supervisor),
+                created_user: dispatch_params.created_user.clone(),
+                ..Default::default()
+            })
+            .await;
+
+        task::spawn(async move {
+            Self::supervise(e, &react, option, stop_signal_rx, dispatch_params).await;
+            react.remove_supervisor(&base_job.eid, id).await;
+        });
+
+        Ok(json!(null))
+    }
+
+    /// run the process and restart it after exit according to the restart policy,
+    /// status updates are best effort so that the process survives comet reconnects
+    async fn supervise(
+        e: Executor,
+        react: &React,
+        option: types::SupervisorOption,
+        mut stop_signal_rx: Receiver<()>,
+        dispatch_params: DispatchJobParams,
+    ) {
+        let base_job = dispatch_params.base_job;
+        let update_params = UpdateJobParams {
+            base_job: base_job.to_pure_job(),
+            schedule_id: dispatch_params.schedule_id,
+            bind_namespace: react.namespace.clone(),
+            bind_ip: react.local_ip.clone(),
+            schedule_type: Some(ScheduleType::Supervisor),
+            created_user: dispatch_params.created_user,
+            ..Default::default()
+        };
+        let mut restart: u32 = 0;
+        let log_tx = react.spawn_log_forwarder(&update_params);
+
+        loop {
+            let start_time = Utc::now();
+            if let Err(e) = react
+                .send_update_job_msg(UpdateJobParams {
+                    run_status: Some(types::RunStatus::Running),
+                    schedule_status: Some(types::ScheduleStatus::Scheduling),
+                    start_time: Some(start_time),
+                    ..update_params.clone()
+                })
+                .await
+            {
+                error!("failed update supervisor status {} - {e}", base_job.eid);
+            }
+
+            let (kill_signal_tx, kill_signal_rx) = channel::<()>(1);
+            let mut stopped = false;
+            let ret = {
+                let run = e.run(Ctx {
+                    kill_signal_rx,
+                    log_tx: Some(log_tx.clone()),
+                });
+                tokio::pin!(run);
+                loop {
+                    tokio::select! {
+                        ret = &mut run => break ret,
+                        Some(_) = stop_signal_rx.recv(), if !stopped => {
+                            stopped = true;
+                            let _ = kill_signal_tx.send(()).await;
+                        }
+                    }
+                }
+            };
+
+            // a process which has been running for a while is considered healthy again
+            if Utc::now() - start_time >= chrono::Duration::seconds(SUPERVISOR_STABLE_SECS) {
+                restart = 0;
+            }
+
+            let is_success = ret.as_ref().is_ok_and(|v| v.is_success());
+            let will_restart = !stopped
+                && option.restart_policy.should_restart(is_success)
+                && (option.max_restart == 0 || restart < option.max_restart);
+            let (run_status, schedule_status) = if will_restart {
+                (
+                    types::RunStatus::Restarting,
+                    types::ScheduleStatus::Scheduling,
+                )
+            } else {
+                (types::RunStatus::Stop, types::ScheduleStatus::Unscheduled)
+            };
+
+            let update_ret = match ret {
+                Ok(output) => {
+                    react
+                        .send_update_job_msg(UpdateJobParams {
+                            run_status: Some(run_status),
+                            schedule_status: Some(schedule_status),
+                            exit_status: output.get_exit_status(),
+                            exit_code: output.get_exit_code(),
+                            start_time: Some(start_time),
+                            stdout: output.get_stdout(),
+                            stderr: output.get_stderr(),
+                            stdout_size: output.get_stdout_size(),
+                            stderr_size: output.get_stderr_size(),
+                            stdout_file: output.get_stdout_file(),
+                            stderr_file: output.get_stderr_file(),
+                            end_time: Some(Utc::now()),
+                            bundle_output: BundleOutputParams::parse(&output),
+                            ..update_params.clone()
+                        })
+                        .await
+                }
+                Err(e) => {
+                    react
+                        .send_update_job_msg(UpdateJobParams {
+                            run_status: Some(run_status),
+                            schedule_status: Some(schedule_status),
+                            exit_status: Some(e.to_string()),
+                            exit_code: Some(99),
+                            start_time: Some(start_time),
+                            stdout: Some(e.to_string()),
+                            stderr: Some(e.to_string()),
+                            end_time: Some(Utc::now()),
+                            ..update_params.clone()
+                        })
+                        .await
+                }
+            };
+            if let Err(e) = update_ret {
+                error!("failed update supervisor status {} - {e}", base_job.eid);
+            }
+
+            if !will_restart {
+                return;
+            }
+
+            restart = restart.saturating_add(1);
+            let delay = option
+                .restart_backoff
+                .delay(restart.min(u8::MAX as u32) as u8);
+            debug!(
+                "restart {} after {:?}, restart: {restart}",
+                base_job.eid, delay
+            );
+
+            tokio::select! {
+                _ = sleep(delay) => {},
+                Some(_) = stop_signal_rx.recv() => {
+                    let now = Utc::now();
+                    if let Err(e) = react
+                        .send_update_job_msg(UpdateJobParams {
+                            run_status: Some(types::RunStatus::Stop),
+                            schedule_status: Some(types::ScheduleStatus::Unscheduled),
+                            exit_status: Some("stopped before restart".to_string()),
+                            start_time: Some(now),
+                            end_time: Some(now),
+                            ..update_params.clone()
+                        })
+                        .await
+                    {
+                        error!("failed update supervisor status {} - {e}", base_job.eid);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn stop_supervisor(dispatch_params: DispatchJobParams, react: React) -> Result<Value> {
+        if react.stop_supervisor(&dispatch_params.base_job.eid).await {
+            return Ok(json!(null));
+        }
+
+        // nothing is supervised on this agent, just correct the status on console
+        let _ = react
+            .send_update_job_msg(UpdateJobParams {
+                base_job: dispatch_params.base_job.to_pure_job(),
+                schedule_status: Some(types::ScheduleStatus::Unscheduled),
+                schedule_id: dispatch_params.schedule_id,
+                bind_namespace: react.namespace.clone(),
+                bind_ip: react.local_ip.clone(),
+                schedule_type: Some(ScheduleType::Supervisor),
+                created_user: dispatch_params.created_user,
+                ..Default::default()
+            })
+            .await?;
+        Ok(json!(null))
+    }
+
     async fn kill(dispatch_params: DispatchJobParams, mut react: React) -> Result<Value> {
         react.kill_job(dispatch_params.base_job.eid.clone()).await;
         Ok(json!(null))
@@ -607,18 +1624,18 @@ impl
 
         if matches!(
             dispatch_params.action,
-            JobAction::StartTimer | JobAction::Exec
+            JobAction::StartTimer | JobAction::Exec | JobAction::StartSupervisor
         ) {
             if let Some(comet_addr) = get_comet_addr() {
-                try_download_file(comet_addr, upload_file).await?;
+                try_download_file(comet_addr, upload_file, &react.transfer).await?;
             }
         }
 
         match dispatch_params.action {
             JobAction::StartTimer => Scheduler::start_timer(dispatch_params, react).await,
             JobAction::StopTimer => Scheduler::stop_timer(dispatch_params, react).await,
-            JobAction::StartSupervisor => todo!(),
-            JobAction::StopSupervisor => todo!(),
+            JobAction::StartSupervisor => Scheduler::start_supervisor(dispatch_params, react).await,
+            JobAction::StopSupervisor => Scheduler::stop_supervisor(dispatch_params, react).await,
             JobAction::Exec => Scheduler::exec(dispatch_params, react).await,
             JobAction::Kill => Scheduler::kill(dispatch_params, react).await,
         }
@@ -629,13 +1646,44 @@ impl
         mut react: React,
     ) -> Result<Value> {
         match action_params.action {
-            RuntimeAction::StopTimer => react.remove_job_schedule(&action_params.eid).await?,
-            RuntimeAction::StopSupervisor => todo!(),
+            RuntimeAction::StopTimer => {
+                react.remove_job_schedule(&action_params.eid).await?;
+                react.store.remove_timer(&action_params.eid).await;
+            }
+            RuntimeAction::StopSupervisor => {
+                react.stop_supervisor(&action_params.eid).await;
+            }
             RuntimeAction::Kill => react.kill_job(action_params.eid).await,
         };
         Ok(json!(null))
     }
 
+    pub async fn list_runtime_state(_req: ListRuntimeStateParams, react: React) -> Result<Value> {
+        Ok(serde_json::to_value(react.runtime_state().await)?)
+    }
+
+    /// read the full output file of a run, only files under the output dir are allowed
+    pub async fn read_output(req: ReadOutputParams, react: React) -> Result<Value> {
+        let root = fs::canonicalize(output_file_root(&react.output_dir)).await?;
+        let filepath = fs::canonicalize(&req.filepath).await?;
+        if !filepath.starts_with(&root) {
+            anyhow::bail!("forbidden to read {}", req.filepath);
+        }
+
+        let mut file = fs::File::open(&filepath).await?;
+        let size = file.metadata().await?.len();
+        let limit = req.limit.min(MAX_READ_OUTPUT_SIZE);
+        file.seek(SeekFrom::Start(req.offset)).await?;
+        let mut data = Vec::new();
+        file.take(limit).read_to_end(&mut data).await?;
+
+        Ok(json!({
+            "size": size,
+            "offset": req.offset,
+            "data": String::from_utf8_lossy(&data),
+        }))
+    }
+
     pub async fn sftp_read_dir(req: SftpReadDirParams) -> Result<Value> {
         let ret = ssh::read_dir(
             &req.ip,
@@ -649,24 +1697,76 @@ impl
         Ok(ret)
     }
 
-    pub async fn sftp_upload(req: SftpUploadParams) -> Result<Value> {
+    pub async fn sftp_upload(req: SftpUploadParams, react: React) -> Result<Value> {
+        let Some(transfer_id) = req.transfer_id else {
+            let ret = ssh::upload(
+                &req.ip,
+                req.port,
+                &req.user,
+                &req.password,
+                &req.filepath,
+                req.data.as_slice(),
+            )
+            .await?;
+            return Ok(serde_json::to_value(ret)?);
+        };
+
+        let file = fs::File::open(react.transfer.path(&transfer_id)?).await?;
         let ret = ssh::upload(
             &req.ip,
             req.port,
             &req.user,
             &req.password,
             &req.filepath,
-            req.data,
+            file,
+        )
+        .await;
+        if let Err(e) = react.transfer.remove(&transfer_id).await {
+            warn!("failed remove transfer {transfer_id} - {e}");
+        }
+        Ok(serde_json::to_value(ret?)?)
+    }
+
+    /// with a transfer id the file is kept on the agent and read in chunks later
+    pub async fn sftp_download(req: SftpDownloadParams, react: React) -> Result<Value> {
+        let Some(transfer_id) = req.transfer_id else {
+            let ret =
+                ssh::download(&req.ip, req.port, &req.user, &req.password, &req.filepath).await?;
+            return Ok(serde_json::to_value(ret)?);
+        };
+
+        let mut file = fs::File::create(react.transfer.part_path(&transfer_id)?).await?;
+        ssh::download_to(
+            &req.ip,
+            req.port,
+            &req.user,
+            &req.password,
+            &req.filepath,
+            &mut file,
         )
         .await?;
-        let ret = serde_json::to_value(ret)?;
-        Ok(ret)
+        let ack = react.transfer.complete(&transfer_id).await?;
+        Ok(serde_json::to_value(ack)?)
     }
 
-    pub async fn sftp_download(req: SftpDownloadParams) -> Result<Value> {
-        let ret = ssh::download(&req.ip, req.port, &req.user, &req.password, &req.filepath).await?;
-        let ret = serde_json::to_value(ret)?;
-        Ok(ret)
+    pub async fn transfer_chunk(req: TransferChunkParams, react: React) -> Result<Value> {
+        let ack = react.transfer.write_chunk(req).await?;
+        Ok(serde_json::to_value(ack)?)
+    }
+
+    pub async fn transfer_status(req: TransferParams, react: React) -> Result<Value> {
+        let ack = react.transfer.status(&req.transfer_id).await?;
+        Ok(serde_json::to_value(ack)?)
+    }
+
+    pub async fn transfer_read(req: TransferReadParams, react: React) -> Result<Value> {
+        let chunk = react.transfer.read_chunk(req).await?;
+        Ok(serde_json::to_value(chunk)?)
+    }
+
+    pub async fn transfer_remove(req: TransferParams, react: React) -> Result<Value> {
+        react.transfer.remove(&req.transfer_id).await?;
+        Ok(json!(null))
     }
 
     pub async fn sftp_remove(req: SftpRemoveParams) -> Result<Value> {
@@ -683,32 +1783,41 @@ impl
         Ok(ret)
     }
 
-    pub async fn handle(msg: MsgReqKind, _bridge: Bridge, react: React) -> Value {
+    /// the errors of the job are replied as response with their code,
+    /// only the requests the agent cannot handle are replied as error
+    pub async fn handle(msg: MsgReqKind, _bridge: Bridge, react: React) -> Result<Value, MsgError> {
         let ret = match msg {
             MsgReqKind::DispatchJobRequest(v) => Self::dispath_job(v, react.clone()).await,
             MsgReqKind::RuntimeActionRequest(v) => Self::runtime_action(v, react.clone()).await,
             MsgReqKind::SftpReadDirRequest(v) => Self::sftp_read_dir(v).await,
-            MsgReqKind::SftpUploadRequest(v) => Self::sftp_upload(v).await,
+            MsgReqKind::SftpUploadRequest(v) => Self::sftp_upload(v, react.clone()).await,
             MsgReqKind::SftpRemoveRequest(v) => Self::sftp_remove(v).await,
-            MsgReqKind::SftpDownloadRequest(v) => Self::sftp_download(v).await,
-            MsgReqKind::PullJobRequest(_) => todo!(),
-            MsgReqKind::HeartbeatRequest(_) => todo!(),
-            _ => todo!(),
+            MsgReqKind::SftpDownloadRequest(v) => Self::sftp_download(v, react.clone()).await,
+            MsgReqKind::ReadOutputRequest(v) => Self::read_output(v, react.clone()).await,
+            MsgReqKind::ListRuntimeStateRequest(v) => {
+                Self::list_runtime_state(v, react.clone()).await
+            }
+            MsgReqKind::TransferChunkRequest(v) => Self::transfer_chunk(v, react.clone()).await,
+            MsgReqKind::TransferStatusRequest(v) => Self::transfer_status(v, react.clone()).await,
+            MsgReqKind::TransferReadRequest(v) => Self::transfer_read(v, react.clone()).await,
+            MsgReqKind::TransferRemoveRequest(v) => Self::transfer_remove(v, react.clone()).await,
+            v => {
+                warn!("unsupported request {}", v.name());
+                return Err(MsgError::unsupported(&v));
+            }
         };
 
-        match ret {
-            Ok(v) => {
-                json!({
-                    "code": 20000,
-                    "msg": "success",
-                    "data": v,
-                })
-            }
+        Ok(match ret {
+            Ok(v) => json!({
+                "code": code::SUCCESS,
+                "msg": "success",
+                "data": v,
+            }),
             Err(e) => json!({
-                "code":50000,
-                "msg":e.to_string(),
+                "code": code::INTERNAL_ERROR,
+                "msg": e.to_string(),
             }),
-        }
+        })
     }
 
     pub async fn recv(&mut self, react: React) {
@@ -761,6 +1870,8 @@ impl
         )
         .await;
         let mut react_clone: React = react.clone();
+        react.spawn_outbox_replay();
+        react.restore().await;
 
         self.ssh_poll().await;
 
@@ -775,8 +1886,9 @@ impl
             self.recv(react.clone()).await;
             info!("reconnect after 1s");
             sleep(Duration::from_secs(1)).await;
-            if let Err(e) = self.connect_comet().await {
-                error!("failed reconnect to comet {:?} - {e}", self.comet_addr);
+            match self.connect_comet().await {
+                Ok(_) => react.outbox.notify(),
+                Err(e) => error!("failed reconnect to comet {:?} - {e}", self.comet_addr),
             }
         }
     }
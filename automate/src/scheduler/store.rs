@@ -0,0 +1,177 @@
+use std::{collections::HashMap, io, path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+use tracing::{error, info};
+
+use crate::bridge::msg::DispatchJobParams;
+
+const STATE_FILE: &str = "runtime.json";
+
+/// Timers and supervisors of the agent keyed by job eid
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct RuntimeState {
+    #[serde(default)]
+    pub timers: HashMap<String, DispatchJobParams>,
+    #[serde(default)]
+    pub supervisors: HashMap<String, DispatchJobParams>,
+    /// last fire time of the timers, used to find the missed runs after restart
+    #[serde(default)]
+    pub fired: HashMap<String, DateTime<Utc>>,
+}
+
+/// The runtime state persisted under the output dir, so that the timers and supervisors
+/// are restored after the agent restarts. The file holds secrets, it is only readable by owner
+#[derive(Clone)]
+pub struct RuntimeStore {
+    path: PathBuf,
+    state: Arc<Mutex<RuntimeState>>,
+}
+
+impl RuntimeStore {
+    /// load the saved state, a broken file is discarded
+    pub async fn open(output_dir: &str) -> Self {
+        let path = PathBuf::from(output_dir).join(STATE_FILE);
+        let state = match fs::read(&path).await {
+            Ok(v) => serde_json::from_slice(&v).unwrap_or_else(|e| {
+                error!("discard broken runtime state {} - {e}", path.display());
+                RuntimeState::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => RuntimeState::default(),
+            Err(e) => {
+                error!("failed read runtime state {} - {e}", path.display());
+                RuntimeState::default()
+            }
+        };
+        info!(
+            "loaded runtime state, timers: {}, supervisors: {}",
+            state.timers.len(),
+            state.supervisors.len()
+        );
+
+        Self {
+            path,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub async fn state(&self) -> RuntimeState {
+        self.state.lock().await.clone()
+    }
+
+    pub async fn add_timer(&self, params: &DispatchJobParams) {
+        self.update(|v| {
+            v.timers
+                .insert(params.base_job.eid.clone(), params.clone())
+                .map_or(true, |old| &old != params)
+        })
+        .await
+    }
+
+    pub async fn remove_timer(&self, eid: &str) {
+        self.update(|v| v.timers.remove(eid).is_some() | v.fired.remove(eid).is_some())
+            .await
+    }
+
+    /// start to track the fire time of a new timer, so that it misses runs even if it never fired
+    pub async fn init_fired(&self, eid: &str, time: DateTime<Utc>) {
+        self.update(|v| {
+            v.timers.contains_key(eid) && !v.fired.contains_key(eid) && {
+                v.fired.insert(eid.to_string(), time);
+                true
+            }
+        })
+        .await
+    }
+
+    pub async fn set_fired(&self, eid: &str, time: DateTime<Utc>) {
+        self.update(|v| {
+            v.timers.contains_key(eid)
+                && v.fired
+                    .insert(eid.to_string(), time)
+                    .map_or(true, |old| old != time)
+        })
+        .await
+    }
+
+    pub async fn add_supervisor(&self, params: &DispatchJobParams) {
+        self.update(|v| {
+            v.supervisors
+                .insert(params.base_job.eid.clone(), params.clone())
+                .map_or(true, |old| &old != params)
+        })
+        .await
+    }
+
+    pub async fn remove_supervisor(&self, eid: &str) {
+        self.update(|v| v.supervisors.remove(eid).is_some()).await
+    }
+
+    /// apply the change and write the state if it is changed,
+    /// a failed write is only logged so that the job is still scheduled
+    async fn update(&self, f: impl FnOnce(&mut RuntimeState) -> bool) {
+        let mut state = self.state.lock().await;
+        if !f(&mut state) {
+            return;
+        }
+        if let Err(e) = self.write(&state).await {
+            error!("failed save runtime state {} - {e}", self.path.display());
+        }
+    }
+
+    /// write to a temporary file and rename it, the saved state is never half written
+    async fn write(&self, state: &RuntimeState) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .await?;
+        file.write_all(&serde_json::to_vec(state)?).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_runtime_store() {
+    let dir = std::env::temp_dir().join(format!("jiascheduler-store-{}", std::process::id()));
+    let output_dir = dir.to_string_lossy().to_string();
+    let params = DispatchJobParams {
+        base_job: crate::scheduler::types::BaseJob {
+            eid: "job1".to_string(),
+            ..Default::default()
+        },
+        schedule_id: "s1".to_string(),
+        fields: None,
+        timer_expr: Some("* * * * * *".to_string()),
+        is_sync: false,
+        created_user: "admin".to_string(),
+        action: crate::scheduler::types::JobAction::StartTimer,
+        supervisor_option: None,
+        timer_option: None,
+        env: HashMap::new(),
+        secrets: HashMap::new(),
+    };
+
+    let store = RuntimeStore::open(&output_dir).await;
+    store.add_timer(&params).await;
+    store.set_fired("job1", Utc::now()).await;
+    store.set_fired("job2", Utc::now()).await;
+    store.add_supervisor(&params).await;
+    store.remove_supervisor("job1").await;
+
+    let state = RuntimeStore::open(&output_dir).await.state().await;
+    assert_eq!(state.timers.get("job1"), Some(&params));
+    assert!(state.supervisors.is_empty());
+    assert!(state.fired.contains_key("job1") && !state.fired.contains_key("job2"));
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,278 @@
+use std::{collections::VecDeque, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use rand::Rng;
+
+use super::types::{Calendar, CalendarPolicy};
+
+/// max days to look for the next business day when a run is shifted
+const MAX_SHIFT_DAYS: u64 = 366;
+
+pub fn parse_time_zone(time_zone: &str) -> Result<Tz> {
+    time_zone
+        .parse::<Tz>()
+        .map_err(|e| anyhow!("invalid time zone {time_zone} - {e}"))
+}
+
+pub fn parse_timer_expr(timer_expr: &str) -> Result<Schedule> {
+    Schedule::from_str(timer_expr)
+        .map_err(|e| anyhow!("failed parse timer expr {timer_expr} - {e}"))
+}
+
+/// the next fire times of the timer expr in the time zone after since
+pub fn next_fire_times<Z: TimeZone>(
+    timer_expr: &str,
+    tz: &Z,
+    since: DateTime<Utc>,
+    num: usize,
+) -> Result<Vec<DateTime<Z>>> {
+    let schedule = parse_timer_expr(timer_expr)?;
+    Ok(schedule.after(&since.with_timezone(tz)).take(num).collect())
+}
+
+/// random delay of a fire within the splay seconds
+pub fn splay_delay(splay: u64) -> Duration {
+    if splay == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::thread_rng().gen_range(0..=splay * 1000))
+}
+
+/// Fire times of a timer missed in a period
+#[derive(Debug, Default, PartialEq)]
+pub struct Missed {
+    /// number of all the missed fire times
+    pub count: usize,
+    /// the latest missed fire times in order, at most the limit
+    pub times: Vec<DateTime<Utc>>,
+}
+
+/// find the fire times of the timer expr in the time zone after since and not after until
+pub fn missed_times<Z: TimeZone>(
+    timer_expr: &str,
+    tz: &Z,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    limit: usize,
+) -> Result<Missed> {
+    let schedule = parse_timer_expr(timer_expr)?;
+
+    let mut missed = Missed::default();
+    let mut times = VecDeque::with_capacity(limit);
+    for v in schedule
+        .after(&since.with_timezone(tz))
+        .map(|v| v.with_timezone(&Utc))
+        .take_while(|v| *v <= until)
+    {
+        missed.count += 1;
+        if limit == 0 {
+            continue;
+        }
+        if times.len() == limit {
+            times.pop_front();
+        }
+        times.push_back(v);
+    }
+    missed.times = times.into();
+    Ok(missed)
+}
+
+/// What to do with a fire of a timer according to its calendar
+#[derive(Debug, PartialEq)]
+pub enum CalendarCheck {
+    Run,
+    /// skip the run for the reason
+    Skip(String),
+    /// run at the time instead for the reason
+    Shift(DateTime<Utc>, String),
+}
+
+/// check the fire time against the calendar in the time zone of the timer
+pub fn check_calendar(
+    calendar: &Calendar,
+    timer_expr: &str,
+    tz: Option<Tz>,
+    time: DateTime<Utc>,
+) -> CalendarCheck {
+    match tz {
+        Some(tz) => check_calendar_tz(calendar, timer_expr, &tz, time),
+        None => check_calendar_tz(calendar, timer_expr, &Local, time),
+    }
+}
+
+fn check_calendar_tz<Z: TimeZone>(
+    calendar: &Calendar,
+    timer_expr: &str,
+    tz: &Z,
+    time: DateTime<Utc>,
+) -> CalendarCheck {
+    if let Some(reason) = blackout_reason(calendar, time) {
+        return CalendarCheck::Skip(reason);
+    }
+
+    let local = time.with_timezone(tz);
+    let date = local.date_naive();
+    let Some(reason) = excluded_reason(calendar, date) else {
+        return CalendarCheck::Run;
+    };
+    if calendar.policy == CalendarPolicy::Skip {
+        return CalendarCheck::Skip(reason);
+    }
+
+    let target = (1..=MAX_SHIFT_DAYS)
+        .filter_map(|v| date.checked_add_days(Days::new(v)))
+        .find(|v| excluded_reason(calendar, *v).is_none())
+        .and_then(|v| tz.from_local_datetime(&v.and_time(local.time())).earliest())
+        .map(|v| v.with_timezone(&Utc));
+
+    match target {
+        None => CalendarCheck::Skip(format!(
+            "{reason}, no business day in {MAX_SHIFT_DAYS} days"
+        )),
+        Some(target) => match blackout_reason(calendar, target) {
+            Some(v) => CalendarCheck::Skip(format!("{reason}, shifted run is {v}")),
+            // the timer runs at the time anyway, the shifted run is merged into it
+            None if fires_at(timer_expr, tz, target) => {
+                CalendarCheck::Skip(format!("{reason}, merged into the run at {target}"))
+            }
+            None => CalendarCheck::Shift(target, reason),
+        },
+    }
+}
+
+fn blackout_reason(calendar: &Calendar, time: DateTime<Utc>) -> Option<String> {
+    calendar
+        .blackout_windows
+        .iter()
+        .find(|v| v.start <= time && time < v.end)
+        .map(|v| {
+            format!(
+                "in blackout window {} - {} of calendar {} {}",
+                v.start, v.end, calendar.name, v.reason
+            )
+            .trim_end()
+            .to_string()
+        })
+}
+
+fn excluded_reason(calendar: &Calendar, date: NaiveDate) -> Option<String> {
+    if calendar.holidays.contains(&date) {
+        return Some(format!("{date} is a holiday of calendar {}", calendar.name));
+    }
+    let weekday = date.weekday().number_from_monday() as u8;
+    if !calendar.business_days.is_empty() && !calendar.business_days.contains(&weekday) {
+        return Some(format!(
+            "{date} is not a business day of calendar {}",
+            calendar.name
+        ));
+    }
+    None
+}
+
+fn fires_at<Z: TimeZone>(timer_expr: &str, tz: &Z, time: DateTime<Utc>) -> bool {
+    let Ok(schedule) = parse_timer_expr(timer_expr) else {
+        return false;
+    };
+    let since = (time - chrono::Duration::seconds(1)).with_timezone(tz);
+    schedule
+        .after(&since)
+        .next()
+        .is_some_and(|v| v.with_timezone(&Utc) == time)
+}
+
+#[test]
+fn test_missed_times() {
+    let since = DateTime::parse_from_rfc3339("2024-01-01T00:00:30Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let until = since + chrono::Duration::minutes(5);
+
+    let missed = missed_times("0 * * * * *", &Utc, since, until, 2).unwrap();
+    assert_eq!(missed.count, 5);
+    assert_eq!(
+        missed.times,
+        vec![
+            until - chrono::Duration::seconds(90),
+            until - chrono::Duration::seconds(30)
+        ]
+    );
+    assert_eq!(
+        missed_times("0 * * * * *", &Utc, until, until, 2)
+            .unwrap()
+            .count,
+        0
+    );
+}
+
+#[test]
+fn test_time_zone() {
+    let tz = parse_time_zone("Asia/Shanghai").unwrap();
+    let since = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let missed = missed_times(
+        "0 0 8 * * *",
+        &tz,
+        since,
+        since + chrono::Duration::days(1),
+        1,
+    )
+    .unwrap();
+    assert_eq!(missed.times, vec![since + chrono::Duration::days(1)]);
+    assert!(parse_time_zone("Mars/Olympus").is_err());
+    assert!(splay_delay(2) <= Duration::from_secs(2));
+}
+
+#[test]
+fn test_check_calendar() {
+    use super::types::BlackoutWindow;
+
+    let time = |v: &str| DateTime::parse_from_rfc3339(v).unwrap().with_timezone(&Utc);
+    let mut calendar = Calendar {
+        name: "cn".to_string(),
+        holidays: vec![NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()],
+        business_days: vec![1, 2, 3, 4, 5],
+        blackout_windows: vec![BlackoutWindow {
+            start: time("2024-01-03T00:00:00Z"),
+            end: time("2024-01-04T00:00:00Z"),
+            reason: "upgrade".to_string(),
+        }],
+        policy: CalendarPolicy::Skip,
+    };
+    let utc = Some(Tz::UTC);
+    // monday 2024-01-01 is a holiday
+    assert!(matches!(
+        check_calendar(&calendar, "0 0 10 * * *", utc, time("2024-01-01T10:00:00Z")),
+        CalendarCheck::Skip(_)
+    ));
+    assert_eq!(
+        check_calendar(&calendar, "0 0 10 * * *", utc, time("2024-01-02T10:00:00Z")),
+        CalendarCheck::Run
+    );
+    assert!(matches!(
+        check_calendar(&calendar, "0 0 10 * * *", utc, time("2024-01-03T10:00:00Z")),
+        CalendarCheck::Skip(_)
+    ));
+
+    calendar.policy = CalendarPolicy::Shift;
+    // a saturday run is shifted to monday, unless the timer runs on monday anyway
+    assert!(matches!(
+        check_calendar(&calendar, "0 0 10 * * *", utc, time("2024-01-06T10:00:00Z")),
+        CalendarCheck::Skip(_)
+    ));
+    assert_eq!(
+        check_calendar(
+            &calendar,
+            "0 0 10 * * Sat",
+            utc,
+            time("2024-01-06T10:00:00Z")
+        ),
+        CalendarCheck::Shift(
+            time("2024-01-08T10:00:00Z"),
+            "2024-01-06 is not a business day of calendar cn".to_string()
+        )
+    );
+}
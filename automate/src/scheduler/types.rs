@@ -1,6 +1,7 @@
-use std::{collections::HashMap, fmt, process::Output};
+use std::{collections::HashMap, fmt, process::ExitStatus, time::Duration};
 
 use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Copy)]
@@ -22,6 +23,8 @@ impl TryFrom<&str> for JobAction {
             "kill" => JobAction::Kill,
             "start_timer" => JobAction::StartTimer,
             "stop_timer" => JobAction::StopTimer,
+            "start_supervisor" => JobAction::StartSupervisor,
+            "stop_supervisor" => JobAction::StopSupervisor,
             _ => return Err(anyhow!("invalid job action {value}")),
         };
 
@@ -64,6 +67,16 @@ pub enum RunStatus {
     #[default]
     Prepare,
     Running,
+    /// the attempt failed and the job will be retried after backoff
+    Retrying,
+    /// waiting for a free slot, see [ConcurrencyPolicy::Queue]
+    Queued,
+    /// dropped because max_parallel was reached, see [ConcurrencyPolicy::Skip]
+    Skipped,
+    /// the supervised process exited and will be restarted after backoff
+    Restarting,
+    /// the timer did not fire because the agent was offline, see [MisfirePolicy]
+    Missed,
     Stop,
 }
 
@@ -72,6 +85,11 @@ impl fmt::Display for RunStatus {
         match self {
             RunStatus::Prepare => write!(f, "prepare"),
             RunStatus::Running => write!(f, "running"),
+            RunStatus::Retrying => write!(f, "retrying"),
+            RunStatus::Queued => write!(f, "queued"),
+            RunStatus::Skipped => write!(f, "skipped"),
+            RunStatus::Restarting => write!(f, "restarting"),
+            RunStatus::Missed => write!(f, "missed"),
             RunStatus::Stop => write!(f, "stop"),
         }
     }
@@ -107,8 +125,44 @@ pub struct BaseJob {
     pub timeout: u64,
     pub work_dir: Option<String>,
     pub work_user: Option<String>,
+    /// run with a login shell style environment instead of the environment of the agent
+    #[serde(default)]
+    pub login_env: bool,
     pub max_retry: u8,
     pub max_parallel: u8,
+    #[serde(default)]
+    pub retry_backoff: RetryBackoff,
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+    /// bytes of stdout and stderr kept each, half from the head and half from the tail,
+    /// 0 means no limit
+    #[serde(default = "default_max_output_size")]
+    pub max_output_size: u64,
+    /// seconds to wait after SIGTERM before SIGKILL when the job is killed or timed out
+    #[serde(default = "default_kill_grace_period")]
+    pub kill_grace_period: u64,
+    #[serde(default)]
+    pub resource_limits: ResourceLimits,
+    #[serde(default)]
+    pub bundle_mode: BundleMode,
+    /// max entries of a bundle running at the same time, 0 means no limit
+    #[serde(default)]
+    pub bundle_concurrency: u32,
+}
+
+pub const DEFAULT_MAX_OUTPUT_SIZE: u64 = 64 << 10;
+
+/// replaces the value of secrets in output and stored data
+pub const SECRET_MASK: &str = "******";
+
+fn default_max_output_size() -> u64 {
+    DEFAULT_MAX_OUTPUT_SIZE
+}
+
+pub const DEFAULT_KILL_GRACE_PERIOD: u64 = 10;
+
+fn default_kill_grace_period() -> u64 {
+    DEFAULT_KILL_GRACE_PERIOD
 }
 
 impl BaseJob {
@@ -125,24 +179,431 @@ impl BaseJob {
             timeout: self.timeout,
             work_dir: self.work_dir.clone(),
             work_user: self.work_user.clone(),
+            login_env: self.login_env,
             max_retry: self.max_retry,
             max_parallel: self.max_parallel,
+            retry_backoff: self.retry_backoff.clone(),
+            concurrency_policy: self.concurrency_policy,
+            max_output_size: self.max_output_size,
+            kill_grace_period: self.kill_grace_period,
+            resource_limits: self.resource_limits.clone(),
+            bundle_mode: self.bundle_mode,
+            bundle_concurrency: self.bundle_concurrency,
+        }
+    }
+
+    /// the longest time a run takes with its retries and backoff,
+    /// none if it is unbounded, e.g. no timeout or waiting in the queue
+    pub fn max_run_time(&self) -> Option<Duration> {
+        if self.timeout == 0
+            || (self.max_parallel > 0 && self.concurrency_policy == ConcurrencyPolicy::Queue)
+        {
+            return None;
+        }
+        let attempt = Duration::from_secs(self.timeout.saturating_add(self.kill_grace_period))
+            .saturating_mul(self.bundle_rounds());
+        let backoff = (1..=self.max_retry)
+            .map(|v| self.retry_backoff.delay(v))
+            .fold(Duration::ZERO, Duration::saturating_add);
+        Some(
+            attempt
+                .saturating_mul(self.max_retry as u32 + 1)
+                .saturating_add(backoff),
+        )
+    }
+
+    /// how many entries of the bundle run one after another in the worst case,
+    /// the timeout applies to each entry, a dag may be a single chain of dependencies
+    fn bundle_rounds(&self) -> u32 {
+        let Some(ref entries) = self.bundle_script else {
+            return 1;
+        };
+        let len = u32::try_from(entries.len()).unwrap_or(u32::MAX).max(1);
+        match self.bundle_mode {
+            BundleMode::Parallel if self.bundle_concurrency > 0 => {
+                len.div_ceil(self.bundle_concurrency)
+            }
+            BundleMode::Parallel => 1,
+            BundleMode::Sequential | BundleMode::Dag => len,
+        }
+    }
+}
+
+/// What to do with a new run when max_parallel runs of the job are already running,
+/// max_parallel 0 means no limit
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ConcurrencyPolicy {
+    /// start the new run anyway, max_parallel is not enforced
+    Allow,
+    /// drop the new run
+    Skip,
+    /// wait until one of the running runs exits, the waiting runs start in order
+    #[default]
+    Queue,
+    /// kill the oldest running run and start the new one after it exits
+    Replace,
+}
+
+impl TryFrom<&str> for ConcurrencyPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let policy = match value {
+            "allow" => ConcurrencyPolicy::Allow,
+            "skip" => ConcurrencyPolicy::Skip,
+            "queue" => ConcurrencyPolicy::Queue,
+            "replace" => ConcurrencyPolicy::Replace,
+            _ => return Err(anyhow!("invalid concurrency policy {value}")),
+        };
+        Ok(policy)
+    }
+}
+
+impl fmt::Display for ConcurrencyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConcurrencyPolicy::Allow => write!(f, "allow"),
+            ConcurrencyPolicy::Skip => write!(f, "skip"),
+            ConcurrencyPolicy::Queue => write!(f, "queue"),
+            ConcurrencyPolicy::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BackoffStrategy {
+    #[default]
+    Fixed,
+    Exponential,
+}
+
+/// Resource limits of a job process and its children, none means no limit.
+/// memory_max, cpu_quota and pids_max are enforced by cgroups v2 if available,
+/// otherwise memory_max falls back to setrlimit with RLIMIT_AS, cpu_quota and pids_max
+/// have no fallback, RLIMIT_NPROC counts every process of the user rather than the job
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// bytes of memory
+    pub memory_max: Option<u64>,
+    /// percent of one cpu, 150 means 1.5 cpus
+    pub cpu_quota: Option<u32>,
+    pub pids_max: Option<u64>,
+    /// max number of open files
+    pub nofile: Option<u64>,
+    /// bytes of core dump file, 0 disables core dump
+    pub core_size: Option<u64>,
+}
+
+/// Delay between two attempts of a failed job, in seconds
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RetryBackoff {
+    pub strategy: BackoffStrategy,
+    pub delay: u64,
+    pub max_delay: u64,
+}
+
+impl Default for RetryBackoff {
+    fn default() -> Self {
+        Self {
+            strategy: BackoffStrategy::Fixed,
+            delay: 1,
+            max_delay: 60,
+        }
+    }
+}
+
+impl RetryBackoff {
+    /// delay before the given retry, retry starts from 1
+    pub fn delay(&self, retry: u8) -> Duration {
+        let secs = match self.strategy {
+            BackoffStrategy::Fixed => self.delay,
+            BackoffStrategy::Exponential => {
+                let factor = 1u64
+                    .checked_shl(retry.saturating_sub(1) as u32)
+                    .unwrap_or(u64::MAX);
+                self.delay.saturating_mul(factor)
+            }
+        };
+        let secs = if self.max_delay > 0 {
+            secs.min(self.max_delay)
+        } else {
+            secs
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    #[default]
+    Always,
+    OnFailure,
+    Never,
+}
+
+impl RestartPolicy {
+    pub fn should_restart(&self, is_success: bool) -> bool {
+        match self {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => !is_success,
+            RestartPolicy::Never => false,
+        }
+    }
+}
+
+impl TryFrom<&str> for RestartPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let policy = match value {
+            "always" => RestartPolicy::Always,
+            "on_failure" => RestartPolicy::OnFailure,
+            "never" => RestartPolicy::Never,
+            _ => return Err(anyhow!("invalid restart policy {value}")),
+        };
+        Ok(policy)
+    }
+}
+
+/// Options of a supervised long-running process
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SupervisorOption {
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    #[serde(default)]
+    pub restart_backoff: RetryBackoff,
+    /// max consecutive restarts, 0 means no limit
+    #[serde(default)]
+    pub max_restart: u32,
+}
+
+/// What to do with the fire times a timer missed while the agent was not running
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MisfirePolicy {
+    /// report the missed runs only
+    #[default]
+    Skip,
+    /// run once for the latest missed fire time
+    RunOnce,
+    /// run for each missed fire time, up to max_misfire_runs
+    RunAll,
+}
+
+impl TryFrom<&str> for MisfirePolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let policy = match value {
+            "skip" => MisfirePolicy::Skip,
+            "run_once" => MisfirePolicy::RunOnce,
+            "run_all" => MisfirePolicy::RunAll,
+            _ => return Err(anyhow!("invalid misfire policy {value}")),
+        };
+        Ok(policy)
+    }
+}
+
+/// Who owns the clock of a timer
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TimerMode {
+    /// the agent runs a cron and keeps the timer in its runtime state
+    #[default]
+    Agent,
+    /// the console evaluates the timer expr and dispatches an exec on each fire,
+    /// the agent keeps no state of the timer
+    Console,
+}
+
+impl TryFrom<&str> for TimerMode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mode = match value {
+            "agent" => TimerMode::Agent,
+            "console" => TimerMode::Console,
+            _ => return Err(anyhow!("invalid timer mode {value}")),
+        };
+        Ok(mode)
+    }
+}
+
+/// Which targets a console timer runs on at each fire
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetPolicy {
+    /// every online target
+    #[default]
+    All,
+    /// one online target, in turn
+    Any,
+}
+
+impl TryFrom<&str> for TargetPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let policy = match value {
+            "all" => TargetPolicy::All,
+            "any" => TargetPolicy::Any,
+            _ => return Err(anyhow!("invalid target policy {value}")),
+        };
+        Ok(policy)
+    }
+}
+
+pub const DEFAULT_MAX_MISFIRE_RUNS: u32 = 10;
+
+fn default_max_misfire_runs() -> u32 {
+    DEFAULT_MAX_MISFIRE_RUNS
+}
+
+/// Options of a timer
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TimerOption {
+    #[serde(default)]
+    pub misfire_policy: MisfirePolicy,
+    /// max missed runs caught up by [MisfirePolicy::RunAll], the earlier ones are reported missed
+    #[serde(default = "default_max_misfire_runs")]
+    pub max_misfire_runs: u32,
+    /// IANA time zone of the timer expr, e.g. Asia/Shanghai, the agent's local time zone if none
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    /// max seconds of the random delay of each fire, spreads the runs of many hosts
+    #[serde(default)]
+    pub splay: u64,
+    /// dates and windows on which the timer does not run
+    #[serde(default)]
+    pub calendar: Option<Calendar>,
+    #[serde(default)]
+    pub mode: TimerMode,
+    /// targets of each fire in [TimerMode::Console]
+    #[serde(default)]
+    pub target_policy: TargetPolicy,
+}
+
+impl TimerOption {
+    pub fn is_console_mode(option: &Option<TimerOption>) -> bool {
+        option
+            .as_ref()
+            .is_some_and(|v| v.mode == TimerMode::Console)
+    }
+}
+
+impl Default for TimerOption {
+    fn default() -> Self {
+        Self {
+            misfire_policy: MisfirePolicy::default(),
+            max_misfire_runs: DEFAULT_MAX_MISFIRE_RUNS,
+            time_zone: None,
+            splay: 0,
+            calendar: None,
+            mode: TimerMode::default(),
+            target_policy: TargetPolicy::default(),
         }
     }
 }
 
+/// What to do with a fire on a date excluded by the calendar
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarPolicy {
+    /// drop the run and report it skipped
+    #[default]
+    Skip,
+    /// run at the same time of the next business day
+    Shift,
+}
+
+impl TryFrom<&str> for CalendarPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let policy = match value {
+            "skip" => CalendarPolicy::Skip,
+            "shift" => CalendarPolicy::Shift,
+            _ => return Err(anyhow!("invalid calendar policy {value}")),
+        };
+        Ok(policy)
+    }
+}
+
+/// A period in which no run is allowed, e.g. a maintenance window
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct BlackoutWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// A calendar of the console evaluated by the agent on each fire of the timer
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Calendar {
+    pub name: String,
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+    /// ISO weekdays of the business days, 1 is Monday, every day is a business day if empty
+    #[serde(default)]
+    pub business_days: Vec<u8>,
+    /// blackout windows are always skipped, whatever the policy is
+    #[serde(default)]
+    pub blackout_windows: Vec<BlackoutWindow>,
+    #[serde(default)]
+    pub policy: CalendarPolicy,
+}
+
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct BundleScript {
     pub eid: String,
     pub cmd_name: String,
     pub args: Vec<String>,
     pub code: String,
+    /// eid of the entries which must succeed before this one, only used by dag mode
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+/// How the entries of a bundle are executed
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleMode {
+    /// one after another in order
+    #[default]
+    Sequential,
+    /// all at the same time, up to bundle_concurrency
+    Parallel,
+    /// an entry starts once its dependencies succeeded, up to bundle_concurrency
+    Dag,
+}
+
+impl TryFrom<&str> for BundleMode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mode = match value {
+            "sequential" => BundleMode::Sequential,
+            "parallel" => BundleMode::Parallel,
+            "dag" => BundleMode::Dag,
+            _ => return Err(anyhow!("invalid bundle mode {value}")),
+        };
+        Ok(mode)
+    }
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
 pub struct UploadFile {
     pub filename: String,
+    #[serde(default, with = "serde_bytes")]
     pub data: Option<Vec<u8>>,
+    /// the file is pushed to the agent in chunks instead of the data
+    #[serde(default)]
+    pub transfer_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, PartialEq, Deserialize, Default, Clone)]
@@ -151,6 +612,7 @@ pub enum ScheduleType {
     Once,
     Timer,
     Flow,
+    Supervisor,
 }
 
 impl TryFrom<&str> for ScheduleType {
@@ -161,6 +623,7 @@ impl TryFrom<&str> for ScheduleType {
             "once" => ScheduleType::Once,
             "flow" => ScheduleType::Flow,
             "timer" => ScheduleType::Timer,
+            "supervisor" => ScheduleType::Supervisor,
             _ => return Err(anyhow!("invalid schedule type").into()),
         };
         Ok(schedule_type)
@@ -173,33 +636,109 @@ impl fmt::Display for ScheduleType {
             ScheduleType::Once => write!(f, "once"),
             ScheduleType::Timer => write!(f, "timer"),
             ScheduleType::Flow => write!(f, "flow"),
+            ScheduleType::Supervisor => write!(f, "supervisor"),
+        }
+    }
+}
+
+/// Why a process exited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitReason {
+    /// exited on its own
+    Exited,
+    /// terminated because of timeout
+    Timeout,
+    /// terminated by user kill
+    Killed,
+    /// killed by the oom killer of the job cgroup
+    OutOfMemory,
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitReason::Exited => write!(f, "exited"),
+            ExitReason::Timeout => write!(f, "terminated by timeout"),
+            ExitReason::Killed => write!(f, "terminated by user kill"),
+            ExitReason::OutOfMemory => write!(f, "killed by oom"),
+        }
+    }
+}
+
+/// Output of a process, stdout and stderr may be truncated according to max_output_size
+#[derive(Debug, Clone)]
+pub struct JobOutput {
+    pub status: ExitStatus,
+    pub exit_reason: ExitReason,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// total bytes written by the process
+    pub stdout_size: u64,
+    pub stderr_size: u64,
+    /// file on the agent keeping the full output if it was truncated
+    pub stdout_file: Option<String>,
+    pub stderr_file: Option<String>,
+}
+
+impl JobOutput {
+    pub fn exit_status(&self) -> String {
+        match self.exit_reason {
+            ExitReason::Exited => self.status.to_string(),
+            _ => format!("{}, {}", self.exit_reason, self.status),
+        }
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.status.success() {
+            self.status.code()
+        } else {
+            // killed, return 9
+            self.status.code().or(Some(9))
+        }
+    }
+}
+
+/// Output of an entry of a bundle, a failed entry never drops the others
+pub enum BundleEntryOutput {
+    Output(JobOutput),
+    /// the process failed to run
+    Failed(String),
+    /// not started because a dependency failed or the job was killed
+    Skipped(String),
+}
+
+impl BundleEntryOutput {
+    pub fn is_success(&self) -> bool {
+        match self {
+            BundleEntryOutput::Output(v) => v.status.success(),
+            _ => false,
         }
     }
 }
 
 pub enum BundleOutput {
-    Output(Output),
-    Bundle(HashMap<String, Output>),
+    Output(JobOutput),
+    Bundle(HashMap<String, BundleEntryOutput>),
 }
 
 impl BundleOutput {
+    pub fn is_success(&self) -> bool {
+        match self {
+            BundleOutput::Output(v) => v.status.success(),
+            BundleOutput::Bundle(v) => v.values().all(|v| v.is_success()),
+        }
+    }
+
     pub fn get_exit_status(&self) -> Option<String> {
         match self {
-            BundleOutput::Output(v) => Some(v.status.to_string()),
+            BundleOutput::Output(v) => Some(v.exit_status()),
             BundleOutput::Bundle(_) => None,
         }
     }
 
     pub fn get_exit_code(&self) -> Option<i32> {
         match self {
-            BundleOutput::Output(v) => {
-                if v.status.success() {
-                    v.status.code()
-                } else {
-                    // killed, return 9
-                    v.status.code().or(Some(9))
-                }
-            }
+            BundleOutput::Output(v) => v.exit_code(),
             BundleOutput::Bundle(_) => None,
         }
     }
@@ -217,6 +756,34 @@ impl BundleOutput {
             BundleOutput::Bundle(_) => None,
         }
     }
+
+    pub fn get_stdout_size(&self) -> Option<u64> {
+        match self {
+            BundleOutput::Output(v) => Some(v.stdout_size),
+            BundleOutput::Bundle(_) => None,
+        }
+    }
+
+    pub fn get_stderr_size(&self) -> Option<u64> {
+        match self {
+            BundleOutput::Output(v) => Some(v.stderr_size),
+            BundleOutput::Bundle(_) => None,
+        }
+    }
+
+    pub fn get_stdout_file(&self) -> Option<String> {
+        match self {
+            BundleOutput::Output(v) => v.stdout_file.clone(),
+            BundleOutput::Bundle(_) => None,
+        }
+    }
+
+    pub fn get_stderr_file(&self) -> Option<String> {
+        match self {
+            BundleOutput::Output(v) => v.stderr_file.clone(),
+            BundleOutput::Bundle(_) => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -259,3 +826,19 @@ impl AssignUserOption {
         }
     }
 }
+
+#[test]
+fn test_retry_backoff() {
+    let backoff = RetryBackoff {
+        strategy: BackoffStrategy::Exponential,
+        delay: 2,
+        max_delay: 30,
+    };
+    assert_eq!(backoff.delay(1), Duration::from_secs(2));
+    assert_eq!(backoff.delay(3), Duration::from_secs(8));
+    assert_eq!(backoff.delay(10), Duration::from_secs(30));
+    assert_eq!(backoff.delay(255), Duration::from_secs(30));
+
+    let backoff = RetryBackoff::default();
+    assert_eq!(backoff.delay(5), Duration::from_secs(1));
+}
@@ -378,13 +378,15 @@ pub async fn read_dir(
     Ok(ret)
 }
 
-pub async fn upload(
+/// copy the data from the reader to the file, the reader is a local file of a transfer
+/// or the data of the request
+pub async fn upload<R: AsyncRead + Unpin>(
     _ip: &str,
     port: u16,
     user: &str,
     password: &str,
     filepath: &str,
-    data: Vec<u8>,
+    mut reader: R,
 ) -> Result<()> {
     let dir = std::path::Path::new(filepath)
         .parent()
@@ -407,7 +409,8 @@ pub async fn upload(
     }
 
     let mut file = sftp_session.create(filepath).await?;
-    file.write_all(&data).await?;
+    tokio::io::copy(&mut reader, &mut file).await?;
+    file.shutdown().await?;
     Ok(())
 }
 
@@ -456,3 +459,26 @@ pub async fn download(
     let data = sftp_session.read(filepath).await?;
     Ok(data)
 }
+
+/// copy the file to the writer without loading it into memory
+pub async fn download_to<W: AsyncWrite + Unpin>(
+    _ip: &str,
+    port: u16,
+    user: &str,
+    password: &str,
+    filepath: &str,
+    writer: &mut W,
+) -> Result<u64> {
+    let ssh_session = Session::connect(ConnectParams {
+        user,
+        password,
+        addrs: ("127.0.0.1", port),
+    })
+    .await?;
+
+    let sftp_session = ssh_session.sftp_client().await?;
+    let mut file = sftp_session.open(filepath).await?;
+    let size = tokio::io::copy(&mut file, writer).await?;
+    writer.flush().await?;
+    Ok(size)
+}
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS `job_calendar` (
+    `id` bigint(20) unsigned NOT NULL AUTO_INCREMENT COMMENT '自增id',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT '日历名称',
+    `holidays` JSON NULL COMMENT '节假日列表,如["2024-10-01"]',
+    `business_days` JSON NULL COMMENT '工作日,1表示周一,为空表示每天都是工作日',
+    `blackout_windows` JSON NULL COMMENT '禁止运行的时间窗口',
+    `info` varchar(500) NOT NULL DEFAULT '' COMMENT '描述信息',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT '创建人',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT '修改人',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '创建时间',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT '修改时间',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_name` (`name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = '作业日历';
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS `job_console_timer` (
+    `id` bigint(20) unsigned NOT NULL AUTO_INCREMENT COMMENT '自增id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT '执行id',
+    `schedule_id` varchar(40) NOT NULL DEFAULT '' COMMENT '调度id',
+    `timer_expr` varchar(200) NOT NULL DEFAULT '' COMMENT '定时表达式',
+    `is_enabled` tinyint(1) NOT NULL DEFAULT 1 COMMENT '是否启用',
+    `last_target` varchar(100) NOT NULL DEFAULT '' COMMENT '上次执行的节点 namespace:ip',
+    `prev_time` timestamp NULL DEFAULT NULL COMMENT '上次执行时间',
+    `next_time` timestamp NULL DEFAULT NULL COMMENT '下次执行时间',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT '创建人',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT '修改人',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT '创建时间',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT '修改时间',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_eid` (`eid`),
+    KEY `idx_next_time` (`is_enabled`, `next_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = '控制台调度的定时作业';
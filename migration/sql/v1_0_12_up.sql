@@ -0,0 +1,3 @@
+ALTER TABLE `instance`
+ADD COLUMN `protocol_version` int(10) unsigned NOT NULL DEFAULT 0 COMMENT '通信协议版本,0表示未上报版本的旧节点' AFTER `ssh_port`,
+ADD COLUMN `capabilities` JSON NULL COMMENT '节点支持的功能' AFTER `protocol_version`;
@@ -0,0 +1,5 @@
+ALTER TABLE `job`
+DROP COLUMN `retry_backoff`,
+MODIFY COLUMN `max_retry` TINYINT UNSIGNED NOT NULL DEFAULT 1 COMMENT '最大重试次数';
+
+ALTER TABLE `job_exec_history` DROP COLUMN `attempt`, DROP COLUMN `retry_exhausted`;
@@ -0,0 +1,10 @@
+ALTER TABLE `job`
+ADD COLUMN `retry_backoff` JSON DEFAULT NULL COMMENT '重试退避策略' AFTER `max_retry`,
+MODIFY COLUMN `max_retry` TINYINT UNSIGNED NOT NULL DEFAULT 0 COMMENT '最大重试次数';
+
+-- max_retry was never honored before, the existing jobs keep their values but are not
+-- retried until a retry_backoff is saved, see the dispatch of the console
+
+ALTER TABLE `job_exec_history`
+ADD COLUMN `attempt` TINYINT UNSIGNED NOT NULL DEFAULT 1 COMMENT '第几次执行' AFTER `exit_code`,
+ADD COLUMN `retry_exhausted` BOOLEAN NOT NULL DEFAULT false COMMENT '是否已用完重试次数' AFTER `attempt`;
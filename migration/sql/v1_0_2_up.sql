@@ -0,0 +1,4 @@
+-- max_parallel is enforced from now on, the runs over it wait in a queue instead of being dropped,
+-- so a manual exec overlapping a timer run still runs after it
+ALTER TABLE `job`
+ADD COLUMN `concurrency_policy` VARCHAR(20) NOT NULL DEFAULT 'queue' COMMENT '达到最大并发数后的策略 allow/skip/queue/replace' AFTER `max_parallel`;
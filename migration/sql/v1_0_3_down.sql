@@ -0,0 +1,10 @@
+ALTER TABLE `job` DROP COLUMN `max_output_size`;
+
+ALTER TABLE `job_exec_history`
+MODIFY COLUMN `output` text NOT NULL COMMENT '执行输出',
+DROP COLUMN `stdout`,
+DROP COLUMN `stderr`,
+DROP COLUMN `stdout_size`,
+DROP COLUMN `stderr_size`,
+DROP COLUMN `stdout_file`,
+DROP COLUMN `stderr_file`;
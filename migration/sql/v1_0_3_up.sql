@@ -0,0 +1,11 @@
+ALTER TABLE `job`
+ADD COLUMN `max_output_size` BIGINT UNSIGNED NOT NULL DEFAULT 65536 COMMENT '标准输出和标准错误各自保留的最大字节数,0表示不限制' AFTER `timeout`;
+
+ALTER TABLE `job_exec_history`
+MODIFY COLUMN `output` MEDIUMTEXT NOT NULL COMMENT '执行输出,由标准错误和标准输出拼接',
+ADD COLUMN `stdout` MEDIUMTEXT NULL COMMENT '标准输出,超出限制时保留头尾' AFTER `output`,
+ADD COLUMN `stderr` MEDIUMTEXT NULL COMMENT '标准错误输出,超出限制时保留头尾' AFTER `stdout`,
+ADD COLUMN `stdout_size` BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT '标准输出总字节数' AFTER `stderr`,
+ADD COLUMN `stderr_size` BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT '标准错误输出总字节数' AFTER `stdout_size`,
+ADD COLUMN `stdout_file` VARCHAR(500) NOT NULL DEFAULT '' COMMENT 'agent上保存完整标准输出的文件' AFTER `stderr_size`,
+ADD COLUMN `stderr_file` VARCHAR(500) NOT NULL DEFAULT '' COMMENT 'agent上保存完整标准错误输出的文件' AFTER `stdout_file`;
@@ -0,0 +1 @@
+ALTER TABLE `job` DROP COLUMN `kill_grace_period`;
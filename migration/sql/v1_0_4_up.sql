@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `kill_grace_period` INT UNSIGNED NOT NULL DEFAULT 10 COMMENT '终止作业时发送SIGTERM后等待的秒数,超时后发送SIGKILL' AFTER `timeout`;
@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `resource_limits` JSON NULL COMMENT '资源限制,包括内存,CPU,进程数,打开文件数,core文件大小' AFTER `kill_grace_period`;
@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `login_env` BOOLEAN NOT NULL DEFAULT false COMMENT '是否使用执行用户的登录环境变量' AFTER `work_user`;
@@ -0,0 +1,3 @@
+ALTER TABLE `job`
+DROP COLUMN `env`,
+DROP COLUMN `secrets`;
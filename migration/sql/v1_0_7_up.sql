@@ -0,0 +1,3 @@
+ALTER TABLE `job`
+ADD COLUMN `env` JSON NULL COMMENT '环境变量' AFTER `login_env`,
+ADD COLUMN `secrets` JSON NULL COMMENT '加密保存的密钥,以环境变量注入,输出中会被屏蔽' AFTER `env`;
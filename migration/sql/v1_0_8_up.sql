@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `params` JSON NULL COMMENT '作业参数声明,包括名称,类型,默认值,是否必填' AFTER `args`;
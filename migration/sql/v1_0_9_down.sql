@@ -0,0 +1,3 @@
+ALTER TABLE `job`
+DROP COLUMN `bundle_mode`,
+DROP COLUMN `bundle_concurrency`;
@@ -0,0 +1,3 @@
+ALTER TABLE `job`
+ADD COLUMN `bundle_mode` VARCHAR(20) NOT NULL DEFAULT 'sequential' COMMENT '脚本包执行方式,sequential顺序执行,parallel并行执行,dag按依赖执行' AFTER `concurrency_policy`,
+ADD COLUMN `bundle_concurrency` INT UNSIGNED NOT NULL DEFAULT 0 COMMENT '脚本包同时执行的最大脚本数,0表示不限制' AFTER `bundle_mode`;
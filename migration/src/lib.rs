@@ -1,12 +1,38 @@
 pub use sea_orm_migration::prelude::*;
 
 mod v1_0_0_create_table;
+mod v1_0_10_job_calendar;
+mod v1_0_11_job_console_timer;
+mod v1_0_12_instance_capability;
+mod v1_0_1_job_retry;
+mod v1_0_2_job_concurrency;
+mod v1_0_3_job_output;
+mod v1_0_4_job_kill;
+mod v1_0_5_job_resource_limits;
+mod v1_0_6_job_login_env;
+mod v1_0_7_job_env;
+mod v1_0_8_job_params;
+mod v1_0_9_job_bundle_mode;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(v1_0_0_create_table::Migration)]
+        vec![
+            Box::new(v1_0_0_create_table::Migration),
+            Box::new(v1_0_1_job_retry::Migration),
+            Box::new(v1_0_2_job_concurrency::Migration),
+            Box::new(v1_0_3_job_output::Migration),
+            Box::new(v1_0_4_job_kill::Migration),
+            Box::new(v1_0_5_job_resource_limits::Migration),
+            Box::new(v1_0_6_job_login_env::Migration),
+            Box::new(v1_0_7_job_env::Migration),
+            Box::new(v1_0_8_job_params::Migration),
+            Box::new(v1_0_9_job_bundle_mode::Migration),
+            Box::new(v1_0_10_job_calendar::Migration),
+            Box::new(v1_0_11_job_console_timer::Migration),
+            Box::new(v1_0_12_instance_capability::Migration),
+        ]
     }
 }
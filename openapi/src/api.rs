@@ -2,6 +2,7 @@ pub mod executor;
 pub mod file;
 pub mod instance;
 pub mod job;
+pub mod job_log;
 pub mod manage;
 pub mod migration;
 pub mod role;
@@ -60,3 +61,18 @@ impl Validator<String> for OneOfValidator {
         self.0.contains(value)
     }
 }
+
+/// an IANA time zone name, empty means the local time zone
+pub struct TimeZoneValidator;
+
+impl Display for TimeZoneValidator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("TimeZoneValidator")
+    }
+}
+
+impl Validator<String> for TimeZoneValidator {
+    fn check(&self, value: &String) -> bool {
+        value == "" || value.parse::<chrono_tz::Tz>().is_ok()
+    }
+}
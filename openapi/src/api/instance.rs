@@ -33,6 +33,9 @@ pub mod types {
         pub role_id: u64,
         pub role_name: String,
         pub instance_group_id: u64,
+        /// bridge protocol version of the agent, 0 if the agent is older than the version
+        pub protocol_version: u32,
+        pub capabilities: Vec<String>,
         pub created_time: String,
         pub updated_time: String,
     }
@@ -205,6 +208,11 @@ impl InstanceApi {
                 updated_time: local_time!(v.updated_time),
                 sys_user: v.sys_user,
                 info: v.info,
+                protocol_version: v.protocol_version,
+                capabilities: v
+                    .capabilities
+                    .and_then(|v| serde_json::from_value(v).ok())
+                    .unwrap_or_default(),
                 created_time: local_time!(v.created_time),
             })
             .collect();
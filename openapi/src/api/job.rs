@@ -5,13 +5,16 @@ use crate::{
     local_time,
     logic::{
         self,
-        job::types::{BundleScriptRecord, DispatchTarget},
+        job::types::{BundleScriptRecord, DispatchTarget, JobParam as JobParamRecord},
     },
     response::{std_into_error, ApiStdResponse},
     return_ok, AppState, IdGenerator,
 };
 
-use automate::JobAction;
+use automate::{
+    scheduler::types::{DEFAULT_KILL_GRACE_PERIOD, DEFAULT_MAX_OUTPUT_SIZE},
+    JobAction,
+};
 use poem::{session::Session, web::Data, Result};
 use poem_openapi::{param::Query, payload::Json, OpenApi};
 use sea_orm::{ActiveValue::NotSet, Set};
@@ -38,18 +41,93 @@ mod types {
         #[oai(validator(min_length = 1, max_length = 50))]
         pub name: String,
         pub work_user: Option<String>,
+        /// run with a login shell style environment of work_user
+        pub login_env: Option<bool>,
+        /// environment variables of the job process
+        pub env: Option<HashMap<String, String>>,
+        /// injected like env and masked in the output, stored encrypted,
+        /// a value of ****** keeps the saved one
+        pub secrets: Option<HashMap<String, String>>,
         pub work_dir: Option<String>,
         pub timeout: Option<u64>,
+        /// seconds to wait after SIGTERM before SIGKILL
+        #[oai(validator(maximum(value = "3600")))]
+        pub kill_grace_period: Option<u32>,
+        pub resource_limits: Option<ResourceLimits>,
+        /// bytes of stdout and stderr kept each, 0 means no limit
+        #[oai(validator(maximum(value = "8388608")))]
+        pub max_output_size: Option<u64>,
         pub max_retry: Option<u8>,
+        pub retry_backoff: Option<RetryBackoff>,
         pub max_parallel: Option<u8>,
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"allow\", \"skip\", \"queue\", \"replace\"])"
+        ))]
+        pub concurrency_policy: Option<String>,
         pub code: Option<String>,
         pub info: Option<String>,
         pub bundle_script: Option<Vec<BundleScript>>,
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"sequential\", \"parallel\", \"dag\"])"
+        ))]
+        pub bundle_mode: Option<String>,
+        /// max bundle entries running at the same time, 0 means no limit
+        pub bundle_concurrency: Option<u32>,
         pub upload_file: Option<String>,
         #[oai(default)]
         pub is_public: Option<bool>,
         pub display_on_dashboard: Option<bool>,
         pub args: Option<HashMap<String, String>>,
+        /// typed params rendered into the code by {{ name }} or {{ name | quote }}
+        pub params: Option<Vec<JobParam>>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct JobParam {
+        pub name: String,
+        #[oai(
+            rename = "type",
+            validator(
+                custom = "crate::api::OneOfValidator::new(vec![\"string\", \"int\", \"bool\", \"enum\"])"
+            )
+        )]
+        #[serde(rename = "type")]
+        pub kind: String,
+        pub default: Option<Value>,
+        #[oai(default)]
+        pub required: bool,
+        /// allowed values of enum
+        #[oai(default)]
+        pub options: Vec<String>,
+        #[oai(default)]
+        pub info: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct RetryBackoff {
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"fixed\", \"exponential\"])"
+        ))]
+        pub strategy: String,
+        /// delay before retry, in seconds
+        pub delay: u64,
+        /// upper bound of the delay, in seconds
+        pub max_delay: u64,
+    }
+
+    /// resource limits of the job process, none means no limit
+    #[derive(Object, Serialize, Default)]
+    pub struct ResourceLimits {
+        /// bytes of memory
+        pub memory_max: Option<u64>,
+        /// percent of one cpu, 150 means 1.5 cpus
+        #[oai(validator(minimum(value = "1")))]
+        pub cpu_quota: Option<u32>,
+        pub pids_max: Option<u64>,
+        /// max number of open files
+        pub nofile: Option<u64>,
+        /// bytes of core dump file, 0 disables core dump
+        pub core_size: Option<u64>,
     }
 
     #[derive(Object, Serialize, Default)]
@@ -60,6 +138,8 @@ mod types {
         pub executor_id: u64,
         pub code: String,
         pub cond_expr: String,
+        /// eid of the entries which must succeed before this one, used by dag mode
+        pub depends_on: Option<Vec<String>>,
     }
 
     pub fn default_page() -> u64 {
@@ -70,6 +150,10 @@ mod types {
         20
     }
 
+    pub fn default_output_limit() -> u64 {
+        64 << 10
+    }
+
     #[derive(Object, Serialize, Default)]
     pub struct QueryJobResp {
         pub total: u64,
@@ -91,13 +175,25 @@ mod types {
         pub display_on_dashboard: bool,
         pub work_dir: String,
         pub work_user: String,
+        pub login_env: bool,
+        pub env: Option<Value>,
+        /// the values are always masked
+        pub secrets: Option<Value>,
         pub timeout: u64,
+        pub kill_grace_period: u32,
+        pub resource_limits: Option<Value>,
+        pub max_output_size: u64,
         pub max_retry: u8,
+        pub retry_backoff: Option<Value>,
         pub max_parallel: u8,
+        pub concurrency_policy: String,
+        pub bundle_mode: String,
+        pub bundle_concurrency: u32,
         pub created_user: String,
         pub updated_user: String,
         pub upload_file: String,
         pub args: Option<Value>,
+        pub params: Option<Value>,
         pub created_time: String,
         pub updated_time: String,
     }
@@ -145,10 +241,52 @@ mod types {
         pub endpoints: Vec<Endpoint>,
         pub eid: String,
         pub timer_expr: Option<TimerExpr>,
+        pub supervisor_option: Option<SupervisorOption>,
+        pub timer_option: Option<TimerOption>,
+        /// environment variables of this dispatch, override the ones of the job
+        pub env: Option<HashMap<String, String>>,
+        /// values of the params declared by the job
+        pub params: Option<HashMap<String, Value>>,
         pub is_sync: bool,
         pub action: String,
     }
 
+    #[derive(Object, Serialize, Default)]
+    pub struct SupervisorOption {
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"always\", \"on_failure\", \"never\"])"
+        ))]
+        pub restart_policy: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub restart_backoff: Option<RetryBackoff>,
+        /// max consecutive restarts, 0 means no limit
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub max_restart: Option<u32>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct TimerOption {
+        /// what to do with the runs missed while the agent was not running
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"skip\", \"run_once\", \"run_all\"])"
+        ))]
+        pub misfire_policy: String,
+        /// max missed runs caught up by run_all
+        #[oai(validator(maximum(value = "100")))]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub max_misfire_runs: Option<u32>,
+        /// the agent runs a cron, or the console owns the clock and dispatches an exec on each fire
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"agent\", \"console\"])"
+        ))]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub mode: Option<String>,
+        /// run on all the online targets or on one of them in turn, only in console mode
+        #[oai(validator(custom = "crate::api::OneOfValidator::new(vec![\"all\", \"any\"])"))]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub target_policy: Option<String>,
+    }
+
     #[derive(Object, Serialize, Default)]
     pub struct DispatchJobResp {
         pub result: u64,
@@ -208,9 +346,18 @@ mod types {
         pub bundle_script_result: Option<serde_json::Value>,
         pub exit_status: String,
         pub exit_code: i64,
+        pub attempt: u8,
+        pub retry_exhausted: bool,
         pub start_time: Option<String>,
         pub end_time: Option<String>,
         pub output: String,
+        pub stdout: Option<String>,
+        pub stderr: Option<String>,
+        pub stdout_size: u64,
+        pub stderr_size: u64,
+        /// the full output is kept on the agent, see /job/exec-output
+        pub stdout_file: String,
+        pub stderr_file: String,
         pub created_user: String,
         pub created_time: String,
         pub updated_time: String,
@@ -223,6 +370,14 @@ mod types {
         pub list: Vec<ExecRecord>,
     }
 
+    #[derive(Object, Serialize, Default)]
+    pub struct ExecOutputResp {
+        /// total bytes of the output file
+        pub size: u64,
+        pub offset: u64,
+        pub data: String,
+    }
+
     #[derive(Serialize, Default, Enum)]
     pub enum JobAction {
         #[default]
@@ -230,6 +385,8 @@ mod types {
         Exec,
         StopTimer,
         Stop,
+        StartSupervisor,
+        StopSupervisor,
     }
 
     #[derive(Object, Serialize, Default)]
@@ -332,6 +489,21 @@ mod types {
         pub day_of_month: String,
         pub month: String,
         pub year: String,
+        /// IANA time zone, e.g. Asia/Shanghai, the agent's local time zone if it is empty
+        #[oai(validator(custom = "crate::api::TimeZoneValidator"))]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub time_zone: Option<String>,
+        /// max seconds of the random delay of each fire
+        #[oai(validator(maximum(value = "86400")))]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub splay: Option<u64>,
+        /// id of the calendar excluding dates and windows from the runs
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub calendar_id: Option<u64>,
+        /// skip the runs on excluded dates or shift them to the next business day
+        #[oai(validator(custom = "crate::api::OneOfValidator::new(vec![\"skip\", \"shift\"])"))]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub calendar_policy: Option<String>,
     }
 
     impl From<String> for TimerExpr {
@@ -344,12 +516,16 @@ mod types {
                 day_of_month: vec.get(3).map_or("1".to_string(), |&v| v.to_string()),
                 month: vec.get(4).map_or("1".to_string(), |&v| v.to_string()),
                 year: vec.get(5).map_or("1".to_string(), |&v| v.to_string()),
+                time_zone: None,
+                splay: None,
+                calendar_id: None,
+                calendar_policy: None,
             }
         }
     }
 
-    impl Into<String> for TimerExpr {
-        fn into(self) -> String {
+    impl TimerExpr {
+        pub fn to_expr(&self) -> String {
             format!(
                 "{} {} {} {} {} {}",
                 self.second, self.minute, self.hour, self.day_of_month, self.month, self.year
@@ -357,6 +533,84 @@ mod types {
         }
     }
 
+    impl Into<String> for TimerExpr {
+        fn into(self) -> String {
+            self.to_expr()
+        }
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct BlackoutWindow {
+        /// rfc3339 time, e.g. 2024-10-01T00:00:00+08:00
+        pub start: String,
+        pub end: String,
+        #[oai(default)]
+        pub reason: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SaveJobCalendarReq {
+        pub id: Option<u64>,
+        #[oai(validator(min_length = 1, max_length = 100))]
+        pub name: String,
+        /// excluded dates, e.g. 2024-10-01
+        #[oai(default)]
+        pub holidays: Vec<String>,
+        /// 1 is Monday and 7 is Sunday, every day is a business day if empty
+        #[oai(default)]
+        pub business_days: Vec<u8>,
+        #[oai(default)]
+        pub blackout_windows: Vec<BlackoutWindow>,
+        #[oai(default)]
+        pub info: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SaveJobCalendarResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct DeleteJobCalendarReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct JobCalendarRecord {
+        pub id: u64,
+        pub name: String,
+        pub holidays: serde_json::Value,
+        pub business_days: serde_json::Value,
+        pub blackout_windows: serde_json::Value,
+        pub info: String,
+        pub created_user: String,
+        pub updated_user: String,
+        pub created_time: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct QueryJobCalendarResp {
+        pub total: u64,
+        pub list: Vec<JobCalendarRecord>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct PreviewTimerReq {
+        pub timer_expr: TimerExpr,
+        /// number of the next fire times, 5 by default
+        #[oai(validator(minimum(value = "1"), maximum(value = "100")))]
+        pub num: Option<u32>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct PreviewTimerResp {
+        pub description: String,
+        /// the time zone of the fire times, local means the time zone of the console
+        pub time_zone: String,
+        pub next_times: Vec<String>,
+    }
+
     #[derive(Object, Serialize, Default)]
     pub struct SaveJobTimerResp {
         pub result: u64,
@@ -428,8 +682,39 @@ impl JobApi {
             .map(|v| serde_json::to_value(&v))
             .transpose()
             .map_err(std_into_error)?;
+        let retry_backoff = match req.retry_backoff {
+            Some(v) => Some(serde_json::to_value(&v)),
+            // a job saved with retries opts in with the default backoff
+            None if req.max_retry.is_some_and(|v| v > 0) => Some(serde_json::to_value(
+                automate::scheduler::types::RetryBackoff::default(),
+            )),
+            None => None,
+        }
+        .transpose()
+        .map_err(std_into_error)?;
+        let resource_limits = req
+            .resource_limits
+            .map(|v| serde_json::to_value(&v))
+            .transpose()
+            .map_err(std_into_error)?;
+        let env = req
+            .env
+            .map(|v| serde_json::to_value(&v))
+            .transpose()
+            .map_err(std_into_error)?;
+        let params = req
+            .params
+            .map(|v| serde_json::to_value(&v).and_then(serde_json::from_value))
+            .transpose()
+            .map_err(std_into_error)?
+            .map(|v: Vec<JobParamRecord>| {
+                logic::job::params::validate_params(&v)?;
+                serde_json::to_value(&v).map_err(anyhow::Error::from)
+            })
+            .transpose()?;
 
         let svc = state.service();
+        let secrets = svc.job.encrypt_secrets(req.id, req.secrets).await?;
 
         let (job_type, bundle_script) = match req.bundle_script {
             Some(v) => {
@@ -442,6 +727,7 @@ impl JobApi {
                         code: v.code.clone(),
                         info: v.info.clone(),
                         cond_expr: v.cond_expr.clone(),
+                        depends_on: v.depends_on.clone().unwrap_or_default(),
                     })
                     .collect();
 
@@ -469,9 +755,21 @@ impl JobApi {
                 info: Set(req.info.unwrap_or_default()),
                 work_dir: Set(req.work_dir.unwrap_or_default()),
                 work_user: Set(req.work_user.unwrap_or_default()),
-                max_retry: Set(req.max_retry.unwrap_or(1)),
+                login_env: Set(req.login_env.unwrap_or_default()),
+                env: Set(env),
+                secrets: Set(secrets),
+                max_retry: Set(req.max_retry.unwrap_or_default()),
+                retry_backoff: Set(retry_backoff),
                 max_parallel: Set(req.max_parallel.unwrap_or(1)),
+                concurrency_policy: Set(req.concurrency_policy.unwrap_or("queue".to_string())),
+                bundle_mode: Set(req.bundle_mode.unwrap_or("sequential".to_string())),
+                bundle_concurrency: Set(req.bundle_concurrency.unwrap_or_default()),
                 timeout: Set(req.timeout.unwrap_or(60)),
+                kill_grace_period: Set(req
+                    .kill_grace_period
+                    .unwrap_or(DEFAULT_KILL_GRACE_PERIOD as u32)),
+                resource_limits: Set(resource_limits),
+                max_output_size: Set(req.max_output_size.unwrap_or(DEFAULT_MAX_OUTPUT_SIZE)),
                 bundle_script,
                 job_type,
                 upload_file: Set(req.upload_file.unwrap_or_default()),
@@ -483,6 +781,7 @@ impl JobApi {
                 created_user: Set(user_info.username.clone()),
                 updated_user: Set(user_info.username.clone()),
                 args: Set(args),
+                params: Set(params),
                 ..Default::default()
             })
             .await?;
@@ -550,11 +849,22 @@ impl JobApi {
                 created_user: v.created_user,
                 updated_user: v.updated_user,
                 args: v.args,
+                params: v.params,
                 work_dir: v.work_dir,
                 work_user: v.work_user,
+                login_env: v.login_env,
+                env: v.env,
+                secrets: logic::job::mask_secrets(v.secrets),
                 timeout: v.timeout,
+                kill_grace_period: v.kill_grace_period,
+                resource_limits: v.resource_limits,
+                max_output_size: v.max_output_size,
                 max_retry: v.max_retry,
+                retry_backoff: v.retry_backoff,
                 max_parallel: v.max_parallel,
+                concurrency_policy: v.concurrency_policy,
+                bundle_mode: v.bundle_mode,
+                bundle_concurrency: v.bundle_concurrency,
                 upload_file: v.upload_file,
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
@@ -587,6 +897,31 @@ impl JobApi {
         let svc = state.service();
         let action = req.action.as_str().try_into()?;
         let schedule_type = req.schedule_type.as_str().try_into()?;
+        let supervisor_option = req
+            .supervisor_option
+            .map(|v| serde_json::to_value(&v).and_then(serde_json::from_value))
+            .transpose()
+            .map_err(std_into_error)?;
+        let mut timer_option: Option<automate::scheduler::types::TimerOption> = req
+            .timer_option
+            .map(|v| serde_json::to_value(&v).and_then(serde_json::from_value))
+            .transpose()
+            .map_err(std_into_error)?;
+        // the time zone, splay and calendar are part of the timer definition
+        if let Some(ref v) = req.timer_expr {
+            if v.time_zone.is_some() || v.splay.is_some() || v.calendar_id.is_some() {
+                let option = timer_option.get_or_insert_with(Default::default);
+                option.time_zone = v.time_zone.clone().filter(|v| v != "");
+                option.splay = v.splay.unwrap_or_default();
+                if let Some(id) = v.calendar_id {
+                    let policy = v.calendar_policy.as_deref().unwrap_or("skip").try_into()?;
+                    option.calendar = Some(svc.job.get_calendar(id, policy).await?);
+                }
+            }
+        }
+        if let Some(ref v) = req.timer_expr {
+            logic::job::check_timer_expr(&v.to_expr(), v.time_zone.as_deref())?;
+        }
         let secret = state.conf.comet_secret.clone();
         let ret = svc
             .job
@@ -605,6 +940,10 @@ impl JobApi {
                 schedule_type,
                 action,
                 req.timer_expr.map(|v| v.into()),
+                supervisor_option,
+                timer_option,
+                req.env.unwrap_or_default(),
+                req.params.unwrap_or_default(),
                 user_info.username.clone(),
             )
             .await?;
@@ -845,7 +1184,15 @@ impl JobApi {
                 bind_ip: v.bind_ip,
                 exit_status: v.exit_status,
                 exit_code: v.exit_code,
+                attempt: v.attempt,
+                retry_exhausted: v.retry_exhausted,
                 output: v.output,
+                stdout: v.stdout,
+                stderr: v.stderr,
+                stdout_size: v.stdout_size,
+                stderr_size: v.stderr_size,
+                stdout_file: v.stdout_file,
+                stderr_file: v.stderr_file,
                 job_type: v.job_type,
                 created_user: v.created_user,
                 bundle_script_result: v.bundle_script_result,
@@ -862,6 +1209,34 @@ impl JobApi {
         })
     }
 
+    #[oai(path = "/exec-output", method = "get")]
+    pub async fn query_exec_output(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(id): Query<u64>,
+        #[oai(validator(custom = "super::OneOfValidator::new(vec![\"stdout\", \"stderr\"])"))]
+        Query(stream): Query<String>,
+        #[oai(default)] Query(offset): Query<u64>,
+        #[oai(
+            default = "types::default_output_limit",
+            validator(maximum(value = "1048576"))
+        )]
+        Query(limit): Query<u64>,
+    ) -> Result<ApiStdResponse<types::ExecOutputResp>> {
+        let svc = state.service();
+        let ret = svc
+            .job
+            .read_exec_output(id, &stream, offset, limit, user_info.username.clone())
+            .await?;
+
+        return_ok!(types::ExecOutputResp {
+            size: ret.size,
+            offset: ret.offset,
+            data: ret.data,
+        })
+    }
+
     #[oai(path = "/action", method = "post")]
     pub async fn action(
         &self,
@@ -877,6 +1252,8 @@ impl JobApi {
             types::JobAction::Exec => JobAction::Exec,
             types::JobAction::StopTimer => JobAction::StopTimer,
             types::JobAction::Stop => JobAction::Kill,
+            types::JobAction::StartSupervisor => JobAction::StartSupervisor,
+            types::JobAction::StopSupervisor => JobAction::StopSupervisor,
         };
         let ret = svc
             .job
@@ -1075,6 +1452,10 @@ impl JobApi {
         user_info: Data<&logic::types::UserInfo>,
         Json(req): Json<types::SaveJobTimerReq>,
     ) -> Result<ApiStdResponse<types::SaveJobTimerResp>> {
+        logic::job::check_timer_expr(
+            &req.timer_expr.to_expr(),
+            req.timer_expr.time_zone.as_deref(),
+        )?;
         let svc = state.service();
         let ret = svc
             .job
@@ -1098,6 +1479,124 @@ impl JobApi {
         });
     }
 
+    #[oai(path = "/preview-timer", method = "post")]
+    pub async fn preview_timer(
+        &self,
+        _session: &Session,
+        Json(req): Json<types::PreviewTimerReq>,
+    ) -> Result<ApiStdResponse<types::PreviewTimerResp>> {
+        let timer_expr = req.timer_expr.to_expr();
+        let time_zone = req.timer_expr.time_zone.filter(|v| v != "");
+        let next_times = logic::job::next_fire_times(
+            &timer_expr,
+            time_zone.as_deref(),
+            req.num.unwrap_or(5) as usize,
+        )?;
+
+        return_ok!(types::PreviewTimerResp {
+            description: logic::job::describe_timer_expr(&timer_expr)?,
+            time_zone: time_zone.unwrap_or("local".to_string()),
+            next_times,
+        });
+    }
+
+    #[oai(path = "/calendar-list", method = "get")]
+    pub async fn query_calendar(
+        &self,
+        state: Data<&AppState>,
+        _session: &Session,
+        user_info: Data<&logic::types::UserInfo>,
+
+        #[oai(default)] Query(name): Query<Option<String>>,
+
+        #[oai(default = "types::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+
+        #[oai(
+            default = "types::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> Result<ApiStdResponse<types::QueryJobCalendarResp>> {
+        let svc = state.service();
+        let ret = svc
+            .job
+            .query_job_calendar(
+                Some(&user_info.username),
+                name.filter(|v| v != ""),
+                page - 1,
+                page_size,
+            )
+            .await?;
+
+        let list = ret
+            .0
+            .into_iter()
+            .map(|v| types::JobCalendarRecord {
+                id: v.id,
+                name: v.name,
+                holidays: v.holidays.unwrap_or(json!([])),
+                business_days: v.business_days.unwrap_or(json!([])),
+                blackout_windows: v.blackout_windows.unwrap_or(json!([])),
+                info: v.info,
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QueryJobCalendarResp { total: ret.1, list })
+    }
+
+    #[oai(path = "/save-calendar", method = "post")]
+    pub async fn save_calendar(
+        &self,
+        state: Data<&AppState>,
+        _session: &Session,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveJobCalendarReq>,
+    ) -> Result<ApiStdResponse<types::SaveJobCalendarResp>> {
+        let (holidays, business_days, blackout_windows) = logic::job::parse_calendar(
+            req.holidays,
+            req.business_days,
+            req.blackout_windows
+                .into_iter()
+                .map(|v| (v.start, v.end, v.reason))
+                .collect(),
+        )?;
+
+        let svc = state.service();
+        let ret = svc
+            .job
+            .save_job_calendar(crate::entity::job_calendar::ActiveModel {
+                id: req.id.map_or(NotSet, |v| Set(v)),
+                name: Set(req.name),
+                holidays: Set(Some(holidays)),
+                business_days: Set(Some(business_days)),
+                blackout_windows: Set(Some(blackout_windows)),
+                info: Set(req.info),
+                created_user: Set(user_info.username.clone()),
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+
+        return_ok!(types::SaveJobCalendarResp {
+            result: ret.id.as_ref().to_owned()
+        });
+    }
+
+    #[oai(path = "/delete-calendar", method = "post")]
+    pub async fn delete_calendar(
+        &self,
+        state: Data<&AppState>,
+        Json(req): Json<types::DeleteJobCalendarReq>,
+    ) -> Result<ApiStdResponse<u64>> {
+        let svc = state.service();
+        let ret = svc.job.delete_job_calendar(req.id).await?;
+        return_ok!(ret);
+    }
+
     #[oai(path = "/delete-timer", method = "post")]
     pub async fn delete_timer(
         &self,
@@ -0,0 +1,99 @@
+use crate::state::AppState;
+use crate::{logic, return_err_to_wsconn};
+
+use automate::bus::Bus;
+use futures::{SinkExt, StreamExt};
+use poem::session::Session as WebSession;
+use poem::web::websocket::{Message, WebSocket};
+use poem::web::{Data, Path, Query};
+use poem::{handler, IntoResponse};
+
+use tracing::error;
+
+pub mod types {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct TailLogQuery {
+        pub namespace: String,
+        pub ip: String,
+    }
+}
+
+#[handler]
+pub async fn tail(
+    Path(schedule_id): Path<String>,
+    state: Data<&AppState>,
+    _session: &WebSession,
+    user_info: Data<&logic::types::UserInfo>,
+    Query(types::TailLogQuery { namespace, ip }): Query<types::TailLogQuery>,
+    ws: WebSocket,
+) -> impl IntoResponse {
+    let state_clone = state.clone();
+    let username = user_info.username.clone();
+
+    ws.on_upgrade(move |socket| async move {
+        let (mut sink, mut stream) = socket.split();
+
+        match state_clone
+            .service()
+            .job
+            .get_schedule(schedule_id.clone())
+            .await
+        {
+            // the output may contain credentials, only the owner of the schedule can tail it
+            Ok(Some(v)) if v.created_user == username => {}
+            Ok(_) => {
+                return_err_to_wsconn!(sink, "Notice: invalid schedule");
+            }
+            Err(e) => {
+                return_err_to_wsconn!(sink, format!("Notice: failed get schedule, {e}"));
+            }
+        }
+
+        let bus = Bus::new(state_clone.redis().clone());
+        // send the buffered lines first, then follow new lines
+        let mut last_id = "0".to_string();
+        // the run is finished once its last batch is read, the connection is closed
+        // if nothing follows it within a read, e.g. the rest of the buffer or the next run
+        let mut finished = false;
+
+        loop {
+            let ret = tokio::select! {
+                msg = stream.next() => match msg {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                },
+                ret = bus.tail_job_log(&namespace, &ip, &schedule_id, &last_id, 1000) => ret,
+            };
+
+            let list = match ret {
+                Ok((_, list)) if list.is_empty() && finished => break,
+                Ok((id, list)) => {
+                    last_id = id;
+                    list
+                }
+                Err(e) => {
+                    return_err_to_wsconn!(sink, format!("Notice: failed read job log, {e}"));
+                }
+            };
+
+            finished = list.last().is_some_and(|v| v.is_eof);
+            for v in list {
+                let data = match serde_json::to_string(&v) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("failed convert job log to json - {e}");
+                        continue;
+                    }
+                };
+                if let Err(e) = sink.send(Message::Text(data)).await {
+                    error!("failed send job log to ws connection - {e}");
+                    return;
+                }
+            }
+        }
+
+        let _ = sink.close().await;
+    })
+}
@@ -15,6 +15,26 @@ pub struct Admin {
     pub password: String,
 }
 
+/// Reconciliation of the job running status with the state reported by the agents
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Reconcile {
+    /// seconds between two rounds, 0 disables the reconciler
+    pub interval: u64,
+    /// dispatch again or stop the timers and supervisors differing from the console,
+    /// otherwise only the running status is fixed to what the agents report
+    pub repair: bool,
+}
+
+impl Default for Reconcile {
+    fn default() -> Self {
+        Self {
+            interval: 300,
+            repair: false,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Conf {
     /// if enable debug mode
@@ -27,6 +47,8 @@ pub struct Conf {
     pub comet_secret: String,
     pub database_url: String,
     pub admin: Admin,
+    #[serde(default)]
+    pub reconcile: Reconcile,
     #[serde(skip)]
     config_file: String,
 }
@@ -16,6 +16,8 @@ pub struct Model {
     pub sys_user: String,
     pub password: String,
     pub ssh_port: u16,
+    pub protocol_version: u32,
+    pub capabilities: Option<Json>,
     pub created_time: DateTimeUtc,
     pub updated_time: DateTimeUtc,
 }
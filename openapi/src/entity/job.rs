@@ -20,14 +20,25 @@ pub struct Model {
     pub upload_file: String,
     pub work_dir: String,
     pub work_user: String,
+    pub login_env: bool,
+    pub env: Option<Json>,
+    pub secrets: Option<Json>,
     pub timeout: u64,
+    pub kill_grace_period: u32,
+    pub resource_limits: Option<Json>,
+    pub max_output_size: u64,
     pub max_retry: u8,
+    pub retry_backoff: Option<Json>,
     pub max_parallel: u8,
+    pub concurrency_policy: String,
+    pub bundle_mode: String,
+    pub bundle_concurrency: u32,
     pub is_public: i8,
     pub display_on_dashboard: bool,
     pub created_user: String,
     pub updated_user: String,
     pub args: Option<Json>,
+    pub params: Option<Json>,
     pub created_time: DateTimeUtc,
     pub updated_time: DateTimeUtc,
 }
@@ -16,8 +16,18 @@ pub struct Model {
     pub bundle_script_result: Option<Json>,
     pub exit_status: String,
     pub exit_code: i32,
+    pub attempt: u8,
+    pub retry_exhausted: bool,
     #[sea_orm(column_type = "Text")]
     pub output: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub stdout: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub stderr: Option<String>,
+    pub stdout_size: u64,
+    pub stderr_size: u64,
+    pub stdout_file: String,
+    pub stderr_file: String,
     pub start_time: Option<DateTimeUtc>,
     pub end_time: Option<DateTimeUtc>,
     pub created_time: DateTimeUtc,
@@ -10,6 +10,8 @@ pub mod instance_group;
 pub mod instance_role;
 pub mod job;
 pub mod job_bundle_script;
+pub mod job_calendar;
+pub mod job_console_timer;
 pub mod job_exec_history;
 pub mod job_organizer;
 pub mod job_organizer_process;
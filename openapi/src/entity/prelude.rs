@@ -8,6 +8,8 @@ pub use super::instance_group::Entity as InstanceGroup;
 pub use super::instance_role::Entity as InstanceRole;
 pub use super::job::Entity as Job;
 pub use super::job_bundle_script::Entity as JobBundleScript;
+pub use super::job_calendar::Entity as JobCalendar;
+pub use super::job_console_timer::Entity as JobConsoleTimer;
 pub use super::job_exec_history::Entity as JobExecHistory;
 pub use super::job_organizer::Entity as JobOrganizer;
 pub use super::job_organizer_process::Entity as JobOrganizerProcess;
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use automate::code;
 use poem::{error::ResponseError, http::StatusCode, Error as PError, IntoResponse};
 use poem_openapi::payload::Json;
 use std::{error::Error as StdError, ops::Deref};
@@ -76,10 +77,10 @@ macro_rules! define_biz_error {
 }
 
 define_biz_error!(
-    (InvalidJSON, 50003, "Invalid JSON format");
-    (BizError, 50000, "Internal error");
-    (InvalidUser, 50004, "Invalid username or passowrd");
-    (NoPermission, 50005, "This operation is not allowed");
+    (InvalidJSON, code::INVALID_JSON, "Invalid JSON format");
+    (BizError, code::INTERNAL_ERROR, "Internal error");
+    (InvalidUser, code::INVALID_USER, "Invalid username or passowrd");
+    (NoPermission, code::NO_PERMISSION, "This operation is not allowed");
 );
 
 impl ResponseError for BizError {
@@ -94,12 +95,12 @@ pub async fn custom_error(e: PError) -> impl IntoResponse {
     let mut msg = e.to_string();
     if code == 500 {
         status_code = StatusCode::OK;
-        code = 50000
+        code = code::INTERNAL_ERROR
     }
 
     if code == 400 {
         status_code = StatusCode::OK;
-        code = 50400
+        code = code::BAD_REQUEST
     }
 
     if msg.contains("Duplicate entry") {
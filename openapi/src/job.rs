@@ -1,12 +1,19 @@
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
 use automate::{
-    bridge::msg::{AgentOfflineParams, AgentOnlineParams, HeartbeatParams},
+    bridge::msg::{AgentOfflineParams, AgentOnlineParams, HeartbeatParams, UpdateJobParams},
     bus::{Bus, Msg},
 };
 use tracing::{error, info};
 
 use crate::{entity::instance, AppState};
 
+/// interval of checking the due timers scheduled by the console
+const CONSOLE_TIMER_INTERVAL: Duration = Duration::from_secs(1);
+/// interval of checking whether the leader should reconcile, see [crate::config::Reconcile]
+const RECONCILE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
 async fn heartbeat(state: AppState, msg: HeartbeatParams) -> Result<()> {
     state
         .service()
@@ -21,6 +28,28 @@ async fn heartbeat(state: AppState, msg: HeartbeatParams) -> Result<()> {
     Ok(())
 }
 
+/// apply the update once, the agent replays the updates queued in its outbox
+/// and an update may be delivered again after a timeout
+async fn update_job(state: AppState, msg: UpdateJobParams) -> Result<()> {
+    let Some(msg_id) = msg.msg_id.clone() else {
+        state.service().job.update_job_status(msg).await?;
+        return Ok(());
+    };
+
+    let bus = Bus::new(state.redis().clone());
+    if !bus.try_mark_update(&msg_id).await? {
+        info!("skip duplicated job update {msg_id}");
+        return Ok(());
+    }
+    if let Err(e) = state.service().job.update_job_status(msg).await {
+        if let Err(e) = bus.unmark_update(&msg_id).await {
+            error!("failed unmark job update {msg_id} - {e}");
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
 async fn agent_online(state: AppState, msg: AgentOnlineParams) -> Result<()> {
     info!(
         "namespace: {}, agent_ip: {} online",
@@ -44,16 +73,19 @@ async fn agent_online(state: AppState, msg: AgentOnlineParams) -> Result<()> {
         }
     }
 
-    Ok(svc
-        .instance
+    svc.instance
         .update_status(
-            msg.namespace,
-            msg.agent_ip,
+            msg.namespace.clone(),
+            msg.agent_ip.clone(),
             1,
             msg.secret_header.assign_user,
             msg.secret_header.ssh_connection_params,
         )
-        .await?)
+        .await?;
+    svc.instance
+        .update_peer(msg.namespace, msg.agent_ip, msg.peer)
+        .await?;
+    Ok(())
 }
 
 async fn agent_offline(state: AppState, msg: AgentOfflineParams) -> Result<()> {
@@ -69,19 +101,64 @@ async fn agent_offline(state: AppState, msg: AgentOfflineParams) -> Result<()> {
         .await?)
 }
 
+/// fire the timers whose clock is owned by the console on the leader replica,
+/// see [automate::scheduler::types::TimerMode]
+fn start_console_timer(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CONSOLE_TIMER_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if !state.leader.is_leader() {
+                continue;
+            }
+            if let Err(e) = state.service().job.fire_console_timers().await {
+                error!("failed fire console timers - {e}");
+            }
+        }
+    });
+}
+
+/// reconcile the running status with the agents periodically on the leader replica
+fn start_reconciler(state: AppState) {
+    let conf = state.conf.reconcile.clone();
+    if conf.interval == 0 {
+        info!("reconciler is disabled");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut last_time: Option<Instant> = None;
+        loop {
+            tokio::time::sleep(RECONCILE_CHECK_INTERVAL).await;
+            if !state.leader.is_leader()
+                || last_time.is_some_and(|v| v.elapsed() < Duration::from_secs(conf.interval))
+            {
+                continue;
+            }
+            last_time = Some(Instant::now());
+            if let Err(e) = state.service().job.reconcile(conf.repair).await {
+                error!("failed reconcile running status - {e}");
+            }
+        }
+    });
+}
+
 pub async fn start(state: AppState) -> Result<()> {
+    state.leader.campaign();
+    start_console_timer(state.clone());
+    start_reconciler(state.clone());
+
     let bus = Bus::new(state.redis().clone());
 
     tokio::spawn(async move {
         loop {
             let ret = bus
-                .recv(|_key, msg| {
+                .recv(&state.replica_id, |_key, msg| {
                     let state = state.clone();
                     Box::pin(async move {
                         match msg {
-                            Msg::UpdateJob(v) => {
-                                let _ = state.service().job.update_job_status(v).await?;
-                            }
+                            Msg::UpdateJob(v) => update_job(state.clone(), v).await?,
                             Msg::Heartbeat(v) => {
                                 let _ = heartbeat(state.clone(), v).await?;
                             }
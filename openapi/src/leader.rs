@@ -0,0 +1,130 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use redis::{Client, Script};
+use tokio::time::timeout;
+use tracing::{error, info};
+
+const LEADER_KEY: &str = "jiascheduler:console:leader";
+/// the lease expires if the leader does not renew it in time, e.g. the console crashed
+const LEASE_TTL: Duration = Duration::from_secs(10);
+const RENEW_INTERVAL: Duration = Duration::from_secs(3);
+/// a renew taking longer counts as failed, it is shorter than `LEASE_TTL - RENEW_INTERVAL`
+/// so that the replica steps down before the lease expires
+const RENEW_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// renew the lease only if it is still held by the replica
+const RENEW_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+const RESIGN_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Leader election of the console replicas through a lease in redis,
+/// the singleton workers such as the console timers only run on the leader
+#[derive(Clone)]
+pub struct Leader {
+    redis: Client,
+    replica_id: String,
+    is_leader: Arc<AtomicBool>,
+}
+
+impl Leader {
+    pub fn new(redis: Client, replica_id: String) -> Self {
+        Self {
+            redis,
+            replica_id,
+            is_leader: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::Relaxed)
+    }
+
+    /// take the lease if it is free or renew it if it is held by the replica
+    async fn try_acquire(&self) -> Result<bool> {
+        let mut conn = self.redis.get_multiplexed_async_connection().await?;
+        let ttl = LEASE_TTL.as_millis() as u64;
+
+        let renewed: i64 = Script::new(RENEW_SCRIPT)
+            .key(LEADER_KEY)
+            .arg(&self.replica_id)
+            .arg(ttl)
+            .invoke_async(&mut conn)
+            .await?;
+        if renewed == 1 {
+            return Ok(true);
+        }
+
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(LEADER_KEY)
+            .arg(&self.replica_id)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl)
+            .query_async(&mut conn)
+            .await?;
+        Ok(acquired.is_some())
+    }
+
+    /// campaign for the lease in background, the replica steps down as soon as
+    /// it fails to renew, before the lease expires and another replica takes it
+    pub fn campaign(&self) {
+        let leader = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RENEW_INTERVAL);
+            loop {
+                interval.tick().await;
+                let is_leader = match timeout(RENEW_TIMEOUT, leader.try_acquire()).await {
+                    Ok(Ok(v)) => v,
+                    Ok(Err(e)) => {
+                        error!("failed renew console leader lease - {e}");
+                        false
+                    }
+                    Err(_) => {
+                        error!("renew console leader lease timed out");
+                        false
+                    }
+                };
+                if leader.is_leader.swap(is_leader, Ordering::Relaxed) != is_leader {
+                    info!(
+                        "console {} {} the leader",
+                        leader.replica_id,
+                        if is_leader { "becomes" } else { "is no longer" }
+                    );
+                }
+            }
+        });
+    }
+
+    /// release the lease so that another replica takes over without waiting for it to expire
+    pub async fn resign(&self) -> Result<()> {
+        self.is_leader.store(false, Ordering::Relaxed);
+        timeout(RENEW_TIMEOUT, async {
+            let mut conn = self.redis.get_multiplexed_async_connection().await?;
+            let _: i64 = Script::new(RESIGN_SCRIPT)
+                .key(LEADER_KEY)
+                .arg(&self.replica_id)
+                .invoke_async(&mut conn)
+                .await?;
+            anyhow::Ok(())
+        })
+        .await
+        .map_err(|_| anyhow!("resign console leader lease timed out"))?
+    }
+}
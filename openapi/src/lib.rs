@@ -3,8 +3,8 @@ pub mod response;
 
 use anyhow::{anyhow, Context, Result};
 use api::{
-    executor::ExecutorApi, file::FileApi, instance::InstanceApi, job::JobApi, manage::ManageApi,
-    migration::MigrationApi, role::RoleApi, terminal, user::UserApi,
+    executor::ExecutorApi, file::FileApi, instance::InstanceApi, job::JobApi, job_log,
+    manage::ManageApi, migration::MigrationApi, role::RoleApi, terminal, user::UserApi,
 };
 use casbin::{CoreApi, DefaultModel, Enforcer};
 
@@ -32,7 +32,7 @@ use sea_orm_adapter::SeaOrmAdapter;
 use state::{AppContext, AppState};
 use std::{path::Path, time::Duration};
 use tokio::sync::mpsc;
-use tracing::info;
+use tracing::{error, info};
 use url::Url;
 
 pub mod api;
@@ -40,6 +40,7 @@ mod config;
 pub mod entity;
 mod error;
 mod job;
+mod leader;
 mod logic;
 pub mod middleware;
 mod migration;
@@ -87,6 +88,7 @@ impl IdGenerator {
     const TIMER_JOB_PREFIX: &'static str = "t";
     const FLOW_JOB_PREFIX: &'static str = "f";
     const SCHEDULE_ID_PREFIX: &'static str = "s";
+    const REPLICA_ID_PREFIX: &'static str = "c";
 
     pub fn get_job_eid() -> String {
         Self::get_id(Self::JOB_PREFIX)
@@ -107,6 +109,11 @@ impl IdGenerator {
         Self::get_id(Self::SCHEDULE_ID_PREFIX)
     }
 
+    /// unique id of a console process, used as its bus consumer and leader lease holder
+    pub fn get_replica_id() -> String {
+        Self::get_id(Self::REPLICA_ID_PREFIX)
+    }
+
     fn get_id(prefix: &str) -> String {
         format!("{prefix}-{}", nanoid!(10)).into()
     }
@@ -265,6 +272,7 @@ pub async fn run(opts: WebapiOptions) -> Result<()> {
         )
         .build()?;
     let state = AppState::Inner(ctx);
+    info!("console replica {} started", state.replica_id);
 
     let api_service = OpenApiService::new(
         (
@@ -297,6 +305,7 @@ pub async fn run(opts: WebapiOptions) -> Result<()> {
 
     job::start(state.clone()).await?;
 
+    let leader = state.leader.clone();
     let ui = api_service.rapidoc();
     let app = Route::new()
         .at("/", EmbeddedFileEndpoint::<Dist>::new("index.html"))
@@ -309,6 +318,10 @@ pub async fn run(opts: WebapiOptions) -> Result<()> {
             "/terminal/tunnel/:ip",
             get(terminal::proxy_webssh).with(AuthMiddleware),
         )
+        .at(
+            "/job/tail-log/:schedule_id",
+            get(job_log::tail).with(AuthMiddleware),
+        )
         .nest("/api", api_service.with(AuthMiddleware))
         .nest("/doc", ui)
         .catch_all_error(custom_error)
@@ -326,7 +339,18 @@ pub async fn run(opts: WebapiOptions) -> Result<()> {
             Some(opts.config_file),
         ));
 
-    Ok(poem::Server::new(TcpListener::bind(conf.bind_addr.clone()))
-        .run(app)
-        .await?)
+    let ret = poem::Server::new(TcpListener::bind(conf.bind_addr.clone()))
+        .run_with_graceful_shutdown(
+            app,
+            async move {
+                let _ = tokio::signal::ctrl_c().await;
+            },
+            Some(Duration::from_secs(10)),
+        )
+        .await;
+    // hand over the singleton workers to another replica at once
+    if let Err(e) = leader.resign().await {
+        error!("failed resign console leader - {e}");
+    }
+    Ok(ret?)
 }
@@ -1,4 +1,4 @@
-use automate::scheduler::types::SshConnectionOption;
+use automate::{bridge::msg::PeerInfo, scheduler::types::SshConnectionOption};
 use chrono::Local;
 
 use redis::Commands;
@@ -31,6 +31,18 @@ use anyhow::Result;
 use super::job::types::InstanceStatSummary;
 use super::types;
 
+/// the protocol version and capabilities the agent announced last time
+pub fn peer_of(model: &instance::Model) -> PeerInfo {
+    PeerInfo {
+        protocol_version: model.protocol_version,
+        capabilities: model
+            .capabilities
+            .clone()
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default(),
+    }
+}
+
 #[derive(Debug, FromQueryResult)]
 struct InstanceStatusCount {
     status: bool,
@@ -48,6 +60,35 @@ impl<'a> InstanceLogic<'a> {
         Self { ctx }
     }
 
+    /// save the protocol version and capabilities the agent announced when it came online
+    pub async fn update_peer(
+        &self,
+        namespace: String,
+        agent_ip: String,
+        peer: PeerInfo,
+    ) -> Result<u64> {
+        let ret = Instance::update_many()
+            .set(instance::ActiveModel {
+                protocol_version: Set(peer.protocol_version),
+                capabilities: Set(Some(serde_json::to_value(peer.capabilities)?)),
+                ..Default::default()
+            })
+            .filter(instance::Column::Namespace.eq(namespace))
+            .filter(instance::Column::Ip.eq(agent_ip))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
+    pub async fn get_peer(&self, namespace: &str, agent_ip: &str) -> Result<PeerInfo> {
+        let model = Instance::find()
+            .filter(instance::Column::Namespace.eq(namespace))
+            .filter(instance::Column::Ip.eq(agent_ip))
+            .one(&self.ctx.db)
+            .await?;
+        Ok(model.as_ref().map(peer_of).unwrap_or_default())
+    }
+
     pub async fn update_status(
         &mut self,
         namespace: String,
@@ -210,6 +251,8 @@ impl<'a> InstanceLogic<'a> {
                 instance::Column::Status,
                 instance::Column::SysUser,
                 instance::Column::SshPort,
+                instance::Column::ProtocolVersion,
+                instance::Column::Capabilities,
                 instance::Column::Password,
                 instance::Column::InstanceGroupId,
                 instance::Column::CreatedTime,
@@ -1,9 +1,14 @@
 use anyhow::{anyhow, Result};
 
 mod bundle_script;
+mod calendar;
+mod console_timer;
 mod dashboard;
 mod exec_history;
+pub mod params;
+mod reconcile;
 mod schedule;
+mod secret;
 mod timer;
 
 use sea_orm::{
@@ -22,6 +27,10 @@ use sea_orm::JoinType;
 
 pub mod types;
 
+pub use calendar::parse_calendar;
+pub use secret::mask_secrets;
+pub use timer::{check_timer_expr, describe_timer_expr, next_fire_times};
+
 pub struct JobLogic<'a> {
     ctx: &'a AppContext,
 }
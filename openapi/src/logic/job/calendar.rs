@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+use automate::scheduler::types::{BlackoutWindow, Calendar, CalendarPolicy};
+use chrono::{DateTime, NaiveDate, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QueryTrait, Set,
+};
+use serde_json::Value;
+
+use super::JobLogic;
+use crate::entity::{job_calendar, prelude::*};
+
+/// parse the dates of a calendar, holidays are like 2024-10-01 and the windows are rfc3339
+pub fn parse_calendar(
+    holidays: Vec<String>,
+    business_days: Vec<u8>,
+    blackout_windows: Vec<(String, String, String)>,
+) -> Result<(Value, Value, Value)> {
+    let holidays = holidays
+        .iter()
+        .map(|v| {
+            NaiveDate::parse_from_str(v, "%Y-%m-%d")
+                .map_err(|e| anyhow!("invalid holiday {v} - {e}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(v) = business_days.iter().find(|v| !(1..=7).contains(*v)) {
+        anyhow::bail!("invalid business day {v}, 1 is Monday and 7 is Sunday");
+    }
+
+    let parse_time = |v: &str| {
+        DateTime::parse_from_rfc3339(v)
+            .map(|v| v.with_timezone(&Utc))
+            .map_err(|e| anyhow!("invalid blackout window time {v} - {e}"))
+    };
+    let blackout_windows = blackout_windows
+        .into_iter()
+        .map(|(start, end, reason)| {
+            let window = BlackoutWindow {
+                start: parse_time(&start)?,
+                end: parse_time(&end)?,
+                reason,
+            };
+            if window.start >= window.end {
+                anyhow::bail!("blackout window {start} - {end} ends before it starts");
+            }
+            Ok(window)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((
+        serde_json::to_value(holidays)?,
+        serde_json::to_value(business_days)?,
+        serde_json::to_value(blackout_windows)?,
+    ))
+}
+
+impl<'a> JobLogic<'a> {
+    pub async fn save_job_calendar(
+        &self,
+        active_model: job_calendar::ActiveModel,
+    ) -> Result<job_calendar::ActiveModel> {
+        Ok(active_model.save(&self.ctx.db).await?)
+    }
+
+    pub async fn query_job_calendar(
+        &self,
+        created_user: Option<&String>,
+        name: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<job_calendar::Model>, u64)> {
+        let model = JobCalendar::find()
+            .apply_if(name, |query, v| {
+                query.filter(job_calendar::Column::Name.contains(v))
+            })
+            .apply_if(created_user, |query, v| {
+                query.filter(job_calendar::Column::CreatedUser.eq(v))
+            });
+
+        let total = model.clone().count(&self.ctx.db).await?;
+
+        let list = model
+            .order_by_desc(job_calendar::Column::Id)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+
+        Ok((list, total))
+    }
+
+    pub async fn delete_job_calendar(&self, id: u64) -> Result<u64> {
+        let ret = JobCalendar::delete_by_id(id).exec(&self.ctx.db).await?;
+        Ok(ret.rows_affected)
+    }
+
+    /// the calendar sent to the agent with the timer
+    pub async fn get_calendar(&self, id: u64, policy: CalendarPolicy) -> Result<Calendar> {
+        let record = JobCalendar::find_by_id(id)
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found calendar {id}"))?;
+
+        Ok(Calendar {
+            name: record.name,
+            holidays: record
+                .holidays
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            business_days: record
+                .business_days
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            blackout_windows: record
+                .blackout_windows
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            policy,
+        })
+    }
+}
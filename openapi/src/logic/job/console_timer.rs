@@ -0,0 +1,419 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use automate::{
+    bridge::msg::UpdateJobParams,
+    code,
+    scheduler::{
+        timer::{check_calendar, next_fire_times, parse_time_zone, CalendarCheck},
+        types::{RunStatus, ScheduleStatus, ScheduleType, TargetPolicy, TimerOption},
+    },
+    DispatchJobParams, JobAction,
+};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, Set};
+use sea_query::OnConflict;
+use serde_json::Value;
+use tracing::{error, info};
+
+use super::{
+    types::{DispatchData, DispatchTarget},
+    JobLogic,
+};
+use crate::entity::{instance, job_console_timer, prelude::*};
+
+fn target_key(target: &DispatchTarget) -> String {
+    format!("{}:{}", target.namespace, target.ip)
+}
+
+/// the next fire time after since, the console's local time zone is used if the timer has none
+pub fn next_fire_time(
+    timer_expr: &str,
+    tz: Option<Tz>,
+    since: DateTime<Utc>,
+) -> Result<DateTime<Utc>> {
+    let next = match tz {
+        Some(tz) => next_fire_times(timer_expr, &tz, since, 1)?
+            .pop()
+            .map(|v| v.with_timezone(&Utc)),
+        None => next_fire_times(timer_expr, &Local, since, 1)?
+            .pop()
+            .map(|v| v.with_timezone(&Utc)),
+    };
+    next.ok_or(anyhow!("timer expr {timer_expr} never fires after {since}"))
+}
+
+/// split the targets into the ones to run on and the ones missing the fire because they are offline.
+/// [TargetPolicy::Any] takes turns from the target after the last one
+pub fn pick_targets(
+    policy: TargetPolicy,
+    targets: &[DispatchTarget],
+    online: &HashSet<String>,
+    last_target: &str,
+) -> (Vec<DispatchTarget>, Vec<DispatchTarget>) {
+    match policy {
+        TargetPolicy::All => targets
+            .iter()
+            .cloned()
+            .partition(|v| online.contains(&target_key(v))),
+        TargetPolicy::Any => {
+            let start = targets
+                .iter()
+                .position(|v| target_key(v) == last_target)
+                .map_or(0, |v| v + 1);
+            let picked = (0..targets.len())
+                .map(|v| &targets[(start + v) % targets.len()])
+                .find(|v| online.contains(&target_key(v)));
+            match picked {
+                Some(v) => (vec![v.clone()], vec![]),
+                None => (vec![], targets.to_vec()),
+            }
+        }
+    }
+}
+
+impl<'a> JobLogic<'a> {
+    /// start or stop the console timer of the job according to the dispatched timer action,
+    /// a job has either an agent timer or a console timer
+    pub async fn sync_console_timer(&self, data: &DispatchData, action: JobAction) -> Result<()> {
+        let console_mode = TimerOption::is_console_mode(&data.params.timer_option);
+        match action {
+            JobAction::StartTimer if console_mode => self.start_console_timer(data).await,
+            JobAction::StartTimer => self
+                .stop_console_timer(&data.params.base_job.eid)
+                .await
+                .map(|_| ()),
+            JobAction::StopTimer => {
+                if self.stop_console_timer(&data.params.base_job.eid).await? {
+                    for target in &data.target {
+                        self.report_console_timer(
+                            &data.params,
+                            target,
+                            UpdateJobParams {
+                                schedule_status: Some(ScheduleStatus::Unscheduled),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn start_console_timer(&self, data: &DispatchData) -> Result<()> {
+        let params = &data.params;
+        let timer_expr = params
+            .timer_expr
+            .clone()
+            .ok_or(anyhow!("missing timer expr of job {}", params.base_job.eid))?;
+        let tz = params
+            .timer_option
+            .as_ref()
+            .and_then(|v| v.time_zone.as_deref())
+            .map(parse_time_zone)
+            .transpose()?;
+        let next_time = next_fire_time(&timer_expr, tz, Utc::now())?;
+
+        JobConsoleTimer::insert(job_console_timer::ActiveModel {
+            eid: Set(params.base_job.eid.clone()),
+            schedule_id: Set(params.schedule_id.clone()),
+            timer_expr: Set(timer_expr),
+            is_enabled: Set(true),
+            next_time: Set(Some(next_time)),
+            created_user: Set(params.created_user.clone()),
+            updated_user: Set(params.created_user.clone()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(job_console_timer::Column::Eid)
+                .update_columns([
+                    job_console_timer::Column::ScheduleId,
+                    job_console_timer::Column::TimerExpr,
+                    job_console_timer::Column::IsEnabled,
+                    job_console_timer::Column::NextTime,
+                    job_console_timer::Column::UpdatedUser,
+                ])
+                .to_owned(),
+        )
+        .exec(&self.ctx.db)
+        .await?;
+
+        for target in &data.target {
+            self.report_console_timer(
+                params,
+                target,
+                UpdateJobParams {
+                    schedule_status: Some(ScheduleStatus::Scheduling),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// disable the console timer of the job, return false if there is none
+    async fn stop_console_timer(&self, eid: &str) -> Result<bool> {
+        let ret = JobConsoleTimer::update_many()
+            .set(job_console_timer::ActiveModel {
+                is_enabled: Set(false),
+                ..Default::default()
+            })
+            .filter(job_console_timer::Column::Eid.eq(eid))
+            .filter(job_console_timer::Column::IsEnabled.eq(true))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected > 0)
+    }
+
+    /// update the running status of the console timer on the target as the agent does for its timer
+    async fn report_console_timer(
+        &self,
+        params: &DispatchJobParams,
+        target: &DispatchTarget,
+        update: UpdateJobParams,
+    ) -> Result<()> {
+        self.update_job_status(UpdateJobParams {
+            base_job: params.base_job.to_pure_job(),
+            schedule_id: params.schedule_id.clone(),
+            schedule_type: Some(ScheduleType::Timer),
+            bind_ip: target.ip.clone(),
+            bind_namespace: target.namespace.clone(),
+            created_user: params.created_user.clone(),
+            ..update
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// fire the due console timers and return the number of fires
+    pub async fn fire_console_timers(&self) -> Result<usize> {
+        let now = Utc::now();
+        let due = JobConsoleTimer::find()
+            .filter(job_console_timer::Column::IsEnabled.eq(true))
+            .filter(job_console_timer::Column::NextTime.lte(now))
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut fired = 0;
+        for record in due {
+            let eid = record.eid.clone();
+            match self.fire_console_timer(record, now).await {
+                Ok(true) => fired += 1,
+                Ok(false) => {}
+                Err(e) => error!("failed fire console timer of job {eid} - {e}"),
+            }
+        }
+        Ok(fired)
+    }
+
+    /// a fire is claimed by moving the next time forward, so that it runs once with many consoles.
+    /// the fires missed while no console is running are run once
+    async fn fire_console_timer(
+        &self,
+        record: job_console_timer::Model,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        let Some(fire_time) = record.next_time else {
+            return Ok(false);
+        };
+
+        let mut data: DispatchData = self
+            .get_schedule(record.schedule_id.clone())
+            .await?
+            .and_then(|v| v.dispatch_data)
+            .ok_or(anyhow!(
+                "cannot found dispatch data of schedule {}",
+                record.schedule_id
+            ))?
+            .try_into()?;
+        let option = data.params.timer_option.clone().unwrap_or_default();
+        let tz = option
+            .time_zone
+            .as_deref()
+            .map(parse_time_zone)
+            .transpose()?;
+
+        let check = match option.calendar {
+            Some(ref v) => check_calendar(v, &record.timer_expr, tz, fire_time),
+            None => CalendarCheck::Run,
+        };
+        let mut next_time = next_fire_time(&record.timer_expr, tz, now)?;
+        // the shifted run is dropped if the timer fires again before it
+        if let CalendarCheck::Shift(v, _) = check {
+            next_time = next_time.min(v);
+        }
+
+        let online: HashSet<String> = Instance::find()
+            .filter(instance::Column::Status.eq(1))
+            .filter(instance::Column::Ip.is_in(data.target.iter().map(|v| v.ip.clone())))
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| format!("{}:{}", v.namespace, v.ip))
+            .collect();
+        let (targets, missed) = pick_targets(
+            option.target_policy,
+            &data.target,
+            &online,
+            &record.last_target,
+        );
+
+        let ret = JobConsoleTimer::update_many()
+            .set(job_console_timer::ActiveModel {
+                prev_time: Set(Some(fire_time)),
+                next_time: Set(Some(next_time)),
+                last_target: Set(targets
+                    .last()
+                    .map_or(record.last_target.clone(), target_key)),
+                ..Default::default()
+            })
+            .filter(job_console_timer::Column::Id.eq(record.id))
+            .filter(job_console_timer::Column::IsEnabled.eq(true))
+            .filter(job_console_timer::Column::NextTime.eq(fire_time))
+            .exec(&self.ctx.db)
+            .await?;
+        if ret.rows_affected == 0 {
+            return Ok(false);
+        }
+
+        let fired = |run_status: Option<RunStatus>, exit_status: Option<String>| UpdateJobParams {
+            schedule_status: Some(ScheduleStatus::Scheduling),
+            run_status,
+            exit_status,
+            start_time: Some(fire_time),
+            end_time: Some(fire_time),
+            prev_time: Some(fire_time),
+            next_time: Some(next_time),
+            ..Default::default()
+        };
+
+        for target in &missed {
+            self.report_console_timer(
+                &data.params,
+                target,
+                fired(
+                    Some(RunStatus::Missed),
+                    Some("missed, agent is offline".to_string()),
+                ),
+            )
+            .await?;
+        }
+
+        let reason = match check {
+            CalendarCheck::Run => None,
+            CalendarCheck::Skip(v) => Some(format!("skipped, {v}")),
+            CalendarCheck::Shift(v, reason) => Some(format!("skipped, {reason}, shifted to {v}")),
+        };
+        if let Some(reason) = reason {
+            for target in &targets {
+                self.report_console_timer(
+                    &data.params,
+                    target,
+                    fired(Some(RunStatus::Skipped), Some(reason.clone())),
+                )
+                .await?;
+            }
+            return Ok(true);
+        }
+
+        self.fill_secrets(&mut data.params).await?;
+        data.params.action = JobAction::Exec;
+        data.params.is_sync = false;
+
+        let sent = futures::future::join_all(
+            targets
+                .iter()
+                .map(|v| self.send_dispatch(v, data.params.clone())),
+        )
+        .await;
+        for (target, ret) in targets.iter().zip(sent) {
+            let update = match ret {
+                Ok(_) => {
+                    info!(
+                        "console timer of job {} fired on {}",
+                        record.eid,
+                        target_key(target)
+                    );
+                    UpdateJobParams {
+                        start_time: None,
+                        end_time: None,
+                        ..fired(None, None)
+                    }
+                }
+                Err(e) => fired(
+                    Some(RunStatus::Missed),
+                    Some(format!("missed, failed dispatch - {e}")),
+                ),
+            };
+            self.report_console_timer(&data.params, target, update)
+                .await?;
+        }
+        Ok(true)
+    }
+
+    /// send the dispatch to the agent through the comet it is linked to
+    async fn send_dispatch(
+        &self,
+        target: &DispatchTarget,
+        dispatch_params: DispatchJobParams,
+    ) -> Result<Value> {
+        let logic = automate::Logic::new(self.ctx.redis().clone());
+        let pair = logic
+            .get_link_pair(target.namespace.clone(), target.ip.clone())
+            .await?;
+        let body = automate::DispatchJobRequest {
+            agent_ip: target.ip.clone(),
+            namespace: target.namespace.clone(),
+            dispatch_params,
+        };
+
+        let ret = self
+            .ctx
+            .http_client
+            .post(format!("http://{}/dispatch", pair.1.comet_addr))
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        if ret["code"] != code::SUCCESS {
+            anyhow::bail!("{}", ret["msg"]);
+        }
+        Ok(ret)
+    }
+}
+
+#[test]
+fn test_pick_targets() {
+    let targets: Vec<DispatchTarget> = ["10.0.0.1", "10.0.0.2", "10.0.0.3"]
+        .into_iter()
+        .map(|v| DispatchTarget {
+            ip: v.to_string(),
+            namespace: "default".to_string(),
+        })
+        .collect();
+    let online = HashSet::from([
+        "default:10.0.0.1".to_string(),
+        "default:10.0.0.3".to_string(),
+    ]);
+
+    let (run, missed) = pick_targets(TargetPolicy::All, &targets, &online, "");
+    assert_eq!(run.len(), 2);
+    assert_eq!(missed[0].ip, "10.0.0.2");
+
+    let (run, missed) = pick_targets(TargetPolicy::Any, &targets, &online, "default:10.0.0.1");
+    assert_eq!(run[0].ip, "10.0.0.3");
+    assert!(missed.is_empty());
+    let (run, _) = pick_targets(TargetPolicy::Any, &targets, &online, "default:10.0.0.3");
+    assert_eq!(run[0].ip, "10.0.0.1");
+
+    let (run, missed) = pick_targets(TargetPolicy::Any, &targets, &HashSet::new(), "");
+    assert!(run.is_empty());
+    assert_eq!(missed.len(), 3);
+}
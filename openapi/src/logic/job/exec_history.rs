@@ -1,11 +1,12 @@
 use crate::entity::{job_exec_history, job_schedule_history, prelude::*};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use automate::{bridge::msg::ReadOutputParams, code};
 use sea_orm::{
     ColumnTrait, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
     QueryTrait,
 };
 
-use super::types::ExecHistoryRelatedScheduleModel;
+use super::types::{ExecHistoryRelatedScheduleModel, ExecOutput};
 use super::JobLogic;
 
 impl<'a> JobLogic<'a> {
@@ -74,4 +75,67 @@ impl<'a> JobLogic<'a> {
 
         Ok((list, total))
     }
+
+    /// read the full output of a run from the agent, it is kept only if it was truncated
+    pub async fn read_exec_output(
+        &self,
+        id: u64,
+        stream: &str,
+        offset: u64,
+        limit: u64,
+        username: String,
+    ) -> Result<ExecOutput> {
+        let record = JobExecHistory::find_by_id(id)
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found exec history {id}"))?;
+
+        let schedule_record = self
+            .get_schedule(record.schedule_id.clone())
+            .await?
+            .filter(|v| v.created_user == username)
+            .ok_or(anyhow!("cannot found schedule {}", record.schedule_id))?;
+
+        let filepath = match stream {
+            "stderr" => record.stderr_file,
+            _ => record.stdout_file,
+        };
+        if filepath.is_empty() {
+            anyhow::bail!(
+                "the {stream} of {} is not truncated, no full output is kept",
+                schedule_record.name
+            );
+        }
+
+        let logic = automate::Logic::new(self.ctx.redis().clone());
+        let pair = logic
+            .get_link_pair(record.bind_namespace.clone(), record.bind_ip.clone())
+            .await?;
+        let api_url = format!("http://{}/output/tunnel/read", pair.1.comet_addr);
+
+        let body = automate::ReadOutputRequest {
+            agent_ip: record.bind_ip,
+            namespace: record.bind_namespace,
+            params: ReadOutputParams {
+                filepath,
+                offset,
+                limit,
+            },
+        };
+
+        let mut ret = self
+            .ctx
+            .http_client
+            .post(api_url)
+            .json(&body)
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+
+        if ret["code"] != code::SUCCESS {
+            anyhow::bail!(ret["msg"].take().to_string())
+        }
+        Ok(serde_json::from_value(ret["data"].take())?)
+    }
 }
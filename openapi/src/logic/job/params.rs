@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use super::types::{JobParam, ParamKind};
+
+/// parameters declared by a job, the legacy args are string parameters with default value
+pub fn declared_params(params: Option<Value>, args: Option<Value>) -> Result<Vec<JobParam>> {
+    let mut params: Vec<JobParam> = params
+        .map(serde_json::from_value)
+        .transpose()?
+        .unwrap_or_default();
+    let args: HashMap<String, String> = args
+        .map(serde_json::from_value)
+        .transpose()?
+        .unwrap_or_default();
+
+    for (name, default) in args {
+        if params.iter().all(|v| v.name != name) {
+            params.push(JobParam {
+                name,
+                default: Some(Value::String(default)),
+                ..Default::default()
+            });
+        }
+    }
+    Ok(params)
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|v| v.is_ascii_alphabetic() || v == '_')
+        && chars.all(|v| v.is_ascii_alphanumeric() || v == '_')
+}
+
+fn check_value(param: &JobParam, value: Value) -> Result<Value> {
+    let name = &param.name;
+    let value = match (param.kind, value) {
+        (ParamKind::String, Value::String(v)) => Value::String(v),
+        (ParamKind::String, v @ (Value::Number(_) | Value::Bool(_))) => {
+            Value::String(v.to_string())
+        }
+        (ParamKind::Int, Value::Number(v)) if v.is_i64() => Value::Number(v),
+        (ParamKind::Int, Value::String(v)) => Value::from(
+            v.trim()
+                .parse::<i64>()
+                .map_err(|_| anyhow!("param {name} must be an integer"))?,
+        ),
+        (ParamKind::Bool, Value::Bool(v)) => Value::Bool(v),
+        (ParamKind::Bool, Value::String(v)) => Value::Bool(
+            v.trim()
+                .parse::<bool>()
+                .map_err(|_| anyhow!("param {name} must be true or false"))?,
+        ),
+        (ParamKind::Enum, Value::String(v)) if param.options.contains(&v) => Value::String(v),
+        (ParamKind::Enum, _) => {
+            anyhow::bail!("param {name} must be one of {}", param.options.join(", "))
+        }
+        (kind, _) => anyhow::bail!("param {name} must be {kind:?}"),
+    };
+    Ok(value)
+}
+
+/// check the declarations before they are saved
+pub fn validate_params(params: &[JobParam]) -> Result<()> {
+    let mut names = HashSet::new();
+    for v in params {
+        if !is_ident(&v.name) {
+            anyhow::bail!("invalid param name {}", v.name);
+        }
+        if !names.insert(v.name.as_str()) {
+            anyhow::bail!("duplicate param {}", v.name);
+        }
+        if v.kind == ParamKind::Enum && v.options.is_empty() {
+            anyhow::bail!("enum param {} has no options", v.name);
+        }
+        if let Some(default) = v.default.clone() {
+            check_value(v, default)?;
+        }
+    }
+    Ok(())
+}
+
+/// resolve the values supplied at dispatch time against the declarations
+pub fn resolve_params(
+    params: &[JobParam],
+    mut values: HashMap<String, Value>,
+) -> Result<HashMap<String, Value>> {
+    let mut resolved = HashMap::new();
+    for param in params {
+        let value = match values.remove(&param.name).or(param.default.clone()) {
+            Some(Value::Null) | None if param.required => {
+                anyhow::bail!("missing required param {}", param.name)
+            }
+            Some(Value::Null) | None => match param.kind {
+                ParamKind::Bool => Value::Bool(false),
+                _ => Value::String("".to_string()),
+            },
+            Some(v) => check_value(param, v)?,
+        };
+        resolved.insert(param.name.clone(), value);
+    }
+
+    if let Some(name) = values.keys().next() {
+        anyhow::bail!("undeclared param {name}");
+    }
+    Ok(resolved)
+}
+
+/// quote the value for shell, so that it is always a single word
+fn shell_quote(v: &str) -> String {
+    format!("'{}'", v.replace('\'', r"'\''"))
+}
+
+/// replace `{{ name }}` with the value, `{{ name | quote }}` quotes the value for shell.
+/// only variables are supported, an undeclared variable or unknown filter is an error
+pub fn render(code: &str, values: &HashMap<String, Value>) -> Result<String> {
+    let mut output = String::with_capacity(code.len());
+    let mut rest = code;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let tail = &rest[start + 2..];
+        let end = tail.find("}}").ok_or(anyhow!("unclosed {{{{ in code"))?;
+
+        let mut parts = tail[..end].split('|').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let filter = parts.next();
+        if parts.next().is_some() {
+            anyhow::bail!("only one filter is allowed in {{{{{}}}}}", &tail[..end]);
+        }
+
+        let value = match values.get(name) {
+            Some(Value::String(v)) => v.to_owned(),
+            Some(v) => v.to_string(),
+            None => anyhow::bail!("undeclared param {name} in code"),
+        };
+
+        match filter {
+            None => output.push_str(&value),
+            Some("quote") => output.push_str(&shell_quote(&value)),
+            Some(v) => anyhow::bail!("unknown filter {v}"),
+        }
+        rest = &tail[end + 2..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[test]
+fn test_render() {
+    let params = vec![
+        JobParam {
+            name: "target".to_string(),
+            required: true,
+            ..Default::default()
+        },
+        JobParam {
+            name: "count".to_string(),
+            kind: ParamKind::Int,
+            default: Some(Value::from(3)),
+            ..Default::default()
+        },
+    ];
+    let values = resolve_params(
+        &params,
+        HashMap::from([("target".to_string(), Value::from("it's"))]),
+    )
+    .unwrap();
+
+    assert_eq!(
+        render("echo {{ target | quote }} {{count}}", &values).unwrap(),
+        r"echo 'it'\''s' 3"
+    );
+    assert!(render("echo {{ other }}", &values).is_err());
+    assert!(resolve_params(&params, HashMap::new()).is_err());
+}
@@ -0,0 +1,351 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use automate::{
+    bridge::msg::{
+        Capability, ListRuntimeStateParams, RuntimeActionParams, RuntimeJob, RuntimeStateReply,
+        UpdateJobParams,
+    },
+    code,
+    comet::types::RuntimeActionRequest,
+    scheduler::types::{RunStatus, RuntimeAction, ScheduleStatus, ScheduleType},
+    BaseJob, JobAction,
+};
+use chrono::Utc;
+use sea_orm::{ActiveValue::NotSet, ColumnTrait, EntityTrait, QueryFilter, Set};
+use serde_json::Value;
+use tracing::{error, info};
+
+use super::JobLogic;
+use crate::{
+    entity::{instance, job_console_timer, job_running_status, prelude::*},
+    logic::instance::peer_of,
+};
+
+/// the rows updated recently are left alone, the update of a change may be on the way
+const RECONCILE_GRACE_SECS: i64 = 60;
+const RECONCILE_USER: &str = "reconciler";
+
+/// Differences between the running status and an agent and what is done about them
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReconcileReport {
+    /// rows fixed to what the agent reports
+    pub fixed: u64,
+    /// timers and supervisors dispatched again to the agent
+    pub redispatched: u64,
+    /// timers and supervisors stopped on the agent
+    pub stopped: u64,
+}
+
+impl ReconcileReport {
+    fn merge(&mut self, other: ReconcileReport) {
+        self.fixed += other.fixed;
+        self.redispatched += other.redispatched;
+        self.stopped += other.stopped;
+    }
+}
+
+fn is_desired(row: &job_running_status::Model) -> bool {
+    row.schedule_status == ScheduleStatus::Scheduling.to_string()
+        || row.schedule_status == ScheduleStatus::Prepare.to_string()
+}
+
+impl<'a> JobLogic<'a> {
+    /// the timers, supervisors and running jobs of the agent
+    pub async fn list_runtime_state(&self, namespace: &str, ip: &str) -> Result<RuntimeStateReply> {
+        let logic = automate::Logic::new(self.ctx.redis().clone());
+        let pair = logic
+            .get_link_pair(namespace.to_string(), ip.to_string())
+            .await?;
+
+        let body = automate::ListRuntimeStateRequest {
+            agent_ip: ip.to_string(),
+            namespace: namespace.to_string(),
+            params: ListRuntimeStateParams::default(),
+        };
+        let mut ret = self
+            .ctx
+            .http_client
+            .post(format!("http://{}/runtime/state", pair.1.comet_addr))
+            .json(&body)
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+
+        if ret["code"] != code::SUCCESS {
+            anyhow::bail!(ret["msg"].take().to_string())
+        }
+        Ok(serde_json::from_value(ret["data"].take())?)
+    }
+
+    /// reconcile the running status of all the online instances
+    pub async fn reconcile(&self, repair: bool) -> Result<ReconcileReport> {
+        let instances = Instance::find()
+            .filter(instance::Column::Status.eq(1))
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut report = ReconcileReport::default();
+        for v in instances {
+            // the agents older than the runtime state cannot be compared
+            if !peer_of(&v).supports(Capability::RuntimeState) {
+                continue;
+            }
+            match self.reconcile_instance(&v.namespace, &v.ip, repair).await {
+                Ok(v) => report.merge(v),
+                Err(e) => error!("failed reconcile {}:{} - {e}", v.namespace, v.ip),
+            }
+        }
+        Ok(report)
+    }
+
+    /// compare the running status of the instance with the state reported by its agent.
+    /// the stale rows are fixed to what the agent reports, with repair the timers and supervisors
+    /// are dispatched again or stopped on the agent to match the running status instead
+    pub async fn reconcile_instance(
+        &self,
+        namespace: &str,
+        ip: &str,
+        repair: bool,
+    ) -> Result<ReconcileReport> {
+        let state = self.list_runtime_state(namespace, ip).await?;
+        let since = Utc::now() - chrono::Duration::seconds(RECONCILE_GRACE_SECS);
+
+        let rows = JobRunningStatus::find()
+            .filter(job_running_status::Column::BindNamespace.eq(namespace))
+            .filter(job_running_status::Column::BindIp.eq(ip))
+            .all(&self.ctx.db)
+            .await?;
+        // the console owns the clock of these timers, the agent has none of them
+        let console_timers: HashSet<String> = JobConsoleTimer::find()
+            .filter(job_console_timer::Column::IsEnabled.eq(true))
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| v.eid)
+            .collect();
+
+        let mut report = ReconcileReport::default();
+        for (schedule_type, active, start, stop) in [
+            (
+                ScheduleType::Timer,
+                &state.timers,
+                JobAction::StartTimer,
+                RuntimeAction::StopTimer,
+            ),
+            (
+                ScheduleType::Supervisor,
+                &state.supervisors,
+                JobAction::StartSupervisor,
+                RuntimeAction::StopSupervisor,
+            ),
+        ] {
+            let mut active: HashMap<&str, &RuntimeJob> =
+                active.iter().map(|v| (v.eid.as_str(), v)).collect();
+
+            for row in rows
+                .iter()
+                .filter(|v| v.schedule_type == schedule_type.to_string())
+            {
+                let job = active.remove(row.eid.as_str());
+                if row.updated_time > since
+                    || (schedule_type == ScheduleType::Timer && console_timers.contains(&row.eid))
+                {
+                    continue;
+                }
+
+                // the agent runs an older schedule of the job
+                let replaced = job
+                    .is_some_and(|v| !v.schedule_id.is_empty() && v.schedule_id != row.schedule_id);
+                match (is_desired(row), job) {
+                    (true, None) if repair => {
+                        self.redispatch_to_agent(namespace, ip, row, start).await?;
+                        report.redispatched += 1;
+                    }
+                    (true, Some(_)) if replaced && repair => {
+                        self.redispatch_to_agent(namespace, ip, row, start).await?;
+                        report.redispatched += 1;
+                    }
+                    (false, Some(job)) if repair => {
+                        self.stop_on_agent(namespace, ip, job, stop.clone()).await?;
+                        report.stopped += 1;
+                    }
+                    (true, None) => {
+                        self.fix_running_status(
+                            row.id,
+                            job_running_status::ActiveModel {
+                                schedule_status: Set(ScheduleStatus::Unscheduled.to_string()),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
+                        report.fixed += 1;
+                    }
+                    (desired, Some(job)) if !desired || replaced => {
+                        self.fix_running_status(
+                            row.id,
+                            job_running_status::ActiveModel {
+                                schedule_status: Set(ScheduleStatus::Scheduling.to_string()),
+                                schedule_id: match job.schedule_id.as_str() {
+                                    "" => NotSet,
+                                    v => Set(v.to_string()),
+                                },
+                                next_time: Set(job.next_time),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
+                        report.fixed += 1;
+                    }
+                    _ => {}
+                }
+            }
+
+            // the agent runs a timer or supervisor the console has no record of
+            for job in active.into_values() {
+                if repair {
+                    self.stop_on_agent(namespace, ip, job, stop.clone()).await?;
+                    report.stopped += 1;
+                } else {
+                    self.update_job_status(UpdateJobParams {
+                        base_job: BaseJob {
+                            eid: job.eid.clone(),
+                            ..Default::default()
+                        },
+                        schedule_id: job.schedule_id.clone(),
+                        schedule_type: Some(schedule_type.clone()),
+                        schedule_status: Some(ScheduleStatus::Scheduling),
+                        bind_ip: ip.to_string(),
+                        bind_namespace: namespace.to_string(),
+                        created_user: RECONCILE_USER.to_string(),
+                        ..Default::default()
+                    })
+                    .await?;
+                    report.fixed += 1;
+                }
+            }
+        }
+
+        // a run cannot be repaired, only its status is fixed
+        let running: HashSet<(String, String)> = state
+            .running
+            .iter()
+            .map(|v| {
+                (
+                    v.eid.clone(),
+                    v.schedule_type.clone().unwrap_or_default().to_string(),
+                )
+            })
+            .collect();
+        for row in rows.iter().filter(|v| v.updated_time <= since) {
+            let is_running = running.contains(&(row.eid.clone(), row.schedule_type.clone()));
+            let run_status = if row.run_status == RunStatus::Running.to_string() && !is_running {
+                RunStatus::Stop
+            } else if row.run_status != RunStatus::Running.to_string() && is_running {
+                RunStatus::Running
+            } else {
+                continue;
+            };
+            let exit_status = match run_status {
+                RunStatus::Stop => Set("lost, the job is not running on the agent".to_string()),
+                _ => Default::default(),
+            };
+            self.fix_running_status(
+                row.id,
+                job_running_status::ActiveModel {
+                    run_status: Set(run_status.to_string()),
+                    exit_status,
+                    ..Default::default()
+                },
+            )
+            .await?;
+            report.fixed += 1;
+        }
+
+        if report.fixed + report.redispatched + report.stopped > 0 {
+            info!("reconciled {namespace}:{ip} - {report:?}");
+        }
+        Ok(report)
+    }
+
+    async fn fix_running_status(
+        &self,
+        id: u64,
+        model: job_running_status::ActiveModel,
+    ) -> Result<()> {
+        JobRunningStatus::update_many()
+            .set(job_running_status::ActiveModel {
+                updated_user: Set(RECONCILE_USER.to_string()),
+                ..model
+            })
+            .filter(job_running_status::Column::Id.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn redispatch_to_agent(
+        &self,
+        namespace: &str,
+        ip: &str,
+        row: &job_running_status::Model,
+        action: JobAction,
+    ) -> Result<()> {
+        info!(
+            "dispatch {} of {} to {namespace}:{ip} again",
+            action, row.eid
+        );
+        self.action(
+            row.schedule_id.clone(),
+            ip.to_string(),
+            RECONCILE_USER.to_string(),
+            namespace.to_string(),
+            action,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// stop the timer or supervisor the agent should not run,
+    /// it is stopped by eid in case the agent does not know its schedule id
+    async fn stop_on_agent(
+        &self,
+        namespace: &str,
+        ip: &str,
+        job: &RuntimeJob,
+        action: RuntimeAction,
+    ) -> Result<()> {
+        info!("stop {} of {} on {namespace}:{ip}", action, job.eid);
+        let logic = automate::Logic::new(self.ctx.redis().clone());
+        let pair = logic
+            .get_link_pair(namespace.to_string(), ip.to_string())
+            .await?;
+
+        let body = RuntimeActionRequest {
+            agent_ip: ip.to_string(),
+            namespace: namespace.to_string(),
+            action_params: RuntimeActionParams {
+                eid: job.eid.clone(),
+                fields: None,
+                is_sync: true,
+                created_user: RECONCILE_USER.to_string(),
+                action,
+            },
+        };
+        let ret = self
+            .ctx
+            .http_client
+            .post(format!("http://{}/runtime/action", pair.1.comet_addr))
+            .json(&body)
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+
+        if ret["code"] != code::SUCCESS {
+            anyhow::bail!("{}", ret["msg"]);
+        }
+        Ok(())
+    }
+}
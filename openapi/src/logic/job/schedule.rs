@@ -1,10 +1,21 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Result};
 
 use automate::{
-    bridge::msg::{BundleOutputParams, UpdateJobParams},
-    scheduler::types::{BundleScript, RunStatus, ScheduleStatus, ScheduleType, UploadFile},
+    bridge::{
+        msg::{BundleOutputParams, UpdateJobParams},
+        transfer,
+    },
+    code,
+    scheduler::types::{
+        BundleScript, RunStatus, ScheduleStatus, ScheduleType, SupervisorOption, TimerOption,
+        UploadFile,
+    },
     JobAction,
 };
 
@@ -23,11 +34,16 @@ use tracing::error;
 use crate::{
     entity::{self, executor, job, job_running_status, job_schedule_history, prelude::*},
     file_name,
-    logic::{executor::ExecutorLogic, job::types::DispatchResult},
+    logic::{
+        executor::ExecutorLogic,
+        job::types::DispatchResult,
+        transfer::{CometChannel, TransferLogic},
+    },
     utils, IdGenerator,
 };
 
 use super::{
+    params,
     types::{BundleScriptRecord, BundleScriptResult, DispatchData, DispatchTarget},
     JobLogic,
 };
@@ -40,9 +56,84 @@ fn test_hello() {
     }
 }
 
+/// The file of a job dispatched to the agents
+#[derive(Clone)]
+struct Artifact {
+    path: String,
+    size: u64,
+    sha256: String,
+    /// the agents supporting the transfer, the file is pushed to them in chunks
+    transfer_targets: Arc<HashSet<(String, String)>>,
+    /// the others take the whole file in the dispatch
+    data: Option<Arc<Vec<u8>>>,
+}
+
+impl Artifact {
+    async fn attach(
+        &self,
+        http_client: &reqwest::Client,
+        comet_addr: &str,
+        target: &DispatchTarget,
+        upload_file: &mut UploadFile,
+    ) -> Result<()> {
+        if !self
+            .transfer_targets
+            .contains(&(target.namespace.clone(), target.ip.clone()))
+        {
+            upload_file.transfer_id = None;
+            upload_file.data = self.data.as_ref().map(|v| v.to_vec());
+            return Ok(());
+        }
+
+        let channel = CometChannel::new(
+            http_client.clone(),
+            comet_addr.to_string(),
+            target.namespace.clone(),
+            target.ip.clone(),
+        );
+        let file = fs::File::open(&self.path).await?;
+        transfer::push(&channel, &self.sha256, file, self.size, &self.sha256).await?;
+        upload_file.transfer_id = Some(self.sha256.clone());
+        upload_file.data = None;
+        Ok(())
+    }
+}
+
 impl<'a> JobLogic<'a> {
     pub async fn compute_bundle_output() {}
 
+    /// the file is read into memory only if some agent does not support the transfer
+    async fn prepare_artifact(
+        &self,
+        path: &str,
+        size: u64,
+        sha256: String,
+        targets: &[DispatchTarget],
+    ) -> Result<Artifact> {
+        let transfer = TransferLogic::new(self.ctx);
+        let mut transfer_targets = HashSet::new();
+        let mut need_data = false;
+        for v in targets {
+            if transfer.is_supported(&v.namespace, &v.ip).await? {
+                transfer_targets.insert((v.namespace.clone(), v.ip.clone()));
+            } else {
+                need_data = true;
+            }
+        }
+        let data = match need_data {
+            true => Some(Arc::new(fs::read(path).await?)),
+            false => None,
+        };
+
+        Ok(Artifact {
+            path: path.to_string(),
+            size,
+            sha256,
+            transfer_targets: Arc::new(transfer_targets),
+            data,
+        })
+    }
+
     pub fn eval(
         &self,
         record: Vec<BundleScriptRecord>,
@@ -70,6 +161,10 @@ impl<'a> JobLogic<'a> {
                             exit_status: val.exit_status.clone(),
                             stdout: val.stdout.clone(),
                             stderr: val.stderr.clone(),
+                            stdout_size: val.stdout_size,
+                            stderr_size: val.stderr_size,
+                            stdout_file: val.stdout_file.clone(),
+                            stderr_file: val.stderr_file.clone(),
                             eval_err,
                             result,
                         };
@@ -192,7 +287,13 @@ impl<'a> JobLogic<'a> {
         let ret = active_model.exec(&self.ctx.db).await?;
 
         match params.run_status {
-            Some(RunStatus::Stop) => {
+            Some(
+                RunStatus::Stop
+                | RunStatus::Retrying
+                | RunStatus::Skipped
+                | RunStatus::Restarting
+                | RunStatus::Missed,
+            ) => {
                 let (bundle_script_result, job_type) = if params.bundle_output.is_some() {
                     let schedule_record = self
                         .get_schedule(params.schedule_id.clone())
@@ -220,9 +321,11 @@ impl<'a> JobLogic<'a> {
                     (NotSet, Set("default".to_string()))
                 };
 
-                let output = params.stdout.unwrap_or_default();
+                // kept for the readers of output, stdout and stderr are already capped by the agent
+                let output = params.stdout.clone().unwrap_or_default();
                 let output = params
                     .stderr
+                    .as_ref()
                     .map_or(output.clone(), |v| format!("{v}\n{output}"));
 
                 let ret = JobExecHistory::insert(entity::job_exec_history::ActiveModel {
@@ -231,7 +334,15 @@ impl<'a> JobLogic<'a> {
                     bind_namespace: Set(params.bind_namespace.clone()),
                     exit_status: Set(params.exit_status.clone().unwrap_or_default()),
                     exit_code: Set(params.exit_code.unwrap_or_default()),
+                    attempt: Set(params.attempt.unwrap_or(1)),
+                    retry_exhausted: Set(params.retry_exhausted.unwrap_or_default()),
                     output: Set(output),
+                    stdout: Set(params.stdout),
+                    stderr: Set(params.stderr),
+                    stdout_size: Set(params.stdout_size.unwrap_or_default()),
+                    stderr_size: Set(params.stderr_size.unwrap_or_default()),
+                    stdout_file: Set(params.stdout_file.unwrap_or_default()),
+                    stderr_file: Set(params.stderr_file.unwrap_or_default()),
                     eid: Set(params.base_job.eid),
                     start_time: Set(params.start_time),
                     end_time: Set(params.end_time),
@@ -257,6 +368,10 @@ impl<'a> JobLogic<'a> {
         schedule_type: ScheduleType,
         action: automate::JobAction,
         timer_expr: Option<String>,
+        supervisor_option: Option<SupervisorOption>,
+        timer_option: Option<TimerOption>,
+        env: HashMap<String, String>,
+        param_values: HashMap<String, Value>,
         created_user: String,
     ) -> Result<u64> {
         let schedule_id = IdGenerator::get_schedule_uid();
@@ -276,16 +391,32 @@ impl<'a> JobLogic<'a> {
                 job_record.executor_id.clone()
             ))?;
 
+        let declared_params =
+            params::declared_params(job_record.params.clone(), job_record.args.clone())?;
+        let fields = params::resolve_params(&declared_params, param_values)?;
+        // the code is rendered only if the job declares params
+        let render_code = |code: &str| {
+            if declared_params.is_empty() {
+                Ok(code.to_string())
+            } else {
+                params::render(code, &fields)
+            }
+        };
+
         let mut dispatch_result = Vec::new();
 
+        // the file is named by its checksum, so that an agent having it is not sent again
         let mut upload_file: Option<UploadFile> = None;
+        let mut artifact: Option<(u64, String)> = None;
 
         if job_record.upload_file != "" {
-            let data = fs::read(job_record.upload_file.clone()).await?;
+            let (size, sha256) = transfer::file_sha256(&job_record.upload_file).await?;
             upload_file = Some(UploadFile {
                 filename: file_name!(job_record.upload_file.clone()),
-                data: Some(data),
+                data: None,
+                transfer_id: Some(sha256.clone()),
             });
+            artifact = Some((size, sha256));
         }
 
         let (bundle_script, job_type): (Option<Vec<BundleScript>>, String) =
@@ -309,10 +440,11 @@ impl<'a> JobLogic<'a> {
                             cmd_name: command_slice
                                 .get(0)
                                 .map_or("".to_string(), |&v| v.to_owned()),
-                            code: v.code.clone(),
+                            code: render_code(&v.code)?,
                             args: command_slice
                                 .get(1..)
                                 .map_or(vec![], |v| v.into_iter().map(|&v| v.to_owned()).collect()),
+                            depends_on: v.depends_on.clone(),
                         })
                     }
 
@@ -323,6 +455,15 @@ impl<'a> JobLogic<'a> {
 
         let command_slice: Vec<&str> = executor_record.command.split(" ").collect();
 
+        let mut job_env: HashMap<String, String> = job_record
+            .env
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()?
+            .unwrap_or_default();
+        job_env.extend(env);
+        let secrets = self.decrypt_secrets(job_record.secrets.clone())?;
+
         let dispatch_params = automate::DispatchJobParams {
             base_job: automate::BaseJob {
                 eid: job_record.eid.clone(),
@@ -330,24 +471,58 @@ impl<'a> JobLogic<'a> {
                     .get(0)
                     .map_or("".to_string(), |&v| v.to_owned()),
                 bundle_script,
-                code: job_record.code.clone(),
+                code: render_code(&job_record.code)?,
                 args: command_slice
                     .get(1..)
                     .map_or(vec![], |v| v.into_iter().map(|&v| v.to_owned()).collect()),
                 upload_file: upload_file.clone(),
                 work_dir: Some(job_record.work_dir.clone()).filter(|v| !v.is_empty()),
                 work_user: Some(job_record.work_user.clone()).filter(|v| !v.is_empty()),
+                login_env: job_record.login_env,
                 timeout: job_record.timeout,
-                max_retry: job_record.max_retry as u8,
+                kill_grace_period: job_record.kill_grace_period as u64,
+                max_output_size: job_record.max_output_size,
+                // the jobs saved before retries were honored have no backoff, they are not retried
+                max_retry: job_record
+                    .retry_backoff
+                    .as_ref()
+                    .map_or(0, |_| job_record.max_retry as u8),
                 max_parallel: job_record.max_parallel as u8,
+                concurrency_policy: job_record
+                    .concurrency_policy
+                    .as_str()
+                    .try_into()
+                    .unwrap_or_default(),
+                retry_backoff: job_record
+                    .retry_backoff
+                    .clone()
+                    .map(serde_json::from_value)
+                    .transpose()?
+                    .unwrap_or_default(),
+                resource_limits: job_record
+                    .resource_limits
+                    .clone()
+                    .map(serde_json::from_value)
+                    .transpose()?
+                    .unwrap_or_default(),
+                bundle_mode: job_record
+                    .bundle_mode
+                    .as_str()
+                    .try_into()
+                    .unwrap_or_default(),
+                bundle_concurrency: job_record.bundle_concurrency,
                 read_code_from_stdin: false,
             },
-            fields: None,
+            fields: Some(fields).filter(|_| !declared_params.is_empty()),
             created_user: created_user.clone(),
             schedule_id: schedule_id.clone(),
             timer_expr: timer_expr.clone(),
             is_sync,
             action: action.clone(),
+            supervisor_option,
+            timer_option,
+            env: job_env,
+            secrets,
         };
 
         let mut dispatch_data = DispatchData {
@@ -358,21 +533,29 @@ impl<'a> JobLogic<'a> {
         endpoints.into_iter().for_each(|v| {
             dispatch_data.target.push(v);
         });
+        self.sync_console_timer(&dispatch_data, action).await?;
+        // the agents only drop their own timer of a console timer, an offline one is not an error
+        let console_timer = action == JobAction::StartTimer
+            && TimerOption::is_console_mode(&dispatch_data.params.timer_option);
+
+        let artifact = match artifact {
+            Some((size, sha256)) => Some(
+                self.prepare_artifact(&job_record.upload_file, size, sha256, &dispatch_data.target)
+                    .await?,
+            ),
+            None => None,
+        };
 
         let logic = automate::Logic::new(self.ctx.redis().clone());
         let http_client = self.ctx.http_client.clone();
 
         let batch_push_ret = utils::async_batch_do(dispatch_data.target.clone(), move |v| {
-            let dispatch_params = dispatch_params.clone();
+            let mut dispatch_params = dispatch_params.clone();
             let logic = logic.clone();
             let http_client = http_client.clone();
             let secret = secret.clone();
+            let artifact = artifact.clone();
             Box::pin(async move {
-                let body = automate::DispatchJobRequest {
-                    agent_ip: v.ip.clone(),
-                    namespace: v.namespace.clone(),
-                    dispatch_params: dispatch_params.clone(),
-                };
                 let pair = match logic.get_link_pair(v.namespace.clone(), v.ip.clone()).await {
                     Ok(v) => v,
                     Err(_) => {
@@ -385,6 +568,28 @@ impl<'a> JobLogic<'a> {
                         })
                     }
                 };
+                if let (Some(artifact), Some(upload_file)) =
+                    (artifact, dispatch_params.base_job.upload_file.as_mut())
+                {
+                    let ret = artifact
+                        .attach(&http_client, &pair.1.comet_addr, &v, upload_file)
+                        .await;
+                    if let Err(e) = ret {
+                        return Ok(DispatchResult {
+                            namespace: v.namespace.clone(),
+                            bind_ip: v.ip.clone(),
+                            response: json!(null),
+                            has_err: true,
+                            call_err: Some(e.to_string()),
+                        });
+                    }
+                }
+
+                let body = automate::DispatchJobRequest {
+                    agent_ip: v.ip.clone(),
+                    namespace: v.namespace.clone(),
+                    dispatch_params: dispatch_params.clone(),
+                };
                 let api_url = format!(
                     "http://{}/dispatch?secret={}",
                     pair.1.comet_addr,
@@ -428,7 +633,7 @@ impl<'a> JobLogic<'a> {
                         })
                     }
                 };
-                let has_err = if ret["code"] != 20000 { true } else { false };
+                let has_err = ret["code"] != code::SUCCESS;
 
                 Ok(DispatchResult {
                     namespace: v.namespace.clone(),
@@ -456,6 +661,10 @@ impl<'a> JobLogic<'a> {
             .upload_file
             .iter_mut()
             .for_each(|v| v.data = None);
+        dispatch_data.params.mask_secrets();
+
+        let mut job_record = job_record;
+        job_record.secrets = super::mask_secrets(job_record.secrets);
 
         let ret = JobScheduleHistory::insert(entity::job_schedule_history::ActiveModel {
             schedule_id: Set(schedule_id.clone()),
@@ -474,7 +683,7 @@ impl<'a> JobLogic<'a> {
         .exec(&self.ctx.db)
         .await?;
 
-        if has_err {
+        if has_err && !console_timer {
             anyhow::bail!("Partial job scheduling failed");
         }
 
@@ -510,9 +719,16 @@ impl<'a> JobLogic<'a> {
         let logic = automate::Logic::new(self.ctx.redis().clone());
 
         for data in runnable {
-            let dispatch_data: DispatchData = data
+            let mut dispatch_data: DispatchData = data
                 .ok_or(anyhow!("cannot found job dispatch data"))?
                 .try_into()?;
+            if let Err(e) = self.fill_secrets(&mut dispatch_data.params).await {
+                error!(
+                    "failed get secrets of job {} - {e}",
+                    dispatch_data.params.base_job.eid
+                );
+                continue;
+            }
 
             let body = automate::DispatchJobRequest {
                 agent_ip: bind_ip.clone(),
@@ -562,7 +778,7 @@ impl<'a> JobLogic<'a> {
                     continue;
                 }
             };
-            if ret["code"] != 20000 {
+            if ret["code"] != code::SUCCESS {
                 error!(
                     "failed check dispatch runnable job response on namespace:{} ip:{}, {}",
                     bind_namespace, bind_ip, ret["msg"]
@@ -586,10 +802,12 @@ impl<'a> JobLogic<'a> {
                 "cannot found job schedule by schedule_id: {schedule_id}"
             ))?;
 
-        let dispatch_data: DispatchData = job_schedule_record
+        let mut dispatch_data: DispatchData = job_schedule_record
             .dispatch_data
             .ok_or(anyhow!("cannot found job dispatch data"))?
             .try_into()?;
+        self.fill_secrets(&mut dispatch_data.params).await?;
+        self.sync_console_timer(&dispatch_data, action).await?;
 
         let logic = automate::Logic::new(self.ctx.redis().clone());
 
@@ -646,7 +864,7 @@ impl<'a> JobLogic<'a> {
                         })
                     }
                 };
-                let has_err = if ret["code"] != 20000 { true } else { false };
+                let has_err = ret["code"] != code::SUCCESS;
 
                 Ok(DispatchResult {
                     namespace: v.namespace.clone(),
@@ -728,7 +946,8 @@ impl<'a> JobLogic<'a> {
             .dispatch_data
             .ok_or(anyhow!("cannot get dispatch data"))?;
 
-        let dispatch_data = serde_json::from_value::<DispatchData>(dispatch_data)?;
+        let mut dispatch_data = serde_json::from_value::<DispatchData>(dispatch_data)?;
+        self.fill_secrets(&mut dispatch_data.params).await?;
 
         // 已经调度过的任务，无需重复下载
         // if dispatch_data.params.base_job.upload_file.is_some() {
@@ -769,7 +988,7 @@ impl<'a> JobLogic<'a> {
             .json::<serde_json::Value>()
             .await?;
 
-        if ret["code"] != 20000 {
+        if ret["code"] != code::SUCCESS {
             anyhow::bail!("failed to dispatch job");
         }
 
@@ -786,9 +1005,10 @@ impl<'a> JobLogic<'a> {
                 // },
                 run_status: match action {
                     JobAction::Exec | JobAction::Kill => Set(RunStatus::Prepare.to_string()),
-                    JobAction::StartTimer | JobAction::StopTimer => NotSet,
-                    JobAction::StartSupervisor => todo!(),
-                    JobAction::StopSupervisor => todo!(),
+                    JobAction::StartTimer
+                    | JobAction::StopTimer
+                    | JobAction::StartSupervisor
+                    | JobAction::StopSupervisor => NotSet,
                 },
                 updated_user: Set(updated_user),
                 ..Default::default()
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use automate::{scheduler::types::SECRET_MASK, DispatchJobParams};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde_json::Value;
+
+use crate::entity::{self, prelude::*};
+
+use super::JobLogic;
+
+/// replace the value of secrets, the saved secrets are never returned
+pub fn mask_secrets(secrets: Option<Value>) -> Option<Value> {
+    let mut secrets = secrets?;
+    if let Some(v) = secrets.as_object_mut() {
+        v.values_mut()
+            .for_each(|v| *v = Value::String(SECRET_MASK.to_string()));
+    }
+    Some(secrets)
+}
+
+impl<'a> JobLogic<'a> {
+    /// encrypt the secrets of the job, a masked value keeps the saved one
+    pub async fn encrypt_secrets(
+        &self,
+        id: Option<u64>,
+        secrets: Option<HashMap<String, String>>,
+    ) -> Result<Option<Value>> {
+        let Some(secrets) = secrets else {
+            return Ok(None);
+        };
+
+        let saved: HashMap<String, String> = match id {
+            Some(id) => Job::find_by_id(id)
+                .one(&self.ctx.db)
+                .await?
+                .and_then(|v| v.secrets)
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            None => HashMap::new(),
+        };
+
+        let mut encrypted = HashMap::new();
+        for (k, v) in secrets {
+            let v = if v == SECRET_MASK {
+                saved
+                    .get(&k)
+                    .cloned()
+                    .ok_or(anyhow!("cannot found saved secret {k}"))?
+            } else {
+                self.ctx.encrypt(v)?
+            };
+            encrypted.insert(k, v);
+        }
+
+        Ok(Some(serde_json::to_value(encrypted)?))
+    }
+
+    pub fn decrypt_secrets(&self, secrets: Option<Value>) -> Result<HashMap<String, String>> {
+        let secrets: HashMap<String, String> = secrets
+            .map(serde_json::from_value)
+            .transpose()?
+            .unwrap_or_default();
+
+        secrets
+            .into_iter()
+            .map(|(k, v)| Ok((k, self.ctx.decrypt(v)?)))
+            .collect()
+    }
+
+    /// the secrets are masked in the stored dispatch data, take them from the job again
+    pub async fn fill_secrets(&self, params: &mut DispatchJobParams) -> Result<()> {
+        if params.secrets.is_empty() {
+            return Ok(());
+        }
+
+        let job_record = Job::find()
+            .filter(entity::job::Column::Eid.eq(params.base_job.eid.clone()))
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found job {}", params.base_job.eid))?;
+
+        params.secrets = self.decrypt_secrets(job_record.secrets)?;
+        Ok(())
+    }
+}
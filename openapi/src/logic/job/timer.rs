@@ -1,4 +1,6 @@
 use anyhow::Result;
+use automate::scheduler::timer;
+use chrono::{Local, Utc};
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder,
     QuerySelect, QueryTrait, Set,
@@ -81,3 +83,84 @@ impl<'a> JobLogic<'a> {
         Ok(ret.rows_affected)
     }
 }
+
+const FIELD_NAMES: [&str; 7] = [
+    "second",
+    "minute",
+    "hour",
+    "day of month",
+    "month",
+    "day of week",
+    "year",
+];
+
+/// check the timer expr and the time zone before they are saved or dispatched
+pub fn check_timer_expr(timer_expr: &str, time_zone: Option<&str>) -> Result<()> {
+    timer::parse_timer_expr(timer_expr)?;
+    if let Some(v) = time_zone.filter(|v| *v != "") {
+        timer::parse_time_zone(v)?;
+    }
+    Ok(())
+}
+
+/// the next fire times of the timer expr formatted in the time zone,
+/// the local time zone of the console is used if none
+pub fn next_fire_times(
+    timer_expr: &str,
+    time_zone: Option<&str>,
+    num: usize,
+) -> Result<Vec<String>> {
+    let now = Utc::now();
+    let list = match time_zone.filter(|v| *v != "") {
+        Some(v) => timer::next_fire_times(timer_expr, &timer::parse_time_zone(v)?, now, num)?
+            .into_iter()
+            .map(|v| v.to_rfc3339())
+            .collect(),
+        None => timer::next_fire_times(timer_expr, &Local, now, num)?
+            .into_iter()
+            .map(|v| v.to_rfc3339())
+            .collect(),
+    };
+    Ok(list)
+}
+
+fn describe_field(name: &str, value: &str) -> Option<String> {
+    match value {
+        "*" | "?" => None,
+        v if v.starts_with("*/") => Some(format!("every {} {name}s", &v[2..])),
+        v if v.contains(['-', ',', '/']) => Some(format!("{name} {v}")),
+        v => Some(format!("at {name} {v}")),
+    }
+}
+
+/// a human-readable description of the timer expr, field by field
+pub fn describe_timer_expr(timer_expr: &str) -> Result<String> {
+    timer::parse_timer_expr(timer_expr)?;
+
+    let parts: Vec<String> = timer_expr
+        .split_whitespace()
+        .zip(FIELD_NAMES)
+        .filter_map(|(v, name)| describe_field(name, v))
+        .collect();
+    if parts.is_empty() {
+        return Ok("every second".to_string());
+    }
+    Ok(parts.join(", "))
+}
+
+#[test]
+fn test_describe_timer_expr() {
+    assert_eq!(
+        describe_timer_expr("0 */5 8-18 * * *").unwrap(),
+        "at second 0, every 5 minutes, hour 8-18"
+    );
+    assert_eq!(describe_timer_expr("* * * * * *").unwrap(), "every second");
+    assert!(describe_timer_expr("0 61 * * * *").is_err());
+    assert!(check_timer_expr("0 0 * * * *", Some("Nowhere/City")).is_err());
+    assert_eq!(
+        next_fire_times("0 0 0 * * *", Some("UTC"), 2)
+            .unwrap()
+            .len(),
+        2
+    );
+}
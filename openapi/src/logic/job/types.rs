@@ -34,10 +34,18 @@ pub struct ExecHistoryRelatedScheduleModel {
     pub bind_ip: String,
     pub job_type: String,
     pub output: String,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub stdout_size: u64,
+    pub stderr_size: u64,
+    pub stdout_file: String,
+    pub stderr_file: String,
     pub bundle_script_result: Option<serde_json::Value>,
     pub created_user: String,
     pub exit_code: i64,
     pub exit_status: String,
+    pub attempt: u8,
+    pub retry_exhausted: bool,
     pub start_time: Option<DateTimeUtc>,
     pub end_time: Option<DateTimeUtc>,
     pub created_time: DateTimeUtc,
@@ -59,15 +67,26 @@ pub struct JobRelatedExecutorModel {
     pub bundle_script: Option<serde_json::Value>,
     pub work_dir: String,
     pub work_user: String,
+    pub login_env: bool,
+    pub env: Option<serde_json::Value>,
+    pub secrets: Option<serde_json::Value>,
     pub upload_file: String,
     pub max_retry: u8,
+    pub retry_backoff: Option<serde_json::Value>,
     pub max_parallel: u8,
+    pub concurrency_policy: String,
+    pub bundle_mode: String,
+    pub bundle_concurrency: u32,
     pub timeout: u64,
+    pub kill_grace_period: u32,
+    pub resource_limits: Option<serde_json::Value>,
+    pub max_output_size: u64,
     pub is_public: i8,
     pub created_user: String,
     pub updated_user: String,
     pub display_on_dashboard: bool,
     pub args: Option<serde_json::Value>,
+    pub params: Option<serde_json::Value>,
     pub created_time: DateTimeUtc,
     pub updated_time: DateTimeUtc,
 }
@@ -145,6 +164,14 @@ pub struct BundleScriptResult {
     pub exit_status: Option<String>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
+    #[serde(default)]
+    pub stdout_size: Option<u64>,
+    #[serde(default)]
+    pub stderr_size: Option<u64>,
+    #[serde(default)]
+    pub stdout_file: Option<String>,
+    #[serde(default)]
+    pub stderr_file: Option<String>,
     pub eval_err: Option<String>,
     pub result: bool,
 }
@@ -157,6 +184,8 @@ pub struct BundleScriptRecord {
     pub executor_id: u64,
     pub info: String,
     pub cond_expr: String,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
@@ -204,3 +233,38 @@ pub struct InstanceStatSummary {
     pub online: u64,
     pub offline: u64,
 }
+
+/// A piece of the full output file kept on the agent
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ExecOutput {
+    pub size: u64,
+    pub offset: u64,
+    pub data: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamKind {
+    #[default]
+    String,
+    Int,
+    Bool,
+    Enum,
+}
+
+/// A typed parameter declared by a job, rendered into the code by `{{ name }}`
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct JobParam {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub kind: ParamKind,
+    #[serde(default)]
+    pub default: Option<Value>,
+    #[serde(default)]
+    pub required: bool,
+    /// allowed values of enum
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub info: String,
+}
@@ -5,8 +5,11 @@ use std::time::Duration;
 use anyhow::Result;
 
 use async_trait::async_trait;
-use automate::bridge::msg::{
-    SftpDownloadParams, SftpReadDirParams, SftpRemoveParams, SftpUploadParams,
+use automate::{
+    bridge::msg::{
+        SftpDownloadParams, SftpReadDirParams, SftpRemoveParams, SftpUploadParams, TransferAck,
+    },
+    code,
 };
 use futures::stream::{SplitSink, SplitStream};
 use futures::{SinkExt, StreamExt};
@@ -17,6 +20,7 @@ use russh_sftp::client::SftpSession;
 use serde_json::Value;
 
 use crate::api::terminal::types::{Msg, MsgType};
+use crate::logic::transfer::TransferLogic;
 use crate::state::AppContext;
 
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -251,7 +255,7 @@ impl<'a> SshLogic<'a> {
             .json::<serde_json::Value>()
             .await?;
 
-        if ret["code"] != 20000 {
+        if ret["code"] != code::SUCCESS {
             anyhow::bail!(ret["msg"].take().to_string())
         } else {
             Ok(ret["data"].take())
@@ -272,6 +276,15 @@ impl<'a> SshLogic<'a> {
         let pair = logic.get_link_pair(namespace.clone(), ip.clone()).await?;
         let api_url = format!("http://{}/sftp/tunnel/upload", pair.1.comet_addr);
 
+        // the file is pushed in chunks first if the agent supports
+        let transfer = TransferLogic::new(self.ctx);
+        let (data, transfer_id) = if transfer.is_supported(&namespace, &ip).await? {
+            let transfer_id = transfer.push_data(&namespace, &ip, data).await?;
+            (vec![], Some(transfer_id))
+        } else {
+            (data, None)
+        };
+
         let body = automate::SftpUploadRequest {
             agent_ip: ip.clone(),
             namespace: namespace.clone(),
@@ -282,6 +295,7 @@ impl<'a> SshLogic<'a> {
                 password,
                 filepath,
                 data,
+                transfer_id,
             },
         };
 
@@ -295,7 +309,7 @@ impl<'a> SshLogic<'a> {
             .json::<serde_json::Value>()
             .await?;
 
-        if ret["code"] != 20000 {
+        if ret["code"] != code::SUCCESS {
             anyhow::bail!(ret["msg"].take().to_string())
         } else {
             Ok(ret["data"].to_string())
@@ -340,7 +354,7 @@ impl<'a> SshLogic<'a> {
             .json::<serde_json::Value>()
             .await?;
 
-        if ret["code"] != 20000 {
+        if ret["code"] != code::SUCCESS {
             anyhow::bail!(ret["msg"].take().to_string())
         } else {
             Ok(ret["data"].to_string())
@@ -360,15 +374,23 @@ impl<'a> SshLogic<'a> {
         let pair = logic.get_link_pair(namespace.clone(), ip.clone()).await?;
         let api_url = format!("http://{}/sftp/tunnel/download", pair.1.comet_addr);
 
+        let transfer = TransferLogic::new(self.ctx);
+        let transfer_id = if transfer.is_supported(&namespace, &ip).await? {
+            Some(nanoid::nanoid!())
+        } else {
+            None
+        };
+
         let body = automate::SftpDownloadRequest {
             agent_ip: ip.clone(),
             namespace: namespace.clone(),
             params: SftpDownloadParams {
-                ip,
+                ip: ip.clone(),
                 port,
                 user,
                 password,
                 filepath,
+                transfer_id: transfer_id.clone(),
             },
         };
 
@@ -382,11 +404,16 @@ impl<'a> SshLogic<'a> {
             .json::<serde_json::Value>()
             .await?;
 
-        if ret["code"] != 20000 {
+        if ret["code"] != code::SUCCESS {
             anyhow::bail!(ret["msg"].take().to_string())
-        } else {
+        }
+        if transfer_id.is_none() {
             let data: Vec<u8> = serde_json::from_value(ret["data"].take())?;
-            Ok(data)
+            return Ok(data);
         }
+
+        // the agent keeps the file and replies the ack of it, the file is pulled in chunks
+        let ack: TransferAck = serde_json::from_value(ret["data"].take())?;
+        transfer.pull_data(&namespace, &ip, &ack).await
     }
 }
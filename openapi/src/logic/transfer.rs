@@ -0,0 +1,161 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use automate::{
+    bridge::{
+        msg::{
+            Capability, TransferAck, TransferChunk, TransferChunkParams, TransferParams,
+            TransferReadParams,
+        },
+        transfer::{self, ChunkChannel, ChunkSource},
+    },
+    code,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use super::instance::InstanceLogic;
+use crate::state::AppContext;
+
+/// The agent reached through its comet, the chunks are relayed one by one
+#[derive(Clone)]
+pub struct CometChannel {
+    http_client: reqwest::Client,
+    comet_addr: String,
+    namespace: String,
+    agent_ip: String,
+}
+
+impl CometChannel {
+    pub fn new(
+        http_client: reqwest::Client,
+        comet_addr: String,
+        namespace: String,
+        agent_ip: String,
+    ) -> Self {
+        Self {
+            http_client,
+            comet_addr,
+            namespace,
+            agent_ip,
+        }
+    }
+
+    async fn post<B: Serialize, R: DeserializeOwned>(&self, route: &str, body: &B) -> Result<R> {
+        let mut ret = self
+            .http_client
+            .post(format!("http://{}/transfer/{route}", self.comet_addr))
+            .json(body)
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+
+        if ret["code"] != code::SUCCESS {
+            anyhow::bail!(ret["msg"].take().to_string())
+        }
+        Ok(serde_json::from_value(ret["data"].take())?)
+    }
+
+    fn params(&self, transfer_id: &str) -> automate::TransferRequest {
+        automate::TransferRequest {
+            agent_ip: self.agent_ip.clone(),
+            namespace: self.namespace.clone(),
+            params: TransferParams {
+                transfer_id: transfer_id.to_string(),
+            },
+        }
+    }
+
+    /// the files of a finished transfer are removed from the agent
+    pub async fn remove(&self, transfer_id: &str) -> Result<()> {
+        let _: Value = self.post("remove", &self.params(transfer_id)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChunkChannel for CometChannel {
+    async fn status(&self, transfer_id: &str) -> Result<TransferAck> {
+        self.post("status", &self.params(transfer_id)).await
+    }
+
+    async fn send_chunk(&self, chunk: TransferChunkParams) -> Result<TransferAck> {
+        let body = automate::TransferChunkRequest {
+            agent_ip: self.agent_ip.clone(),
+            namespace: self.namespace.clone(),
+            params: chunk,
+        };
+        self.post("chunk", &body).await
+    }
+}
+
+#[async_trait]
+impl ChunkSource for CometChannel {
+    async fn read_chunk(&self, params: TransferReadParams) -> Result<TransferChunk> {
+        let body = automate::TransferReadRequest {
+            agent_ip: self.agent_ip.clone(),
+            namespace: self.namespace.clone(),
+            params,
+        };
+        self.post("read", &body).await
+    }
+}
+
+pub struct TransferLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> TransferLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    /// the agents before the transfer was introduced take the whole file in a message
+    pub async fn is_supported(&self, namespace: &str, ip: &str) -> Result<bool> {
+        let peer = InstanceLogic::new(self.ctx).get_peer(namespace, ip).await?;
+        Ok(peer.supports(Capability::Transfer))
+    }
+
+    pub async fn channel(&self, namespace: &str, ip: &str) -> Result<CometChannel> {
+        let logic = automate::Logic::new(self.ctx.redis().clone());
+        let pair = logic
+            .get_link_pair(namespace.to_string(), ip.to_string())
+            .await?;
+        Ok(CometChannel::new(
+            self.ctx.http_client.clone(),
+            pair.1.comet_addr,
+            namespace.to_string(),
+            ip.to_string(),
+        ))
+    }
+
+    /// push the data to the agent, the id of the transfer is returned
+    pub async fn push_data(&self, namespace: &str, ip: &str, data: Vec<u8>) -> Result<String> {
+        let channel = self.channel(namespace, ip).await?;
+        let transfer_id = nanoid::nanoid!();
+        let sha256 = transfer::sha256_hex(&data);
+        let size = data.len() as u64;
+        transfer::push(
+            &channel,
+            &transfer_id,
+            std::io::Cursor::new(data),
+            size,
+            &sha256,
+        )
+        .await?;
+        Ok(transfer_id)
+    }
+
+    /// pull the file the agent kept for the transfer, it is removed from the agent after
+    pub async fn pull_data(&self, namespace: &str, ip: &str, ack: &TransferAck) -> Result<Vec<u8>> {
+        let channel = self.channel(namespace, ip).await?;
+        let mut data = Vec::with_capacity(ack.received as usize);
+        let ret = transfer::pull(&channel, ack, &mut data).await;
+        if let Err(e) = channel.remove(&ack.transfer_id).await {
+            warn!("failed remove transfer {} - {e}", ack.transfer_id);
+        }
+        ret?;
+        Ok(data)
+    }
+}
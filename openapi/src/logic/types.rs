@@ -88,6 +88,8 @@ pub struct InstanceRecord {
     pub instance_group: Option<String>,
     pub instance_group_id: u64,
     pub ssh_port: u16,
+    pub protocol_version: u32,
+    pub capabilities: Option<serde_json::Value>,
     pub created_time: DateTimeUtc,
     pub updated_time: DateTimeUtc,
 }
@@ -9,7 +9,7 @@ macro_rules! api_response {
 macro_rules! return_ok {
     ($data:expr) => {
         return Ok(poem_openapi::payload::Json(crate::response::StdResponse {
-            code: 20000,
+            code: automate::code::SUCCESS,
             data: Some($data),
             msg: "success".to_string(),
         }))
@@ -20,7 +20,7 @@ macro_rules! return_ok {
 macro_rules! return_err {
     ($msg:expr) => {{
         let mut e = poem::Error::from_string($msg, poem::http::StatusCode::OK);
-        e.set_data(50001i32);
+        e.set_data(automate::code::BIZ_ERROR);
         return Err(e);
     }};
 }
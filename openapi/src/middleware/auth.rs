@@ -26,7 +26,7 @@ where
 
     async fn call(&self, mut req: Request) -> Result<Self::Output> {
         let login_resp = Json(serde_json::json! ({
-            "code": 50401,
+            "code": automate::code::NOT_LOGIN,
             "msg": "not login",
         }))
         .into_response();
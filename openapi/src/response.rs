@@ -1,3 +1,4 @@
+use automate::code;
 use poem::{http::StatusCode, Error};
 use poem_openapi::{
     payload::Json,
@@ -16,13 +17,13 @@ pub struct StdResponse<T: ParseFromJSON + ToJSON> {
 
 pub fn std_into_error(e: impl std::error::Error + Sync + Send + 'static) -> Error {
     let mut e = Error::new(e, StatusCode::OK);
-    e.set_data(50001i32);
+    e.set_data(code::BIZ_ERROR);
     e
 }
 
 pub fn anyhow_into_error(e: anyhow::Error) -> Error {
     let mut e = Error::from((StatusCode::OK, e));
-    e.set_data(50001i32);
+    e.set_data(code::BIZ_ERROR);
     e
 }
 
@@ -1,4 +1,5 @@
 use crate::config::Conf;
+use crate::leader::Leader;
 use crate::logic::role;
 use crate::logic::ssh::SshLogic;
 use crate::logic::types::Permission;
@@ -61,6 +62,7 @@ pub struct AppContextBuilder {
     conf: Option<Conf>,
     http_client: Option<reqwest::Client>,
     enforcer: Option<Arc<RwLock<Enforcer>>>,
+    replica_id: Option<String>,
 }
 
 impl AppContextBuilder {
@@ -88,14 +90,25 @@ impl AppContextBuilder {
         self
     }
 
+    pub fn replica_id(mut self, replica_id: String) -> Self {
+        self.replica_id = Some(replica_id);
+        self
+    }
+
     pub fn build(self) -> Result<AppContext> {
+        let redis = self
+            .redis
+            .ok_or(anyhow::anyhow!("redis client is required"))?;
+        let replica_id = self
+            .replica_id
+            .unwrap_or_else(crate::IdGenerator::get_replica_id);
         Ok(AppContext {
+            leader: Leader::new(redis.clone(), replica_id.clone()),
+            replica_id,
+            redis,
             db: self
                 .db
                 .ok_or(anyhow::anyhow!("database connection is required"))?,
-            redis: self
-                .redis
-                .ok_or(anyhow::anyhow!("redis client is required"))?,
             conf: self.conf.ok_or(anyhow::anyhow!("config is required"))?,
             http_client: self
                 .http_client
@@ -114,6 +127,9 @@ pub struct AppContext {
     pub conf: Conf,
     pub http_client: reqwest::Client,
     pub enforcer: Arc<RwLock<Enforcer>>,
+    /// unique id of the console process among the replicas
+    pub replica_id: String,
+    pub leader: Leader,
 }
 
 impl AppContext {
@@ -124,6 +140,7 @@ impl AppContext {
             redis: None,
             conf: None,
             http_client: None,
+            replica_id: None,
         }
     }
 
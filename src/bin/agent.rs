@@ -3,9 +3,12 @@ use clap::Parser;
 
 use tracing::error;
 
-use automate::scheduler::{
-    types::{AssignUserOption, SshConnectionOption},
-    Scheduler,
+use automate::{
+    bridge::protocol::DEFAULT_COMPRESS_THRESHOLD,
+    scheduler::{
+        types::{AssignUserOption, SshConnectionOption},
+        Scheduler,
+    },
 };
 
 #[derive(Parser, Debug)]
@@ -46,6 +49,9 @@ struct AgentArgs {
     /// Assign this instance to a user and specify their password
     #[arg(long)]
     assign_password: Option<String>,
+    /// Compress the frames sent to comet above the size in bytes, 0 disables compression
+    #[arg(long, default_value_t = DEFAULT_COMPRESS_THRESHOLD)]
+    compress_threshold: usize,
 }
 
 #[tokio::main]
@@ -64,6 +70,7 @@ async fn main() -> Result<()> {
         SshConnectionOption::build(args.ssh_user, args.ssh_password, args.ssh_port),
         AssignUserOption::build(args.assign_username, args.assign_password),
     );
+    scheduler.set_compress_threshold(args.compress_threshold);
 
     if let Err(e) = scheduler.connect_comet().await {
         error!("failed connect to comet - {e}");
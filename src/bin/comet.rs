@@ -1,9 +1,12 @@
 use std::net::SocketAddr;
 
 use anyhow::Result;
-use automate::comet::{
-    handler::{self, middleware::bearer_auth},
-    Comet,
+use automate::{
+    bridge::protocol::DEFAULT_COMPRESS_THRESHOLD,
+    comet::{
+        handler::{self, middleware::bearer_auth},
+        Comet,
+    },
 };
 use clap::Parser;
 use poem::{get, listener::TcpListener, post, EndpointExt, Route, Server};
@@ -25,6 +28,9 @@ struct CometArgs {
     redis_url: String,
     #[arg(long, default_value_t = String::from("rYzBYE+cXbtdMg=="))]
     secret: String,
+    /// compress the frames sent to agents above the size in bytes, 0 disables compression
+    #[arg(long, default_value_t = DEFAULT_COMPRESS_THRESHOLD)]
+    compress_threshold: usize,
 }
 
 #[tokio::main]
@@ -38,7 +44,8 @@ async fn main() -> Result<()> {
     let redis_client = redis::Client::open(args.redis_url).expect("failed connect to redis");
 
     let port = args.bind.parse::<SocketAddr>()?.port();
-    let comet = Comet::new(redis_client, port, args.secret.clone());
+    let comet = Comet::new(redis_client, port, args.secret.clone())
+        .set_compress_threshold(args.compress_threshold);
 
     let app = Route::new()
         .at(
@@ -104,6 +111,42 @@ async fn main() -> Result<()> {
             handler::sftp_download
                 .with(bearer_auth(&args.secret))
                 .data(comet.clone()),
+        )
+        .at(
+            "/output/tunnel/read",
+            handler::read_output
+                .with(bearer_auth(&args.secret))
+                .data(comet.clone()),
+        )
+        .at(
+            "/runtime/state",
+            handler::list_runtime_state
+                .with(bearer_auth(&args.secret))
+                .data(comet.clone()),
+        )
+        .at(
+            "/transfer/chunk",
+            handler::transfer_chunk
+                .with(bearer_auth(&args.secret))
+                .data(comet.clone()),
+        )
+        .at(
+            "/transfer/status",
+            handler::transfer_status
+                .with(bearer_auth(&args.secret))
+                .data(comet.clone()),
+        )
+        .at(
+            "/transfer/read",
+            handler::transfer_read
+                .with(bearer_auth(&args.secret))
+                .data(comet.clone()),
+        )
+        .at(
+            "/transfer/remove",
+            handler::transfer_remove
+                .with(bearer_auth(&args.secret))
+                .data(comet.clone()),
         );
 
     Ok(Server::new(TcpListener::bind(args.bind)).run(app).await?)